@@ -0,0 +1,122 @@
+// Compares the quad tree with the one it replaced, run with `cargo bench`
+#![allow(dead_code)]
+
+mod legacy;
+
+// The game is a single binary, so the simulation modules the tree needs are pulled in directly
+#[path = "../../src/simulation"]
+mod simulation {
+    pub mod ball;
+    pub mod capsule;
+    pub mod gravity;
+    pub mod quad_tree;
+    pub mod random;
+}
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use macroquad::math::Vec2;
+
+use simulation::{quad_tree::*, random::Random};
+
+const SIMULATION_DT: f32 = 1. / 240.;
+
+const BALL_COUNTS: [usize; 3] = [300, 3000, 30000];
+const BALL_RADIUS: f32 = 7.;
+// Area around each ball, keeps the same density for every ball count
+const SPACE_PER_BALL: f32 = 30.;
+const ITERATIONS: u32 = 20;
+
+// Mean duration of a run of the function, after a first warm up run
+fn measure(mut run: impl FnMut()) -> Duration {
+    run();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        run();
+    }
+    return start.elapsed() / ITERATIONS;
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "balls", "old build", "old query", "new build", "new move", "new query"
+    );
+
+    for count in BALL_COUNTS {
+        let mut random = Random::new(count as u64);
+        let side = (count as f32).sqrt() * SPACE_PER_BALL;
+        let area = Rect::new(0., 0., side * 2., side * 2.);
+        let positions: Vec<Vec2> = (0..count)
+            .map(|_| {
+                Vec2::new(
+                    random.gen_range(-side / 2., side / 2.),
+                    random.gen_range(-side / 2., side / 2.),
+                )
+            })
+            .collect();
+        // Roughly what balls move in a step
+        let moves: Vec<Vec2> = (0..count)
+            .map(|_| Vec2::new(random.gen_range(-1., 1.), random.gen_range(-1., 1.)))
+            .collect();
+        let queries: Vec<Rect> = positions
+            .iter()
+            .map(|position| Rect::new(position.x, position.y, BALL_RADIUS * 4., BALL_RADIUS * 4.))
+            .collect();
+
+        let mut legacy_tree = legacy::QuadTree::new(legacy::Rect::new(0., 0., side * 2., side * 2.));
+        let old_build = measure(|| {
+            legacy_tree = legacy::QuadTree::new(legacy::Rect::new(0., 0., side * 2., side * 2.));
+            for (index, position) in positions.iter().enumerate() {
+                legacy_tree.add(legacy::QuadTreeEntry::new(*position, index));
+            }
+        });
+
+        let mut result = Vec::new();
+        let old_query = measure(|| {
+            for query in &queries {
+                result.clear();
+                legacy_tree.query_entries(
+                    &legacy::Rect::new(query.x, query.y, BALL_RADIUS * 4., BALL_RADIUS * 4.),
+                    &mut result,
+                );
+                black_box(&result);
+            }
+        });
+
+        let mut tree = QuadTree::new(area);
+        let new_build = measure(|| {
+            tree.clear();
+            for (index, position) in positions.iter().enumerate() {
+                tree.add(QuadTreeEntry::new(*position, index, 1.));
+            }
+        });
+
+        // Moving back and forth, so that every run starts from the same tree
+        let mut current = positions.clone();
+        let mut direction = 1.;
+        let new_move = measure(|| {
+            for index in 0..count {
+                let next = current[index] + moves[index] * direction;
+                tree.move_entry(current[index], QuadTreeEntry::new(next, index, 1.));
+                current[index] = next;
+            }
+            direction = -direction;
+        });
+
+        let mut result = Vec::new();
+        let new_query = measure(|| {
+            for query in &queries {
+                result.clear();
+                tree.query_entries(query, &mut result);
+                black_box(&result);
+            }
+        });
+
+        println!(
+            "{:>8} {:>12?} {:>12?} {:>12?} {:>12?} {:>12?}",
+            count, old_build, old_query, new_build, new_move, new_query
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use macroquad::{
+    input::{is_key_pressed, KeyCode},
+    logging::error,
+};
+use serde::Deserialize;
+
+use crate::storage::read_save_file;
+
+use gamepad::Gamepads;
+
+const BINDINGS_FILE: &str = "controls.toml";
+
+// Keys that can be bound, named as in KeyCode
+const BINDABLE_KEYS: [KeyCode; 60] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Kp0,
+    KeyCode::Kp1,
+    KeyCode::Kp2,
+    KeyCode::Kp3,
+    KeyCode::Kp4,
+    KeyCode::Kp5,
+    KeyCode::Kp6,
+    KeyCode::Kp7,
+    KeyCode::Kp8,
+    KeyCode::Kp9,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Tab,
+    KeyCode::Enter,
+    KeyCode::LeftShift,
+    KeyCode::RightShift,
+    KeyCode::LeftControl,
+    KeyCode::RightControl,
+    KeyCode::LeftAlt,
+    KeyCode::RightAlt,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+];
+
+// Changes of the player orbit, each press is one step
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlayerControl {
+    Clockwise,
+    AntiClockwise,
+    Reverse,
+    RadiusOut,
+    RadiusIn,
+}
+
+impl PlayerControl {
+    pub const ALL: [PlayerControl; 5] = [
+        PlayerControl::Clockwise,
+        PlayerControl::AntiClockwise,
+        PlayerControl::Reverse,
+        PlayerControl::RadiusOut,
+        PlayerControl::RadiusIn,
+    ];
+
+    pub fn get_name(&self) -> &'static str {
+        return match self {
+            PlayerControl::Clockwise => "clockwise",
+            PlayerControl::AntiClockwise => "anticlockwise",
+            PlayerControl::Reverse => "reverse",
+            PlayerControl::RadiusOut => "radius_out",
+            PlayerControl::RadiusIn => "radius_in",
+        };
+    }
+
+    pub fn from_name(name: &str) -> Option<PlayerControl> {
+        return PlayerControl::ALL
+            .iter()
+            .copied()
+            .find(|control| control.get_name() == name);
+    }
+}
+
+// Buttons named after their place, bumpers are the top shoulder buttons
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    Select,
+    Start,
+}
+
+impl GamepadButton {
+    const ALL: [GamepadButton; 14] = [
+        GamepadButton::South,
+        GamepadButton::East,
+        GamepadButton::North,
+        GamepadButton::West,
+        GamepadButton::LeftBumper,
+        GamepadButton::RightBumper,
+        GamepadButton::LeftTrigger,
+        GamepadButton::RightTrigger,
+        GamepadButton::DPadUp,
+        GamepadButton::DPadDown,
+        GamepadButton::DPadLeft,
+        GamepadButton::DPadRight,
+        GamepadButton::Select,
+        GamepadButton::Start,
+    ];
+}
+
+// Key or gamepad button triggering a control. Written as the key name, "E" or "Left", or as the
+// gamepad button name after "Pad", "PadSouth" or "PadRightBumper".
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Binding {
+    Key(KeyCode),
+    Gamepad(GamepadButton),
+}
+
+impl TryFrom<String> for Binding {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Binding, String> {
+        let button = name.strip_prefix("Pad").and_then(|button_name| {
+            GamepadButton::ALL
+                .iter()
+                .find(|button| format!("{:?}", button) == button_name)
+        });
+        match button {
+            Some(button) => return Ok(Binding::Gamepad(*button)),
+            None => {}
+        }
+
+        return BINDABLE_KEYS
+            .iter()
+            .find(|key| format!("{:?}", key) == name)
+            .map(|key| Binding::Key(*key))
+            .ok_or_else(|| format!("unknown key or gamepad button `{}`", name));
+    }
+}
+
+// Bindings of each control, several can trigger the same one
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ControlBindings {
+    pub clockwise: Vec<Binding>,
+    pub anticlockwise: Vec<Binding>,
+    pub reverse: Vec<Binding>,
+    pub radius_out: Vec<Binding>,
+    pub radius_in: Vec<Binding>,
+}
+
+impl Default for ControlBindings {
+    fn default() -> ControlBindings {
+        return ControlBindings {
+            clockwise: vec![
+                Binding::Key(KeyCode::E),
+                Binding::Gamepad(GamepadButton::RightBumper),
+            ],
+            anticlockwise: vec![
+                Binding::Key(KeyCode::Q),
+                Binding::Gamepad(GamepadButton::LeftBumper),
+            ],
+            reverse: vec![
+                Binding::Key(KeyCode::X),
+                Binding::Gamepad(GamepadButton::North),
+            ],
+            radius_out: vec![
+                Binding::Key(KeyCode::W),
+                Binding::Gamepad(GamepadButton::DPadUp),
+            ],
+            radius_in: vec![
+                Binding::Key(KeyCode::S),
+                Binding::Gamepad(GamepadButton::DPadDown),
+            ],
+        };
+    }
+}
+
+impl ControlBindings {
+    // The ones from the user save folder, controls missing there keep their default bindings
+    pub fn load() -> ControlBindings {
+        return read_save_file(BINDINGS_FILE)
+            .and_then(|text| match toml::from_str(&text) {
+                Ok(bindings) => Some(bindings),
+                Err(e) => {
+                    error!("Could not read {} {}", BINDINGS_FILE, e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+    }
+
+    pub fn get(&self, control: PlayerControl) -> &[Binding] {
+        return match control {
+            PlayerControl::Clockwise => &self.clockwise,
+            PlayerControl::AntiClockwise => &self.anticlockwise,
+            PlayerControl::Reverse => &self.reverse,
+            PlayerControl::RadiusOut => &self.radius_out,
+            PlayerControl::RadiusIn => &self.radius_in,
+        };
+    }
+}
+
+// Reads the keys and gamepad buttons bound to the player controls
+pub struct Controls {
+    bindings: ControlBindings,
+    gamepads: Gamepads,
+}
+
+impl Controls {
+    pub fn new() -> Controls {
+        return Controls {
+            bindings: ControlBindings::load(),
+            gamepads: Gamepads::new(),
+        };
+    }
+
+    // Reads the gamepads, once per update before checking the controls
+    pub fn update(&mut self) {
+        self.gamepads.update();
+    }
+
+    pub fn is_pressed(&self, control: PlayerControl) -> bool {
+        return self
+            .bindings
+            .get(control)
+            .iter()
+            .any(|binding| match binding {
+                Binding::Key(key) => is_key_pressed(*key),
+                Binding::Gamepad(button) => self.gamepads.is_pressed(*button),
+            });
+    }
+}
+
+#[cfg(feature = "gamepad")]
+mod gamepad {
+    use macroquad::logging::error;
+
+    use super::GamepadButton;
+
+    fn to_gamepad_button(button: gilrs::Button) -> Option<GamepadButton> {
+        return match button {
+            gilrs::Button::South => Some(GamepadButton::South),
+            gilrs::Button::East => Some(GamepadButton::East),
+            gilrs::Button::North => Some(GamepadButton::North),
+            gilrs::Button::West => Some(GamepadButton::West),
+            gilrs::Button::LeftTrigger => Some(GamepadButton::LeftBumper),
+            gilrs::Button::RightTrigger => Some(GamepadButton::RightBumper),
+            gilrs::Button::LeftTrigger2 => Some(GamepadButton::LeftTrigger),
+            gilrs::Button::RightTrigger2 => Some(GamepadButton::RightTrigger),
+            gilrs::Button::DPadUp => Some(GamepadButton::DPadUp),
+            gilrs::Button::DPadDown => Some(GamepadButton::DPadDown),
+            gilrs::Button::DPadLeft => Some(GamepadButton::DPadLeft),
+            gilrs::Button::DPadRight => Some(GamepadButton::DPadRight),
+            gilrs::Button::Select => Some(GamepadButton::Select),
+            gilrs::Button::Start => Some(GamepadButton::Start),
+            _ => None,
+        };
+    }
+
+    // Buttons pressed on any gamepad since the last update
+    pub struct Gamepads {
+        gilrs: Option<gilrs::Gilrs>,
+        pressed: Vec<GamepadButton>,
+    }
+
+    impl Gamepads {
+        pub fn new() -> Gamepads {
+            return Gamepads {
+                gilrs: gilrs::Gilrs::new()
+                    .map_err(|e| error!("Could not read gamepads {}", e))
+                    .ok(),
+                pressed: Vec::new(),
+            };
+        }
+
+        pub fn update(&mut self) {
+            self.pressed.clear();
+            match self.gilrs {
+                Some(ref mut gilrs) => {
+                    while let Some(event) = gilrs.next_event() {
+                        match event.event {
+                            gilrs::EventType::ButtonPressed(button, _) => {
+                                match to_gamepad_button(button) {
+                                    Some(button) => self.pressed.push(button),
+                                    None => {}
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+
+        pub fn is_pressed(&self, button: GamepadButton) -> bool {
+            return self.pressed.contains(&button);
+        }
+    }
+}
+
+// Gamepads are only read with the gamepad feature, as it needs libudev on linux
+#[cfg(not(feature = "gamepad"))]
+mod gamepad {
+    use super::GamepadButton;
+
+    pub struct Gamepads;
+
+    impl Gamepads {
+        pub fn new() -> Gamepads {
+            return Gamepads;
+        }
+
+        pub fn update(&mut self) {}
+
+        pub fn is_pressed(&self, _button: GamepadButton) -> bool {
+            return false;
+        }
+    }
+}
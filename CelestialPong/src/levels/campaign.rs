@@ -0,0 +1,109 @@
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    levels::{garden_level::*, level_definition::*, levels::*, title_screen::*},
+    storage::{read_save_file, write_save_file},
+};
+
+const CAMPAIGN_PATH: &str = "levels/campaign.toml";
+const PROGRESS_FILE: &str = "campaign.toml";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StageDefinition {
+    pub name: String,
+    pub file: String,
+    // The stage can be replayed in endless mode once won, its level needs waves
+    #[serde(default)]
+    pub endless: bool,
+}
+
+// Ordered list of the garden stages
+#[derive(Clone, Debug, Deserialize)]
+pub struct Campaign {
+    pub stages: Vec<StageDefinition>,
+}
+
+impl Campaign {
+    pub fn load() -> Result<Campaign, String> {
+        let text = read_level_file(CAMPAIGN_PATH)?;
+        let campaign: Campaign =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", CAMPAIGN_PATH, e))?;
+
+        if campaign.stages.is_empty() {
+            return Err(format!("{}: the campaign has no stage", CAMPAIGN_PATH));
+        }
+
+        return Ok(campaign);
+    }
+
+    pub fn load_stage(&self, stage: usize) -> Result<LevelDefinition, String> {
+        match self.stages.get(stage) {
+            Some(definition) => return LevelDefinition::load(&definition.file),
+            None => return Err(format!("{}: no stage {}", CAMPAIGN_PATH, stage)),
+        }
+    }
+}
+
+// Creates the garden of a stage, going back to the title screen if it can't be loaded
+pub fn start_stage(level_parameters: LevelParameters, stage: usize, endless: bool) -> Level {
+    match Campaign::load().and_then(|campaign| campaign.load_stage(stage)) {
+        Ok(definition) if endless && definition.waves.is_none() => {
+            error!(
+                "Could not start stage {} in endless mode, it has no waves",
+                stage
+            );
+            return Level::TitleScreen(TitleScreen::new(level_parameters));
+        }
+        Ok(definition) => {
+            return Level::GardenLevel(GardenLevel::new(
+                level_parameters,
+                stage,
+                definition,
+                endless,
+            ))
+        }
+        Err(e) => {
+            error!("Could not start stage {}", e);
+            return Level::TitleScreen(TitleScreen::new(level_parameters));
+        }
+    }
+}
+
+// Stages the player has access to, kept in the user save folder
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CampaignProgress {
+    pub unlocked_stages: usize,
+}
+
+impl CampaignProgress {
+    pub fn load() -> CampaignProgress {
+        let progress = read_save_file(PROGRESS_FILE)
+            .and_then(|text| toml::from_str::<CampaignProgress>(&text).ok());
+
+        return CampaignProgress {
+            // The first stage is always playable
+            unlocked_stages: progress.map_or(1, |p| p.unlocked_stages.max(1)),
+        };
+    }
+
+    pub fn is_unlocked(&self, stage: usize) -> bool {
+        return stage < self.unlocked_stages;
+    }
+
+    // Unlocks the stage after the completed one
+    pub fn complete(&mut self, stage: usize) {
+        if self.is_unlocked(stage + 1) {
+            return;
+        }
+
+        self.unlocked_stages = stage + 2;
+        match toml::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|text| write_save_file(PROGRESS_FILE, &text))
+        {
+            Err(e) => error!("Could not save campaign progress {}", e),
+            _ => {}
+        }
+    }
+}
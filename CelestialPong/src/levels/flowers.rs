@@ -0,0 +1,193 @@
+use macroquad::{
+    color::Color,
+    math::{vec2, Vec2},
+    texture::{draw_texture_ex, DrawTextureParams, Texture2D},
+};
+
+use crate::{
+    levels::level_definition::GrowthDefinition,
+    simulation::{
+        ball::{Ball, BallType},
+        gravity::get_orbital_velocity,
+    },
+};
+
+// Textures of the flowers once they outgrew the sprout, which is the one of the level
+pub const GROWN_FLOWER_TEXTURES: [&str; 3] = ["small_flower.png", "flower4.png", "flower5.png"];
+pub const GROWTH_STAGES: usize = 4;
+
+// Height above the surface seeds are released at, so that they do not land right away
+const SEED_CLEARANCE: f32 = 12.;
+// Seeds leave a bit faster than a circular orbit, going up and around the body
+const SEED_SPEED_RATIO: f32 = 1.15;
+
+// Spikes damage the flowers close to where they land
+pub fn is_spike(ball_type: BallType) -> bool {
+    return ball_type == BallType::BadBall || ball_type == BallType::Splitter;
+}
+
+// Flower planted on a body, its position is relative to that body
+pub struct Flower {
+    pub body: usize,
+    pub offset: Vec2,
+    pub rotation: f32,
+    // From 0 for a sprout to GROWTH_STAGES - 1 for a fully grown flower
+    pub stage: usize,
+    pub health: u32,
+    // Seconds spent in the current stage, or since the last seed once fully grown
+    growth: f32,
+    seeds_left: u32,
+    // Ball that planted the flower, the seeds it spreads are copies of it
+    seed: Ball,
+}
+
+impl Flower {
+    pub fn is_grown(&self) -> bool {
+        return self.stage == GROWTH_STAGES - 1;
+    }
+}
+
+// Flowers of the garden, growing from the seeds landing on the bodies. Grown flowers spread seeds
+// into orbit, and spikes landing near flowers damage them.
+pub struct FlowerGarden {
+    pub flowers: Vec<Flower>,
+    // Flowers planted since the start, dead ones included
+    pub planted: usize,
+    definition: GrowthDefinition,
+}
+
+impl FlowerGarden {
+    pub fn new(definition: GrowthDefinition) -> FlowerGarden {
+        return FlowerGarden {
+            flowers: Vec::new(),
+            planted: 0,
+            definition,
+        };
+    }
+
+    pub fn clear(&mut self) {
+        self.flowers.clear();
+        self.planted = 0;
+    }
+
+    // Plants a sprout where the seed touched the body
+    pub fn plant(&mut self, body_index: usize, body: &Ball, seed: &Ball, rotation_jitter: f32) {
+        let direction = (body.position - seed.position).normalize();
+        self.planted = self.planted + 1;
+        self.flowers.push(Flower {
+            body: body_index,
+            offset: seed.position + direction * seed.radius * -1.5 - body.position,
+            rotation: -direction.angle_between(vec2(0.0, 1.0)) + rotation_jitter,
+            stage: 0,
+            health: self.definition.health,
+            growth: 0.,
+            seeds_left: self.definition.seeds_per_flower,
+            seed: Ball {
+                ball_type: BallType::GoodBall,
+                ..*seed
+            },
+        });
+    }
+
+    // Damages the flowers of the body close to where a spike landed, killing the ones out of health
+    pub fn damage(&mut self, body_index: usize, body: &Ball, spike: &Ball) {
+        let impact = spike.position - body.position;
+        let reach = self.definition.damage_radius + spike.radius;
+        for flower in &mut self.flowers {
+            if flower.body == body_index && flower.offset.distance(impact) <= reach {
+                flower.health = flower.health.saturating_sub(1);
+            }
+        }
+        self.flowers.retain(|flower| flower.health > 0);
+    }
+
+    // Grows the flowers, the grown ones releasing their seeds in orbit around their body
+    pub fn update(&mut self, dt: f32, bodies: &[Ball], balls: &mut Vec<Ball>) {
+        let definition = &self.definition;
+        for flower in &mut self.flowers {
+            flower.growth = flower.growth + dt;
+            if !flower.is_grown() {
+                if flower.growth >= definition.stage_time {
+                    flower.stage = flower.stage + 1;
+                    flower.growth = 0.;
+                }
+                continue;
+            }
+
+            if flower.seeds_left == 0 || flower.growth < definition.seed_interval {
+                continue;
+            }
+            flower.seeds_left = flower.seeds_left - 1;
+            flower.growth = 0.;
+
+            let body = &bodies[flower.body];
+            let up = flower.offset.normalize();
+            let mut seed = Ball {
+                position: body.position + up * (body.radius + flower.seed.radius + SEED_CLEARANCE),
+                ..flower.seed
+            };
+            let velocity = get_orbital_velocity(&seed, body) * SEED_SPEED_RATIO + body.velocity;
+            seed.set_velocity(velocity, dt);
+            balls.push(seed);
+        }
+    }
+
+    // Points of the flowers alive, depending on how far they grew
+    pub fn get_score(&self) -> i32 {
+        return self
+            .flowers
+            .iter()
+            .map(|flower| self.definition.stage_scores[flower.stage])
+            .sum();
+    }
+
+    pub fn get_grown_count(&self) -> usize {
+        return self
+            .flowers
+            .iter()
+            .filter(|flower| flower.is_grown())
+            .count();
+    }
+
+    // Textures are the sprout one followed by the grown ones
+    pub fn draw(&self, bodies: &[Ball], textures: &[Texture2D]) {
+        for flower in &self.flowers {
+            let position = bodies[flower.body].position + flower.offset;
+            // Damaged flowers fade to brown
+            let health = flower.health as f32 / self.definition.health as f32;
+            let color = Color::new(1., 0.6 + 0.4 * health, 0.4 + 0.6 * health, 1.);
+
+            match flower.stage {
+                0 => {
+                    draw_texture_ex(
+                        &textures[0],
+                        position.x - 16.,
+                        position.y - 16.,
+                        color,
+                        DrawTextureParams {
+                            dest_size: Some(Vec2::new(20., 40.)),
+                            rotation: flower.rotation,
+                            ..Default::default()
+                        },
+                    );
+                }
+                stage => {
+                    // Grown flowers stand on the surface, growing outward
+                    let size = 16. + 6. * stage as f32;
+                    let center = position + flower.offset.normalize() * (size / 2. - 10.);
+                    draw_texture_ex(
+                        &textures[stage],
+                        center.x - size / 2.,
+                        center.y - size / 2.,
+                        color,
+                        DrawTextureParams {
+                            dest_size: Some(Vec2::new(size, size)),
+                            rotation: flower.rotation,
+                            ..Default::default()
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
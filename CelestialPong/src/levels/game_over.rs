@@ -1,32 +1,149 @@
 use macroquad::{
-    color::colors, input::is_mouse_button_pressed, math::vec2, prelude::*, text::draw_text,
-    texture::Texture2D, window,
+    color::colors,
+    input::is_mouse_button_pressed,
+    math::vec2,
+    prelude::*,
+    text::draw_text,
+    texture::Texture2D,
+    ui::{root_ui, Skin},
+    window,
 };
 
-use crate::levels::levels::*;
+use crate::levels::{campaign::*, high_scores::*, levels::*, objectives::LevelOutcome};
 
-use super::title_screen::TitleScreen;
+use super::title_screen::{get_menu_skin, TitleScreen};
+
+const TABLE_FONT_SIZE: f32 = 20.;
+const TABLE_COLUMNS: [f32; 5] = [0., 170., 230., 310., 410.];
 
 pub struct GameOver {
     level_parameters: LevelParameters,
-    final_score: i32,
+    score: GardenScore,
+    outcome: LevelOutcome,
+    // Stage unlocked by winning, if the campaign has one after the played stage
+    next_stage: Option<usize>,
+    // Level file the high scores are kept for
+    level_key: String,
+    high_scores: HighScores,
+    // Name being typed when the score made it in the table
+    entered_name: Option<String>,
+    new_entry_rank: Option<usize>,
+    button_skin: Skin,
     texture: Texture2D,
 }
 
 impl GameOver {
-    pub fn game_over(final_score: i32, level_parameters: LevelParameters) -> Level {
+    pub fn game_over(
+        score: GardenScore,
+        outcome: LevelOutcome,
+        stage: usize,
+        endless: bool,
+        level_parameters: LevelParameters,
+    ) -> Level {
+        let campaign = Campaign::load().ok();
+        let level_key = campaign
+            .as_ref()
+            .and_then(|campaign| campaign.stages.get(stage))
+            .map_or(format!("stage {}", stage), |stage| stage.file.clone());
+        // Endless runs have their own table
+        let level_key = match endless {
+            true => format!("{} endless", level_key),
+            false => level_key,
+        };
+
+        let mut next_stage = None;
+        if outcome.is_victory() {
+            CampaignProgress::load().complete(stage);
+            next_stage = campaign
+                .filter(|campaign| stage + 1 < campaign.stages.len())
+                .map(|_| stage + 1);
+        }
+
+        let high_scores = HighScores::load();
+        let entered_name = match high_scores.qualifies(&level_key, score.total) {
+            true => Some(high_scores.last_name.clone()),
+            false => None,
+        };
+
         return Level::GameOver(GameOver {
             level_parameters,
+            score,
+            outcome,
+            next_stage,
+            level_key,
+            high_scores,
+            entered_name,
+            new_entry_rank: None,
+            button_skin: get_menu_skin(),
             texture: Texture2D::from_file_with_format(
                 include_bytes!("..\\..\\textures\\end_screen.png"),
                 None,
             ),
-            final_score,
         });
     }
 
+    fn submit_entry(&mut self) {
+        match self.entered_name.take() {
+            Some(name) => {
+                let name = match name.trim().is_empty() {
+                    true => "Anonymous".to_owned(),
+                    false => name.trim().to_owned(),
+                };
+                self.new_entry_rank = self
+                    .high_scores
+                    .insert(&self.level_key, ScoreEntry::new(name, self.score));
+                self.high_scores.save();
+            }
+            None => {}
+        }
+    }
+
+    fn update_name_entry(&mut self) {
+        match self.entered_name {
+            Some(ref mut name) => {
+                while let Some(character) = get_char_pressed() {
+                    if !character.is_control() && name.chars().count() < MAX_NAME_LENGTH {
+                        name.push(character);
+                    }
+                }
+
+                if is_key_pressed(KeyCode::Backspace) {
+                    name.pop();
+                }
+            }
+            None => {}
+        }
+
+        if is_key_pressed(KeyCode::Enter) {
+            self.submit_entry();
+        }
+    }
+
     pub fn update(&mut self) -> Level {
+        self.update_name_entry();
+
+        match self.next_stage {
+            Some(stage) => {
+                root_ui().push_skin(&self.button_skin.clone());
+                let next = root_ui().button(
+                    vec2(
+                        window::screen_width() / 2. - 50.,
+                        window::screen_height() / 4. - 60.,
+                    ),
+                    "Next stage",
+                );
+                root_ui().pop_skin();
+
+                if next {
+                    self.submit_entry();
+                    return start_stage(self.level_parameters, stage, false);
+                }
+            }
+            None => {}
+        }
+
         if is_mouse_button_pressed(window::miniquad::MouseButton::Left) {
+            self.submit_entry();
             return Level::TitleScreen(TitleScreen::new(self.level_parameters));
         }
 
@@ -48,7 +165,22 @@ impl GameOver {
             },
         );
 
-        let label = format!("{}", &self.final_score);
+        // Victory or defeat with its reason, over the end screen
+        let message = self.outcome.get_message();
+        let message_size = measure_text(&message, None, font_size as u16, 1.);
+        let message_color = match self.outcome.is_victory() {
+            true => colors::GOLD,
+            false => colors::ORANGE,
+        };
+        draw_text(
+            &message,
+            center.x - message_size.width / 2.,
+            center.y / 2. - font_size * 0.5,
+            font_size,
+            message_color,
+        );
+
+        let label = format!("{}", &self.score.total);
         draw_text(
             &label,
             center.x + center.x * 0.5,
@@ -56,5 +188,56 @@ impl GameOver {
             font_size,
             colors::WHITE,
         );
+
+        // High score table, under the end screen but moved up over it when the window is too short
+        let entries = self.high_scores.get_entries(&self.level_key);
+        let prompt_rows = match self.entered_name {
+            Some(_) => 1.5,
+            None => 0.,
+        };
+        let table_height = (entries.len() as f32 + prompt_rows) * TABLE_FONT_SIZE;
+        let texture_bottom = center.y / 2. + window::screen_width() / 2.;
+        let left = center.x - 250.;
+        let mut y = (texture_bottom + TABLE_FONT_SIZE)
+            .min(window::screen_height() - table_height)
+            .max(TABLE_FONT_SIZE);
+
+        match self.entered_name {
+            Some(ref name) => {
+                draw_text(
+                    &format!("New high score! Name: {}_ (Enter)", name),
+                    left,
+                    y,
+                    TABLE_FONT_SIZE,
+                    colors::GOLD,
+                );
+                y = y + TABLE_FONT_SIZE * 1.5;
+            }
+            None => {}
+        }
+
+        for (rank, entry) in entries.iter().enumerate() {
+            let color = match self.new_entry_rank == Some(rank) {
+                true => colors::GOLD,
+                false => colors::WHITE,
+            };
+            let columns = [
+                format!("{}. {}", rank + 1, entry.name),
+                format!("{}", entry.score.total),
+                format!("{} balls", entry.score.surviving_balls),
+                format!("{} flowers", entry.score.flowers),
+                entry.get_date(),
+            ];
+            for (column, text) in columns.iter().enumerate() {
+                draw_text(
+                    text,
+                    left + TABLE_COLUMNS[column],
+                    y,
+                    TABLE_FONT_SIZE,
+                    color,
+                );
+            }
+            y = y + TABLE_FONT_SIZE;
+        }
     }
 }
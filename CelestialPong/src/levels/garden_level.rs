@@ -4,156 +4,161 @@
 use macroquad::{
     color::{self, colors, hsl_to_rgb},
     prelude::*,
-    rand::{srand, RandomRange},
+    ui::{root_ui, Skin},
 };
 
 use crate::{
-    levels::{levels::*, title_screen::*},
-    simulation::{ball::*, gravity::*, quad_tree::*},
-    visual::radial_gradiant::get_radial_gradient_texture,
+    controls::{Controls, PlayerControl},
+    levels::{
+        flowers::*, high_scores::*, level_definition::*, levels::*, objectives::*, paddle::*,
+        player::*, replay::*, title_screen::*, waves::*, weapon::*,
+    },
+    simulation::{ball::*, gravity::*, interactions::*, random::*, world::*},
+    visual::{
+        radial_gradiant::get_radial_gradient_texture,
+        textures::load_texture_by_name,
+        ui_textures::{get_anti_clockwise_skin, get_clockwise_skin},
+    },
 };
 
 use crate::{simulation::quad_tree, SIMULATION_DT};
 
 use super::game_over::GameOver;
 
-const NB_BALLS: usize = 300;
-const BALL_RADII: f32 = 7.;
-const BALL_MASS: f32 = 40.;
+// Bad balls this close to the mouse are aimed at instead of the mouse
+const AIM_ASSIST_RADIUS: f32 = 40.;
 
-const NB_BAD_BALLS: usize = 20;
-const NB_SEED: usize = 15;
-
-const BODY_MASS: f32 = 10000000.;
 // const BODY_BOUNCYNESS: f32 = 0.9;
 
-const ORBIT_TRAP: f32 = 10.0;
-const ORBIT_TRAP_SIZE: f32 = 9.;
-
-const MIN_START_ORBIT: f32 = 210.;
-const MAX_START_ORBIT: f32 = 351.;
-
 const TRACE_SIZE: usize = 5000;
 
-struct Player {
-    position: Vec2,
-    orientation: f32,
-    orbiting_center: Vec2,
-    orbiting_radius: f32,
-    azimut: f32,
-    azimut_speed: f32,
+// On-screen orbit buttons, in the bottom right corner
+const ORBIT_BUTTON_SIZE: f32 = 48.;
+const ORBIT_BUTTON_MARGIN: f32 = 16.;
+
+// Seconds a blast ring stays on screen
+const BLAST_RING_DURATION: f64 = 0.5;
+
+// Top left corners of the anti clockwise and clockwise buttons
+fn get_orbit_button_positions() -> [Vec2; 2] {
+    let x = screen_width() - (ORBIT_BUTTON_SIZE + ORBIT_BUTTON_MARGIN) * 2.;
+    let y = screen_height() - ORBIT_BUTTON_SIZE - ORBIT_BUTTON_MARGIN;
+    return [
+        vec2(x, y),
+        vec2(x + ORBIT_BUTTON_SIZE + ORBIT_BUTTON_MARGIN, y),
+    ];
 }
 
-impl Player {
-    pub fn update(&mut self, dt: f32) {
-        self.azimut = self.azimut + self.azimut_speed * dt;
-        self.position = self.orbiting_center
-            + Vec2::from_angle(self.azimut).rotate(Vec2::X) * self.orbiting_radius;
-    }
-
-    pub fn draw(&self) {
-        draw_poly(
-            self.position.x,
-            self.position.y,
-            3,
-            10.,
-            self.orientation,
-            colors::GOLD,
+fn reset_balls(
+    world: &mut World,
+    populations: &Vec<PopulationDefinition>,
+    screen_area: &quad_tree::Rect,
+    random: &mut Random,
+) {
+    world.clear_balls();
+
+    for population in populations {
+        spawn_population(
+            population,
+            population.count,
+            &world.static_bodies,
+            screen_area,
+            random,
+            &mut world.balls,
         );
     }
 }
 
-struct SeededFlower {
-    position: Vec2,
-    rotation: f32,
-}
-
-fn random_orbital_pos(center: Vec2, min_radius: f32, max_radius: f32) -> Vec2 {
-    let angle = RandomRange::gen_range(0., std::f32::consts::PI * 2.);
-    let result = Vec2::from((angle.cos(), angle.sin()));
-    let rad = RandomRange::gen_range(min_radius, max_radius);
-    let result = center + result * rad;
-    return result;
-}
-
-fn reset_balls(balls: &mut Vec<Ball>, static_bodies: &Vec<Ball>) {
-    balls.clear();
-
-    for index in 0..NB_BALLS {
-        let position =
-            random_orbital_pos(static_bodies[0].position, MIN_START_ORBIT, MAX_START_ORBIT);
-
-        let ball_type = match index < NB_BAD_BALLS {
-            true => BallType::BadBall,
-            false => match index < NB_BAD_BALLS + NB_SEED {
-                true => BallType::GoodBall,
-                false => BallType::Ball,
-            },
-        };
-
-        let color = match ball_type {
-            BallType::BadBall | BallType::GoodBall => WHITE,
-            _ => hsl_to_rgb(
-                RandomRange::gen_range(0., 1.),
-                RandomRange::gen_range(0.45, 0.95),
-                RandomRange::gen_range(0.65, 0.99),
-            ),
-        };
-
-        let radius = match ball_type {
-            BallType::BadBall => BALL_RADII * 1.3,
-            BallType::GoodBall => BALL_RADII * 0.8,
-            _ => BALL_RADII,
-        };
-
-        let mut ball = Ball::new(
-            position,
-            Vec2::ZERO,
-            radius,
-            BALL_MASS,
-            color,
-            RandomRange::gen_range(-std::f32::consts::PI, std::f32::consts::PI),
-            RandomRange::gen_range(-std::f32::consts::PI * 2., std::f32::consts::PI * 2.),
-            ball_type,
-        );
-
-        let ball_speed = get_orbital_velocity(&ball, &static_bodies[0]);
-
-        ball.set_velocity(ball_speed, SIMULATION_DT);
-        balls.push(ball);
-    }
+// Index of a ball once the ball at removed_index is gone, None when it was that ball
+fn follow_removal(index: Option<usize>, removed_index: usize) -> Option<usize> {
+    return match index {
+        Some(index) if index == removed_index => None,
+        Some(index) if index > removed_index => Some(index - 1),
+        _ => index,
+    };
 }
 
 pub struct GardenLevel {
     paused: bool,
-    balls: Vec<Ball>,
-    static_bodies: Vec<Ball>,
-    tree_area: quad_tree::Rect,
-    quad_tree: QuadTree,
+    world: World,
+    random: Random,
+
+    // Update count since the level started, used to timestamp the player actions
+    frame: u64,
+    actions: Vec<PlayerAction>,
+    // Orbit controls read this frame, given to the next update only
+    orbit_actions: Vec<PlayerAction>,
+    recording: Recording,
+    replay: Option<Recording>,
+    replay_cursor: usize,
+    replay_matched: Option<bool>,
 
     player: Player,
+    controls: Controls,
+    clockwise_skin: Skin,
+    anti_clockwise_skin: Skin,
+    // Played instead of the launcher when the level has one
+    paddle: Option<Paddle>,
+    // Mouse azimut last given to the paddle
+    paddle_mouse_azimut: Option<f32>,
+    // Garden balls that got past the paddle this step
+    lost_balls: Vec<usize>,
+    weapon: Weapon,
+    // Blasts of the gravity bombs, with the time they went off at
+    blast_rings: Vec<(Vec2, f32, f64)>,
+    // Bad ball the launches go toward, picked near the mouse
+    aim_target: Option<usize>,
+    near_balls: Vec<quad_tree::QuadTreeEntry>,
 
     main_camera: Camera2D,
-    collided_balls: Vec<usize>,
-    balls_marked_for_delete: Vec<usize>,
     traces: [Vec2; TRACE_SIZE],
     trace_index: usize,
 
-    seeded_flowers: Vec<SeededFlower>,
-
-    kill_distance_squared: f32,
+    flowers: FlowerGarden,
+    // Balls sent during the level, when it has waves
+    waves: Option<WaveSpawner>,
+    // Waves keep coming until the garden dies, instead of playing for the level objectives
+    endless: bool,
+    // Points given by the interaction rules
+    interaction_score: i32,
+    // World time the level started at, and the flowers it started with, for the objectives
+    start_time: f64,
+    initial_flowers: usize,
+
+    // Campaign stage being played
+    stage: usize,
+    definition: LevelDefinition,
     level_parameters: LevelParameters,
+    // Visible part of the garden, bad balls leaving it are lost
+    screen_area: quad_tree::Rect,
     background: Texture2D,
 
-    body_texture: Texture2D,
-    ball_texture: Texture2D,
-    bad_ball_texture: Texture2D,
-    seed_texture: Texture2D,
-    seeded_flower_texture: Texture2D,
+    body_textures: Vec<Texture2D>,
+    ball_textures: Vec<(BallType, Texture2D)>,
+    // Sprout texture of the level followed by the grown flower ones
+    flower_textures: Vec<Texture2D>,
+}
+
+fn load_texture_or_empty(name: &str) -> Texture2D {
+    return load_texture_by_name(name).unwrap_or_else(|e| {
+        error!("Could not load texture {}", e);
+        Texture2D::empty()
+    });
 }
 
 impl GardenLevel {
-    pub fn new(level_parameters: LevelParameters) -> GardenLevel {
+    pub fn new(
+        level_parameters: LevelParameters,
+        stage: usize,
+        mut definition: LevelDefinition,
+        endless: bool,
+    ) -> GardenLevel {
+        // Endless gardens are only lost, once every flower is gone
+        if endless {
+            definition.rules.objectives.clear();
+            definition.rules.lose_conditions = vec![LoseCondition::NoFlowersLeft];
+        }
+
         let tree_area = quad_tree::Rect::new(
             0.,
             0.,
@@ -164,15 +169,80 @@ impl GardenLevel {
         let background = get_radial_gradient_texture(
             level_parameters.window_size[0] as u32,
             level_parameters.window_size[1] as u32,
-            colors::BLUE,
+            to_color(definition.background_color),
         );
 
+        let mut world = World::new(tree_area, definition.orbit_trap, definition.orbit_trap_size);
+        world.set_broadphase(definition.broadphase);
+        world.balls.reserve(
+            definition
+                .populations
+                .iter()
+                .map(|population| population.count)
+                .sum(),
+        );
+        for body in &definition.bodies {
+            world.add_body(
+                Ball::new(
+                    to_vec2(body.position),
+                    Vec2::ZERO,
+                    body.radius,
+                    body.mass,
+                    color::WHITE,
+                    0.0,
+                    0.0,
+                    BallType::Body,
+                ),
+                body.get_path(),
+                SIMULATION_DT,
+            );
+        }
+        world.interactions = InteractionTable::new(definition.interactions.clone());
+        world.capsules = definition
+            .capsules
+            .iter()
+            .map(|capsule| capsule.get_capsule())
+            .collect();
+        let orbiting_center = get_barycenter_body(&world.static_bodies).position;
+        let paddle = definition.paddle.clone().map(|paddle| {
+            Paddle::new(
+                paddle,
+                orbiting_center,
+                definition.player.orbiting_radius,
+                world.capsules.len(),
+            )
+        });
+        let mut player = Player::new(&definition.player, orbiting_center);
+        match paddle {
+            Some(ref paddle) => {
+                world.capsules.push(paddle.get_capsule());
+                player.position = paddle.get_launch_position();
+            }
+            None => {}
+        }
+
+        world.kill_distance_squared =
+            f32::powf(level_parameters.window_size[0] * f32::sqrt(2.) / 2., 2.);
+        let screen_area = quad_tree::Rect::new(
+            0.,
+            0.,
+            level_parameters.window_size[0],
+            level_parameters.window_size[1],
+        );
+        world.bad_ball_area = Some(screen_area);
+
         return GardenLevel {
             paused: false,
-            balls: Vec::with_capacity(NB_BALLS),
-            static_bodies: Vec::new(),
-            tree_area,
-            quad_tree: QuadTree::new(tree_area),
+            world,
+            random: Random::new(level_parameters.seed),
+
+            frame: 0,
+            actions: Vec::new(),
+            orbit_actions: Vec::new(),
+            recording: Recording::new(level_parameters.seed, stage, endless),
+            replay: None,
+            replay_cursor: 0,
+            replay_matched: None,
 
             main_camera: Camera2D {
                 zoom: Vec2::from((
@@ -182,257 +252,516 @@ impl GardenLevel {
                 ..Default::default()
             },
 
-            player: Player {
-                position: Vec2::new(300., 300.),
-                orientation: 0.,
-                orbiting_center: vec2(0., 0.),
-                orbiting_radius: 400.,
-                azimut: 0.,
-                azimut_speed: -0.15,
-            },
+            player,
+            controls: Controls::new(),
+            clockwise_skin: get_clockwise_skin(ORBIT_BUTTON_SIZE, ORBIT_BUTTON_SIZE),
+            anti_clockwise_skin: get_anti_clockwise_skin(ORBIT_BUTTON_SIZE, ORBIT_BUTTON_SIZE),
+            paddle,
+            paddle_mouse_azimut: None,
+            lost_balls: Vec::new(),
+            weapon: Weapon::new(definition.weapon.clone()),
+            blast_rings: Vec::new(),
+            aim_target: None,
+            near_balls: Vec::new(),
 
-            collided_balls: Vec::with_capacity(NB_BALLS),
-            balls_marked_for_delete: Vec::with_capacity(NB_BALLS),
             traces: [Vec2::ZERO; TRACE_SIZE],
             trace_index: 0,
             level_parameters,
-            kill_distance_squared: f32::powf(
-                level_parameters.window_size[0] * f32::sqrt(2.) / 2.,
-                2.,
-            ),
-            seeded_flowers: Vec::new(),
+            screen_area,
+            flowers: FlowerGarden::new(definition.growth.clone()),
+            waves: definition
+                .waves
+                .clone()
+                .map(|waves| WaveSpawner::new(waves, endless)),
+            endless,
+            interaction_score: 0,
+            start_time: 0.,
+            initial_flowers: 0,
             background,
-            body_texture: Texture2D::from_file_with_format(
-                include_bytes!("..\\..\\textures\\planet2.png"),
-                None,
-            ),
+            body_textures: definition
+                .bodies
+                .iter()
+                .map(|body| load_texture_or_empty(&body.texture))
+                .collect(),
+            ball_textures: definition
+                .get_all_populations()
+                .filter_map(|population| {
+                    population
+                        .texture
+                        .as_ref()
+                        .map(|texture| (population.ball_type, load_texture_or_empty(texture)))
+                })
+                .collect(),
+            flower_textures: std::iter::once(definition.flower_texture.as_str())
+                .chain(GROWN_FLOWER_TEXTURES)
+                .map(load_texture_or_empty)
+                .collect(),
+            stage,
+            definition,
+        };
+    }
 
-            ball_texture: Texture2D::from_file_with_format(
-                include_bytes!("..\\..\\textures\\flower_white.png"),
-                None,
-            ),
+    // Plays back a recorded session, giving control back to the player once it is over
+    pub fn from_replay(
+        level_parameters: LevelParameters,
+        definition: LevelDefinition,
+        replay: Recording,
+    ) -> GardenLevel {
+        let mut level = GardenLevel::new(
+            LevelParameters {
+                seed: replay.seed,
+                ..level_parameters
+            },
+            replay.stage,
+            definition,
+            replay.endless,
+        );
+        level.replay = Some(replay);
+        return level;
+    }
 
-            bad_ball_texture: Texture2D::from_file_with_format(
-                include_bytes!("..\\..\\textures\\spike_v2.png"),
-                None,
-            ),
+    pub fn init(&mut self) {
+        reset_balls(
+            &mut self.world,
+            &self.definition.populations,
+            &self.screen_area,
+            &mut self.random,
+        );
+        self.start_time = self.world.time;
+        self.initial_flowers = self.count_flowers();
+    }
 
-            seed_texture: Texture2D::from_file_with_format(
-                include_bytes!("..\\..\\textures\\seed_v2.png"),
-                None,
-            ),
+    fn count_flowers(&self) -> usize {
+        return self
+            .world
+            .balls
+            .iter()
+            .filter(|ball| ball.ball_type.is_flower())
+            .count();
+    }
 
-            seeded_flower_texture: Texture2D::from_file_with_format(
-                include_bytes!("..\\..\\textures\\flower_sproute.png"),
-                None,
-            ),
+    fn get_progress(&self) -> GardenProgress<'_> {
+        return GardenProgress {
+            elapsed: (self.world.time - self.start_time) as f32,
+            balls: &self.world.balls,
+            initial_flowers: self.initial_flowers,
+            planted_flowers: self.flowers.planted,
+            out_of_lives: self
+                .paddle
+                .as_ref()
+                .map_or(false, |paddle| paddle.lives == 0),
+            waves_left: self.waves.as_ref().map_or(false, |waves| !waves.is_over()),
         };
     }
 
-    pub fn init(&mut self) {
-        self.static_bodies.push(Ball::new(
-            Vec2::new(0., 0.),
-            Vec2::ZERO,
-            90.,
-            BODY_MASS,
-            color::WHITE,
-            0.0,
-            0.0,
-            BallType::Body,
-        ));
-
-        reset_balls(&mut self.balls, &self.static_bodies);
+    fn save_recording(&mut self) {
+        self.recording.end_frame = self.frame;
+        self.recording.end_hash = self.world.state_hash();
+        match self.recording.save(LAST_REPLAY_PATH) {
+            Err(e) => error!("Could not save replay {}", e),
+            _ => {}
+        }
     }
 
-    pub fn update(&mut self) -> Level {
-        if is_key_pressed(KeyCode::Space) {
-            self.paused = !self.paused;
+    // Closest bad ball to the mouse, if one is close enough
+    fn find_aim_target(&mut self, mouse_pos: Vec2) -> Option<usize> {
+        self.world
+            .broadphase
+            .query_circle(mouse_pos, AIM_ASSIST_RADIUS, &mut self.near_balls);
+        // The broadphase is from the last step, balls removed since are skipped
+        return self
+            .near_balls
+            .iter()
+            .map(|near| near.payload)
+            .find(|index| {
+                self.world
+                    .balls
+                    .get(*index)
+                    .map_or(false, |ball| ball.ball_type == BallType::BadBall)
+            });
+    }
+
+    fn read_paddle_actions(&mut self, mouse_pos: Vec2) {
+        let paddle = match self.paddle {
+            Some(ref paddle) => paddle,
+            None => return,
+        };
+
+        let is_down = |keys: [KeyCode; 2]| keys.iter().any(|key| is_key_down(*key));
+        let mut steering = 0.;
+        if is_down([KeyCode::Left, KeyCode::A]) {
+            steering = steering - 1.;
+        }
+        if is_down([KeyCode::Right, KeyCode::D]) {
+            steering = steering + 1.;
+        }
+        if steering != paddle.steering {
+            self.actions.push(PlayerAction::SteerPaddle { steering });
         }
 
-        if is_key_down(KeyCode::R) {
-            srand(1);
-            reset_balls(&mut self.balls, &self.static_bodies);
-            self.seeded_flowers.clear();
+        let azimut = paddle.get_azimut_toward(mouse_pos);
+        if self.paddle_mouse_azimut != Some(azimut) {
+            self.paddle_mouse_azimut = Some(azimut);
+            self.actions.push(PlayerAction::MovePaddle { azimut });
         }
+    }
 
-        if is_key_pressed(KeyCode::Backspace) {
-            let title = TitleScreen::new(self.level_parameters);
-            return Level::TitleScreen(title);
+    // Orbit changes from the bound keys and buttons and from the on-screen buttons, the paddle is
+    // moved with its own keys. They are read once per frame rather than per update, so that the
+    // updates of a sped up frame do not repeat a press or a click.
+    pub fn read_frame_inputs(&mut self) {
+        self.orbit_actions.clear();
+        if self.paddle.is_some() || self.replay.is_some() {
+            return;
         }
 
-        let dt = SIMULATION_DT;
+        self.controls.update();
+        for control in PlayerControl::ALL {
+            if self.controls.is_pressed(control) {
+                self.orbit_actions.push(PlayerAction::Control { control });
+            }
+        }
 
-        if !self.paused {
-            self.quad_tree = QuadTree::new(self.tree_area);
-            // Updating ball position
-            self.collided_balls.clear();
-            let nb_balls = self.balls.len();
-            for index in 0..nb_balls {
-                let ball = self.balls.get(index).unwrap();
-                self.quad_tree.add(QuadTreeEntry::new(ball.position, index));
-                let mut local_force = Vec2::ZERO;
-
-                // Comuting gravity
-                for body in &self.static_bodies {
-                    local_force = local_force + get_gravity_force(ball, body)
-                }
+        let [anti_clockwise_position, clockwise_position] = get_orbit_button_positions();
+        root_ui().push_skin(&self.anti_clockwise_skin.clone());
+        if root_ui().button(anti_clockwise_position, "") {
+            self.orbit_actions.push(PlayerAction::Control {
+                control: PlayerControl::AntiClockwise,
+            });
+        }
+        root_ui().pop_skin();
 
-                // Trapping ball in the nearest body
-                match self.static_bodies.iter().min_by(|body, other| {
-                    (body.position - ball.position)
-                        .length_squared()
-                        .total_cmp(&(other.position - ball.position).length_squared())
-                }) {
-                    Some(closest_body) => {
-                        let ideal_velocity = get_orbital_velocity(ball, closest_body);
-                        let delta = if ideal_velocity.dot(ball.velocity) > 0. {
-                            ideal_velocity - ball.velocity
-                        } else {
-                            ideal_velocity * -1. - ball.velocity
-                        };
-                        // If the ball velocity differ from the ideal orbit, nudge the ball toward that velocity
-                        if delta.length_squared() > ORBIT_TRAP_SIZE {
-                            local_force = local_force + (delta / delta.length()) * ORBIT_TRAP;
-                        }
-                    }
-                    _ => {}
-                }
+        root_ui().push_skin(&self.clockwise_skin.clone());
+        if root_ui().button(clockwise_position, "") {
+            self.orbit_actions.push(PlayerAction::Control {
+                control: PlayerControl::Clockwise,
+            });
+        }
+        root_ui().pop_skin();
+    }
 
-                let ball = self.balls.get_mut(index).unwrap();
-                // ball.update(dt, local_force);
-                ball.update_verlet(dt, local_force);
+    // Clicks on the on-screen buttons do not launch
+    fn is_mouse_over_orbit_buttons(&self) -> bool {
+        let (x, y) = mouse_position();
+        return self.paddle.is_none()
+            && get_orbit_button_positions().iter().any(|position| {
+                Rect::new(position.x, position.y, ORBIT_BUTTON_SIZE, ORBIT_BUTTON_SIZE)
+                    .contains(vec2(x, y))
+            });
+    }
 
-                // Recode previous positions
-                self.traces[self.trace_index] = ball.position;
-                self.trace_index = (self.trace_index + 1) % self.traces.len();
+    // Player actions for this frame, from the replay being played or from the inputs
+    fn read_actions(&mut self, aim_pos: Vec2, mouse_pos: Vec2) {
+        self.actions.clear();
 
-                // Delete balls that have gone too far
-                if ball.position.length_squared() > self.kill_distance_squared {
-                    self.balls_marked_for_delete.push(index);
-                } else if ball.ball_type == BallType::BadBall {
-                    if ball.position.x.abs() > self.level_parameters.window_size[0] / 2.
-                        || ball.position.y.abs() > self.level_parameters.window_size[1] / 2.
-                    {
-                        self.balls_marked_for_delete.push(index);
+        match self.replay {
+            Some(ref replay) => {
+                while let Some(recorded) = replay.actions.get(self.replay_cursor) {
+                    if recorded.frame != self.frame {
+                        break;
                     }
+                    self.actions.push(recorded.action);
+                    self.replay_cursor = self.replay_cursor + 1;
                 }
             }
+            None => {
+                if is_key_pressed(KeyCode::Space) {
+                    self.actions.push(PlayerAction::TogglePause);
+                }
 
-            // Colliding balls
-            for index in 0..self.balls.len() {
-                // Has ball already collided this frame
-                if self.collided_balls.iter().any(|c| c == &index) {
-                    continue;
+                if is_key_down(KeyCode::R) {
+                    self.actions.push(PlayerAction::Reset);
                 }
 
-                let zone_check = self.balls[index].get_collision_area();
-                let mut near_balls = Vec::new();
-                self.quad_tree.query_entries(&zone_check, &mut near_balls);
-                for entry in near_balls {
-                    if entry.payload == index
-                        || self.collided_balls.iter().any(|c| c == &entry.payload)
-                    {
-                        continue;
-                    }
+                // Holding the button charges the shot, it is launched once released
+                if is_mouse_button_pressed(MouseButton::Left) && !self.is_mouse_over_orbit_buttons()
+                {
+                    self.weapon.start_charge(self.world.time);
+                }
+                if is_mouse_button_released(MouseButton::Left) && self.weapon.is_charging() {
+                    self.actions.push(PlayerAction::Launch {
+                        aim: aim_pos - self.player.position,
+                        charge: self.weapon.release_charge(self.world.time),
+                        shot: self.weapon.shot,
+                    });
+                }
 
-                    let other_ball_index = entry.payload;
+                self.read_shot_selection();
 
-                    if self.balls[index].check_collision(&self.balls[other_ball_index]) {
-                        if index > other_ball_index {
-                            let (left, right) = self.balls.split_at_mut(index);
-                            right[0].collide(&mut left[other_ball_index], dt);
-                        } else {
-                            let (left, right) = self.balls.split_at_mut(other_ball_index);
-                            right[0].collide(&mut left[index], dt);
-                        }
+                self.actions.append(&mut self.orbit_actions);
+                self.read_paddle_actions(mouse_pos);
+            }
+        }
+    }
 
-                        self.collided_balls.push(index);
-                        self.collided_balls.push(other_ball_index);
-                    }
+    // Number keys pick a shot, the mouse wheel goes through them
+    fn read_shot_selection(&mut self) {
+        let shots = self.weapon.get_shots();
+        let current = shots
+            .iter()
+            .position(|shot| shot == &self.weapon.shot)
+            .unwrap_or(0);
+        let keys = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4];
+        let wheel = mouse_wheel().1;
+
+        let selected = match keys.iter().position(|key| is_key_pressed(*key)) {
+            Some(index) if index < shots.len() => index,
+            _ if wheel > 0. => (current + 1) % shots.len(),
+            _ if wheel < 0. => (current + shots.len() - 1) % shots.len(),
+            _ => current,
+        };
+        self.weapon.shot = shots[selected];
+    }
+
+    fn apply_action(&mut self, action: PlayerAction) {
+        match action {
+            PlayerAction::TogglePause => {
+                self.paused = !self.paused;
+            }
+            PlayerAction::Reset => {
+                self.random = Random::new(self.random.seed());
+                reset_balls(
+                    &mut self.world,
+                    &self.definition.populations,
+                    &self.screen_area,
+                    &mut self.random,
+                );
+                self.flowers.clear();
+                self.interaction_score = 0;
+                self.start_time = self.world.time;
+                self.initial_flowers = self.count_flowers();
+                match self.waves {
+                    Some(ref mut waves) => waves.reset(),
+                    None => {}
+                }
+                self.weapon.reset();
+                self.blast_rings.clear();
+                match self.paddle {
+                    Some(ref mut paddle) => paddle.reset(),
+                    None => {}
                 }
             }
-
-            // Bounce of static bodies
-            for body in self.static_bodies.iter_mut() {
-                let query = body.get_collision_area();
-                let mut near_objects = Vec::new();
-                self.quad_tree.query_entries(&query, &mut near_objects);
-                for near in near_objects {
-                    let ball = self.balls.get_mut(near.payload).unwrap();
-                    if body.check_collision(&ball) {
-                        // DELETE
-                        if !self.balls_marked_for_delete.contains(&near.payload) {
-                            self.balls_marked_for_delete.push(near.payload);
-                            if ball.ball_type == BallType::GoodBall {
-                                let direction = (body.position - ball.position).normalize();
-                                self.seeded_flowers.push(SeededFlower {
-                                    position: ball.position + direction * ball.radius * -1.5,
-                                    rotation: -direction.angle_between(vec2(0.0, 1.0))
-                                        + RandomRange::gen_range(-0.22, 0.22),
-                                });
-                            }
-                        }
+            PlayerAction::Control { control } => {
+                self.player.apply_control(control);
+            }
+            PlayerAction::SteerPaddle { steering } => match self.paddle {
+                Some(ref mut paddle) => {
+                    paddle.steering = steering;
+                    // The mouse takes over again once it moves
+                    if steering != 0. {
+                        paddle.target = None;
                     }
                 }
+                None => {}
+            },
+            PlayerAction::MovePaddle { azimut } => match self.paddle {
+                Some(ref mut paddle) => paddle.target = Some(azimut),
+                None => {}
+            },
+            PlayerAction::Launch { aim, charge, shot } => {
+                self.player.orientation =
+                    -aim.normalize().angle_between(Vec2::X) / std::f32::consts::PI * 180.;
+                self.weapon.shot = shot;
+
+                // Launches during the cooldown or without enough ammo are dropped
+                match self.weapon.fire(
+                    shot,
+                    self.player.position,
+                    aim.normalize(),
+                    charge,
+                    self.world.time,
+                ) {
+                    Some(ball) => self.world.balls.push(ball),
+                    None => {}
+                }
             }
+        }
+    }
 
-            self.balls_marked_for_delete.sort_unstable();
-            for index in self.balls_marked_for_delete.iter().rev() {
-                self.balls.remove(*index);
-            }
-
-            self.balls_marked_for_delete.clear();
-
-            self.player.update(dt);
+    pub fn update(&mut self) -> Level {
+        if is_key_pressed(KeyCode::Backspace) {
+            self.save_recording();
+            let title = TitleScreen::new(self.level_parameters);
+            return Level::TitleScreen(title);
         }
 
         let (spx, spy) = mouse_position();
         let mouse_pos = Vec2::new(spx, spy);
         let mouse_pos = self.main_camera.screen_to_world(mouse_pos);
 
-        let player_to_mouse = mouse_pos - self.player.position;
-        let player_orientation =
-            -player_to_mouse.normalize().angle_between(Vec2::X) / std::f32::consts::PI * 180.;
-        self.player.orientation = player_orientation;
+        let mut aim_pos = mouse_pos;
+        self.aim_target = None;
+        if self.replay.is_none() {
+            self.aim_target = self.find_aim_target(mouse_pos);
+            match self.aim_target {
+                Some(index) => aim_pos = self.world.balls[index].position,
+                None => {}
+            }
 
-        let dist_check = BALL_RADII * BALL_RADII * 10.;
-        let mut near_balls = Vec::new();
-        self.quad_tree.query_entries(
-            &quad_tree::Rect::new(mouse_pos.x, mouse_pos.y, dist_check, dist_check),
-            &mut near_balls,
-        );
+            let player_to_mouse = aim_pos - self.player.position;
+            let player_orientation =
+                -player_to_mouse.normalize().angle_between(Vec2::X) / std::f32::consts::PI * 180.;
+            self.player.orientation = player_orientation;
+        }
 
-        if is_mouse_button_pressed(MouseButton::Left) {
-            let ball_vel = (mouse_pos - self.player.position).normalize() * 7.;
-            let ball = Ball::new(
-                self.player.position,
-                ball_vel,
-                BALL_RADII * 1.2,
-                BALL_MASS * 2.,
-                colors::BLUE,
-                0.0,
-                0.0,
-                BallType::Projectil,
-            );
+        self.read_actions(aim_pos, mouse_pos);
+        for index in 0..self.actions.len() {
+            let action = self.actions[index];
+            self.recording.push(self.frame, action);
+            self.apply_action(action);
+        }
+
+        let dt = SIMULATION_DT;
+
+        if !self.paused {
+            let step_time = self.world.time + dt as f64;
+            match self.paddle {
+                Some(ref mut paddle) => {
+                    paddle.update(&mut self.world.capsules[paddle.capsule_index], dt);
+                    self.player.azimut = paddle.azimut;
+                    self.player.position = paddle.get_launch_position();
+                }
+                None => {}
+            }
+
+            for event in self.world.step(dt) {
+                match event {
+                    WorldEvent::Interaction {
+                        ball,
+                        other: body,
+                        body_index: Some(body_index),
+                        outcome: Outcome::Plant,
+                    } => {
+                        let jitter = self.random.gen_range(-0.22, 0.22);
+                        self.flowers.plant(*body_index, body, ball, jitter);
+                    }
+                    WorldEvent::BodyImpact {
+                        body_index,
+                        body,
+                        ball,
+                    } if is_spike(ball.ball_type) => {
+                        self.flowers.damage(*body_index, body, ball);
+                    }
+                    WorldEvent::Interaction {
+                        outcome: Outcome::Score { points },
+                        ..
+                    } => {
+                        self.interaction_score = self.interaction_score + points;
+                    }
+                    WorldEvent::CapsuleHit {
+                        capsule_index,
+                        ball,
+                        ..
+                    } => match self.paddle {
+                        Some(ref mut paddle)
+                            if *capsule_index == paddle.capsule_index
+                                && is_kept_by_paddle(ball.ball_type) =>
+                        {
+                            paddle.returns = paddle.returns + 1;
+                        }
+                        _ => {}
+                    },
+                    WorldEvent::EffectTriggered { ball } => match ball.effect {
+                        Some(ImpactEffect::Blast { radius, .. }) => {
+                            self.blast_rings.push((ball.position, radius, step_time));
+                        }
+                        _ => {}
+                    },
+                    WorldEvent::Removed { index, .. } => {
+                        self.aim_target = follow_removal(self.aim_target, *index);
+                    }
+                    _ => {}
+                }
+            }
+            self.blast_rings
+                .retain(|(_, _, start)| step_time - start < BLAST_RING_DURATION);
+
+            // Garden balls that got past the paddle are lost
+            match self.paddle {
+                Some(ref mut paddle) => {
+                    self.lost_balls.clear();
+                    for (index, ball) in self.world.balls.iter().enumerate() {
+                        if is_kept_by_paddle(ball.ball_type)
+                            && paddle.is_past(ball.position, ball.radius)
+                        {
+                            self.lost_balls.push(index);
+                        }
+                    }
+                    for event in self.world.remove_balls(&self.lost_balls) {
+                        match event {
+                            WorldEvent::Removed { index, .. } => {
+                                paddle.lives = paddle.lives.saturating_sub(1);
+                                self.aim_target = follow_removal(self.aim_target, *index);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                None => {}
+            }
+
+            self.flowers
+                .update(dt, &self.world.static_bodies, &mut self.world.balls);
+
+            match self.waves {
+                Some(ref mut waves) => {
+                    waves.update(
+                        (self.world.time - self.start_time) as f32,
+                        &self.world.static_bodies,
+                        &self.screen_area,
+                        &mut self.random,
+                        &mut self.world.balls,
+                    );
+                }
+                None => {}
+            }
+
+            // Recode previous positions
+            for ball in &self.world.balls {
+                self.traces[self.trace_index] = ball.position;
+                self.trace_index = (self.trace_index + 1) % self.traces.len();
+            }
 
-            self.balls.push(ball);
+            if self.paddle.is_none() {
+                self.player.update(dt);
+            }
         }
 
-        if !self
-            .balls
-            .iter()
-            .any(|ball| ball.ball_type == BallType::BadBall)
-        {
-            let score = self
-                .balls
-                .iter()
-                .filter(|ball| ball.ball_type == BallType::Ball)
-                .count()
-                + self.seeded_flowers.len() * 10;
-            return GameOver::game_over(score as i32, self.level_parameters);
+        self.frame = self.frame + 1;
+        match self.replay {
+            Some(ref replay) if self.frame >= replay.end_frame => {
+                // Playback is over, checking it went the same way and giving control back
+                self.replay_matched = Some(self.world.state_hash() == replay.end_hash);
+                self.replay = None;
+            }
+            _ => {}
         }
 
-        return Level::None;
+        let rules = &self.definition.rules;
+        match get_outcome(rules, &self.get_progress()) {
+            Some(outcome) => {
+                let surviving_balls = self.count_flowers();
+                let flowers = self.flowers.flowers.len();
+                let score = GardenScore {
+                    total: surviving_balls as i32 * rules.score_per_ball
+                        + self.flowers.get_score()
+                        + self.interaction_score
+                        + self.waves.as_ref().map_or(0, |waves| waves.get_score())
+                        + self.paddle.as_ref().map_or(0, |paddle| paddle.get_score()),
+                    surviving_balls,
+                    flowers,
+                };
+                self.save_recording();
+                return GameOver::game_over(
+                    score,
+                    outcome,
+                    self.stage,
+                    self.endless,
+                    self.level_parameters,
+                );
+            }
+            None => return Level::None,
+        }
     }
 
     pub fn draw(&self) {
@@ -454,45 +783,254 @@ impl GardenLevel {
         //     draw_circle(trace.x, trace.y, 1., colors::BLUE);
         // }
 
-        self.player.draw();
+        // The paddle is drawn with the capsules
+        if self.paddle.is_none() {
+            self.player.draw();
+        }
 
-        for ball in &self.balls {
-            let texture = match ball.ball_type {
-                BallType::BadBall => Some(&self.bad_ball_texture),
-                BallType::Ball => Some(&self.ball_texture),
-                BallType::GoodBall => Some(&self.seed_texture),
-                _ => None,
-            };
+        for ball in &self.world.balls {
+            let texture = self
+                .ball_textures
+                .iter()
+                .find(|(ball_type, _)| ball_type == &ball.ball_type)
+                .map(|(_, texture)| texture);
             ball.draw(texture);
-            if ball.ball_type == BallType::Ball {
-                draw_circle(
-                    ball.position.x,
-                    ball.position.y,
-                    ball.radius / 4.,
-                    hsl_to_rgb(0.65, 0.80, 0.65),
+
+            // Marks of the archetypes, over the texture of their population
+            let position = ball.position;
+            match ball.ball_type {
+                BallType::Ball => {
+                    draw_circle(
+                        position.x,
+                        position.y,
+                        ball.radius / 4.,
+                        hsl_to_rgb(0.65, 0.80, 0.65),
+                    );
+                }
+                BallType::ShieldedBall => {
+                    draw_circle(
+                        position.x,
+                        position.y,
+                        ball.radius / 4.,
+                        hsl_to_rgb(0.65, 0.80, 0.65),
+                    );
+                    draw_circle_lines(
+                        position.x,
+                        position.y,
+                        ball.radius + 2.,
+                        1.5,
+                        colors::SKYBLUE,
+                    );
+                }
+                BallType::Magnet => {
+                    draw_circle_lines(
+                        position.x,
+                        position.y,
+                        MAGNET_RADIUS,
+                        1.,
+                        Color::new(0.6, 0.6, 1., 0.15),
+                    );
+                }
+                BallType::Bomb => {
+                    draw_circle(position.x, position.y, ball.radius / 3., colors::RED);
+                }
+                BallType::Splitter => {
+                    let half = Vec2::from_angle(ball.rotation) * ball.radius;
+                    draw_line(
+                        position.x - half.x,
+                        position.y - half.y,
+                        position.x + half.x,
+                        position.y + half.y,
+                        1.5,
+                        colors::ORANGE,
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        match self
+            .aim_target
+            .and_then(|index| self.world.balls.get(index))
+        {
+            Some(target) => {
+                draw_circle_lines(
+                    target.position.x,
+                    target.position.y,
+                    target.radius + 4.,
+                    1.,
+                    colors::WHITE,
                 );
             }
+            None => {}
         }
 
-        for body in &self.static_bodies {
-            body.draw(Some(&self.body_texture));
+        for capsule in &self.world.capsules {
+            capsule.draw();
         }
 
-        for flower in &self.seeded_flowers {
-            draw_texture_ex(
-                &self.seeded_flower_texture,
-                flower.position.x - 16.,
-                flower.position.y - 16.,
-                colors::WHITE,
-                DrawTextureParams {
-                    dest_size: Some(Vec2::new(20., 40.)),
-                    rotation: flower.rotation,
-                    ..Default::default()
-                },
+        for (position, radius, start) in &self.blast_rings {
+            let progress = ((self.world.time - start) / BLAST_RING_DURATION) as f32;
+            draw_circle_lines(
+                position.x,
+                position.y,
+                radius * (1. - progress),
+                2.,
+                Color::new(0.93, 0.51, 0.93, 1. - progress),
             );
         }
 
+        for (body, texture) in self.world.static_bodies.iter().zip(&self.body_textures) {
+            body.draw(Some(texture));
+        }
+
+        self.flowers
+            .draw(&self.world.static_bodies, &self.flower_textures);
+
         // quad_tree.debug_draw();
         set_default_camera();
+
+        draw_text_ex(
+            &format!(
+                "seed {} tick {} hash {:016x}",
+                self.random.seed(),
+                self.world.tick,
+                self.world.state_hash()
+            ),
+            7.,
+            17.,
+            TextParams {
+                font_size: 10,
+                ..Default::default()
+            },
+        );
+
+        let replay_status = match (&self.replay, self.replay_matched) {
+            (Some(replay), _) => Some(format!("replay {}/{}", self.frame, replay.end_frame)),
+            (None, Some(true)) => Some("replay matched".to_owned()),
+            (None, Some(false)) => Some("replay diverged".to_owned()),
+            _ => None,
+        };
+        match replay_status {
+            Some(status) => {
+                draw_text_ex(
+                    &status,
+                    7.,
+                    27.,
+                    TextParams {
+                        font_size: 10,
+                        ..Default::default()
+                    },
+                );
+            }
+            None => {}
+        }
+
+        match self.paddle {
+            Some(ref paddle) => {
+                draw_text_ex(
+                    &format!("lives {} returns {}", paddle.lives, paddle.returns),
+                    7.,
+                    47.,
+                    TextParams {
+                        font_size: 20,
+                        ..Default::default()
+                    },
+                );
+            }
+            None => {}
+        }
+
+        // Objectives under the paddle lives, met ones in green
+        let progress = self.get_progress();
+        let mut y = match self.paddle {
+            Some(_) => 67.,
+            None => 47.,
+        };
+        for objective in &self.definition.rules.objectives {
+            let color = match objective.is_met(&progress) {
+                true => colors::LIME,
+                false => colors::WHITE,
+            };
+            draw_text_ex(
+                &objective.describe(&progress),
+                7.,
+                y,
+                TextParams {
+                    font_size: 16,
+                    color,
+                    ..Default::default()
+                },
+            );
+            y = y + 18.;
+        }
+
+        match self.waves {
+            Some(ref waves) => {
+                let wave = match waves.get_count() {
+                    Some(count) => format!("wave {}/{}", waves.wave, count),
+                    None => format!("wave {}", waves.wave),
+                };
+                let next = match waves.get_next_wave_time() {
+                    Some(time) => format!(", next in {:.0}s", (time - progress.elapsed).max(0.)),
+                    None => String::new(),
+                };
+                draw_text_ex(
+                    &format!("{}{}", wave, next),
+                    7.,
+                    y,
+                    TextParams {
+                        font_size: 16,
+                        ..Default::default()
+                    },
+                );
+            }
+            None => {}
+        }
+
+        draw_text_ex(
+            &format!(
+                "flowers {} grown {}",
+                self.flowers.flowers.len(),
+                self.flowers.get_grown_count()
+            ),
+            7.,
+            screen_height() - ORBIT_BUTTON_MARGIN - 38.,
+            TextParams {
+                font_size: 20,
+                ..Default::default()
+            },
+        );
+
+        self.draw_weapon_hud();
+    }
+
+    // Selected shot and ammo, with bars for the charge and the cooldown
+    fn draw_weapon_hud(&self) {
+        let ammo = match self.weapon.ammo {
+            Some(ammo) => ammo.to_string(),
+            None => "-".to_owned(),
+        };
+        let y = screen_height() - ORBIT_BUTTON_MARGIN;
+        draw_text_ex(
+            &format!("{} ammo {}", self.weapon.shot.get_name(), ammo),
+            7.,
+            y - 14.,
+            TextParams {
+                font_size: 20,
+                color: match self.weapon.has_ammo_for(self.weapon.shot) {
+                    true => colors::WHITE,
+                    false => colors::RED,
+                },
+                ..Default::default()
+            },
+        );
+
+        let bar_width = 120.;
+        let charge = self.weapon.get_charge(self.world.time);
+        let cooldown = self.weapon.get_cooldown(self.world.time);
+        draw_rectangle_lines(7., y - 6., bar_width, 6., 1., colors::GRAY);
+        draw_rectangle(7., y - 6., bar_width * charge, 6., colors::GOLD);
+        draw_rectangle(7., y - 6., bar_width * cooldown, 6., colors::DARKGRAY);
     }
 }
@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{read_save_file, write_save_file};
+
+const HIGH_SCORES_FILE: &str = "high_scores.toml";
+pub const MAX_HIGH_SCORES: usize = 10;
+pub const MAX_NAME_LENGTH: usize = 12;
+
+// What a garden was worth when it ended
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct GardenScore {
+    pub total: i32,
+    pub surviving_balls: usize,
+    pub flowers: usize,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    pub name: String,
+    pub score: GardenScore,
+    // Seconds since the unix epoch
+    pub timestamp: u64,
+}
+
+impl ScoreEntry {
+    pub fn new(name: String, score: GardenScore) -> ScoreEntry {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+
+        return ScoreEntry {
+            name,
+            score,
+            timestamp,
+        };
+    }
+
+    // Date as year-month-day, from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    pub fn get_date(&self) -> String {
+        let days = (self.timestamp / 86400) as i64 + 719468;
+        let era = days / 146097;
+        let day_of_era = days - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        return format!("{:04}-{:02}-{:02}", year, month, day);
+    }
+}
+
+// Best scores of each level, kept in the user save folder
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    // Name typed for the last entry, proposed for the next one
+    #[serde(default)]
+    pub last_name: String,
+    // Entries sorted from the best score, keyed by level file
+    #[serde(default)]
+    pub levels: BTreeMap<String, Vec<ScoreEntry>>,
+}
+
+impl HighScores {
+    pub fn load() -> HighScores {
+        return read_save_file(HIGH_SCORES_FILE)
+            .and_then(|text| toml::from_str(&text).ok())
+            .unwrap_or_default();
+    }
+
+    pub fn save(&self) {
+        match toml::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|text| write_save_file(HIGH_SCORES_FILE, &text))
+        {
+            Err(e) => error!("Could not save high scores {}", e),
+            _ => {}
+        }
+    }
+
+    pub fn get_entries(&self, level: &str) -> &[ScoreEntry] {
+        return self
+            .levels
+            .get(level)
+            .map_or(&[], |entries| entries.as_slice());
+    }
+
+    pub fn qualifies(&self, level: &str, total: i32) -> bool {
+        let entries = self.get_entries(level);
+        return entries.len() < MAX_HIGH_SCORES
+            || entries
+                .last()
+                .map_or(true, |lowest| total > lowest.score.total);
+    }
+
+    // Adds the entry after the ones with the same score, returns its rank if it made it in the table
+    pub fn insert(&mut self, level: &str, entry: ScoreEntry) -> Option<usize> {
+        self.last_name = entry.name.clone();
+
+        let entries = self.levels.entry(level.to_owned()).or_default();
+        let rank = entries
+            .iter()
+            .position(|other| other.score.total < entry.score.total)
+            .unwrap_or(entries.len());
+        entries.insert(rank, entry);
+        entries.truncate(MAX_HIGH_SCORES);
+
+        return match rank < MAX_HIGH_SCORES {
+            true => Some(rank),
+            false => None,
+        };
+    }
+}
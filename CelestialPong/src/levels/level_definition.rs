@@ -0,0 +1,659 @@
+use macroquad::prelude::*;
+use serde::Deserialize;
+
+use crate::simulation::{
+    ball::{BallMaterial, BallType},
+    body_path::BodyPath,
+    broadphase::BroadphaseKind,
+    capsule::Capsule,
+    interactions::{InteractionRule, Outcome},
+};
+
+use crate::levels::{
+    flowers::GROWTH_STAGES,
+    objectives::{LoseCondition, Objective},
+    weapon::ShotType,
+};
+
+fn get_embedded_level_file(path: &str) -> Option<&'static str> {
+    let text: &'static str = match path {
+        "levels/campaign.toml" => include_str!("..\\..\\levels\\campaign.toml"),
+        "levels/garden.toml" => include_str!("..\\..\\levels\\garden.toml"),
+        "levels/thorny_garden.toml" => include_str!("..\\..\\levels\\thorny_garden.toml"),
+        "levels/crowded_garden.toml" => include_str!("..\\..\\levels\\crowded_garden.toml"),
+        "levels/twin_gardens.toml" => include_str!("..\\..\\levels\\twin_gardens.toml"),
+        "levels/moon_garden.toml" => include_str!("..\\..\\levels\\moon_garden.toml"),
+        "levels/paddle_garden.toml" => include_str!("..\\..\\levels\\paddle_garden.toml"),
+        "levels/wild_garden.toml" => include_str!("..\\..\\levels\\wild_garden.toml"),
+        _ => return None,
+    };
+    return Some(text);
+}
+
+// The file next to the game if there is one, the one shipped in the game otherwise
+pub fn read_level_file(path: &str) -> Result<String, String> {
+    if std::path::Path::new(path).exists() {
+        return std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e));
+    }
+
+    return get_embedded_level_file(path)
+        .map(|text| text.to_owned())
+        .ok_or_else(|| format!("{}: level file not found", path));
+}
+
+// How far and how fast the player can change its orbit. Speeds are reached with inertia, and
+// each press of a control changes the wanted speed or radius by a step.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct OrbitControlsDefinition {
+    // Radians per second
+    pub max_azimut_speed: f32,
+    pub speed_step: f32,
+    pub azimut_acceleration: f32,
+    // Pixels the player can go in or out of its orbiting radius
+    pub radius_range: f32,
+    pub radius_step: f32,
+    pub radial_acceleration: f32,
+    pub max_radial_speed: f32,
+}
+
+impl Default for OrbitControlsDefinition {
+    fn default() -> OrbitControlsDefinition {
+        return OrbitControlsDefinition {
+            max_azimut_speed: 0.6,
+            speed_step: 0.1,
+            azimut_acceleration: 0.3,
+            radius_range: 60.,
+            radius_step: 15.,
+            radial_acceleration: 200.,
+            max_radial_speed: 80.,
+        };
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PlayerDefinition {
+    pub orbiting_radius: f32,
+    // Radians per second at the start of the level
+    pub azimut_speed: f32,
+    #[serde(default)]
+    pub controls: OrbitControlsDefinition,
+}
+
+impl PlayerDefinition {
+    fn validate(&self) -> Result<(), String> {
+        let controls = &self.controls;
+        if controls.max_azimut_speed <= 0.
+            || controls.speed_step <= 0.
+            || controls.azimut_acceleration <= 0.
+            || controls.radius_step <= 0.
+            || controls.radial_acceleration <= 0.
+            || controls.max_radial_speed <= 0.
+        {
+            return Err("player controls need positive speeds, steps and accelerations".to_owned());
+        }
+        if !(0. ..self.orbiting_radius).contains(&controls.radius_range) {
+            return Err("player radius_range must be in [0, orbiting_radius)".to_owned());
+        }
+        if self.azimut_speed.abs() > controls.max_azimut_speed {
+            return Err("player azimut_speed is over its max_azimut_speed".to_owned());
+        }
+        return Ok(());
+    }
+}
+
+// Paddle moved along the player orbit to send the balls back into the garden, played instead of
+// the launcher. Every garden ball getting past it costs a life.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PaddleDefinition {
+    pub length: f32,
+    pub radius: f32,
+    // Radians per second along the orbit
+    pub speed: f32,
+    // Degrees the balls are turned by when hitting the ends of the paddle
+    pub max_deflection: f32,
+    pub lives: u32,
+    pub score_per_return: i32,
+}
+
+impl PaddleDefinition {
+    fn validate(&self) -> Result<(), String> {
+        if self.length <= 0. || self.radius <= 0. || self.speed <= 0. {
+            return Err("paddle needs a positive length, radius and speed".to_owned());
+        }
+        if !(0. ..90.).contains(&self.max_deflection) {
+            return Err("paddle max_deflection must be in [0, 90)".to_owned());
+        }
+        if self.lives == 0 {
+            return Err("paddle needs at least one life".to_owned());
+        }
+        return Ok(());
+    }
+}
+
+// Launcher of the player. Holding the launch charges the shot, a full charge multiplies its speed
+// and mass by the max_charge ones. Times are in seconds.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct WeaponDefinition {
+    // Shots are unlimited when missing
+    pub ammo: Option<u32>,
+    pub cooldown: f32,
+    pub charge_time: f32,
+    pub max_charge_speed: f32,
+    pub max_charge_mass: f32,
+    // Shots the player can choose from, the first one is selected at the start
+    pub shots: Vec<ShotType>,
+}
+
+impl Default for WeaponDefinition {
+    fn default() -> WeaponDefinition {
+        return WeaponDefinition {
+            ammo: None,
+            cooldown: 0.25,
+            charge_time: 1.,
+            max_charge_speed: 1.8,
+            max_charge_mass: 2.,
+            shots: vec![ShotType::Normal],
+        };
+    }
+}
+
+impl WeaponDefinition {
+    fn validate(&self) -> Result<(), String> {
+        if self.cooldown < 0. || self.charge_time <= 0. {
+            return Err("weapon needs a positive cooldown and charge_time".to_owned());
+        }
+        if self.max_charge_speed < 1. || self.max_charge_mass < 1. {
+            return Err(
+                "weapon max_charge_speed and max_charge_mass must be at least 1".to_owned(),
+            );
+        }
+        if self.shots.is_empty() {
+            return Err("weapon needs at least one shot".to_owned());
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RulesDefinition {
+    // Every objective has to be met to win the level
+    pub objectives: Vec<Objective>,
+    #[serde(default)]
+    pub lose_conditions: Vec<LoseCondition>,
+    pub score_per_ball: i32,
+}
+
+impl RulesDefinition {
+    fn validate(&self) -> Result<(), String> {
+        if !self
+            .objectives
+            .iter()
+            .any(|objective| objective.completes_level())
+        {
+            return Err(
+                "rules need an objective to clear, survive or plant, keeping flowers alive never ends the level"
+                    .to_owned(),
+            );
+        }
+        for objective in &self.objectives {
+            let valid = match *objective {
+                Objective::Clear { time_limit, .. } => time_limit.map_or(true, |time| time > 0.),
+                Objective::Survive { seconds } => seconds > 0.,
+                Objective::Plant { flowers } => flowers > 0,
+                Objective::KeepAlive { fraction } => fraction > 0. && fraction <= 1.,
+            };
+            if !valid {
+                return Err(format!(
+                    "{:?} objective needs positive times and counts, and a fraction in ]0, 1]",
+                    objective
+                ));
+            }
+        }
+        for condition in &self.lose_conditions {
+            match *condition {
+                LoseCondition::TimeLimit { seconds } if seconds <= 0. => {
+                    return Err("time limit lose condition needs positive seconds".to_owned());
+                }
+                _ => {}
+            }
+        }
+        return Ok(());
+    }
+}
+
+// How the flowers planted by the seeds grow, spread and die. Times are in seconds.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct GrowthDefinition {
+    // Time spent in each stage before growing to the next one
+    pub stage_time: f32,
+    // Spike hits a flower takes before dying
+    pub health: u32,
+    // Distance from a spike impact at which flowers are hit
+    pub damage_radius: f32,
+    // Time between the seeds released by a grown flower
+    pub seed_interval: f32,
+    pub seeds_per_flower: u32,
+    // Points of a flower at the end of the level, from sprout to fully grown
+    pub stage_scores: [i32; GROWTH_STAGES],
+}
+
+impl Default for GrowthDefinition {
+    fn default() -> GrowthDefinition {
+        return GrowthDefinition {
+            stage_time: 12.,
+            health: 3,
+            damage_radius: 30.,
+            seed_interval: 15.,
+            seeds_per_flower: 2,
+            stage_scores: [2, 4, 7, 10],
+        };
+    }
+}
+
+impl GrowthDefinition {
+    fn validate(&self) -> Result<(), String> {
+        if self.stage_time <= 0. || self.seed_interval <= 0. {
+            return Err("growth needs a positive stage_time and seed_interval".to_owned());
+        }
+        if self.health == 0 {
+            return Err("growth needs flowers with at least one health".to_owned());
+        }
+        return Ok(());
+    }
+}
+
+// Elliptic orbit around a body defined before, angles are in degrees
+#[derive(Clone, Debug, Deserialize)]
+pub struct KeplerOrbitDefinition {
+    pub parent: usize,
+    pub semi_major_axis: f32,
+    #[serde(default)]
+    pub eccentricity: f32,
+    #[serde(default)]
+    pub periapsis_angle: f32,
+    // Angle along the orbit at the start of the level
+    #[serde(default)]
+    pub phase: f32,
+    #[serde(default)]
+    pub retrograde: bool,
+}
+
+// Loop through the points, taking period seconds
+#[derive(Clone, Debug, Deserialize)]
+pub struct ScriptedPathDefinition {
+    pub points: Vec<[f32; 2]>,
+    pub period: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BodyDefinition {
+    // Only used by bodies that neither orbit nor follow a path
+    #[serde(default)]
+    pub position: [f32; 2],
+    pub radius: f32,
+    pub mass: f32,
+    pub texture: String,
+    #[serde(default)]
+    pub orbit: Option<KeplerOrbitDefinition>,
+    #[serde(default)]
+    pub path: Option<ScriptedPathDefinition>,
+}
+
+impl BodyDefinition {
+    pub fn get_path(&self) -> BodyPath {
+        return match (&self.orbit, &self.path) {
+            (Some(orbit), _) => BodyPath::Kepler {
+                parent: orbit.parent,
+                semi_major_axis: orbit.semi_major_axis,
+                eccentricity: orbit.eccentricity,
+                periapsis_angle: orbit.periapsis_angle.to_radians(),
+                phase: orbit.phase.to_radians(),
+                retrograde: orbit.retrograde,
+            },
+            (None, Some(path)) => BodyPath::Scripted {
+                points: path.points.iter().map(|point| to_vec2(*point)).collect(),
+                period: path.period,
+            },
+            (None, None) => BodyPath::Static,
+        };
+    }
+
+    fn validate(&self, index: usize) -> Result<(), String> {
+        match (&self.orbit, &self.path) {
+            (Some(_), Some(_)) => {
+                return Err(format!(
+                    "body {} can not both orbit and follow a path",
+                    index
+                ))
+            }
+            (Some(orbit), None) => {
+                if orbit.parent >= index {
+                    return Err(format!(
+                        "body {} must orbit around a body defined before it",
+                        index
+                    ));
+                }
+                if orbit.semi_major_axis <= 0. {
+                    return Err(format!(
+                        "body {} orbit needs a positive semi_major_axis",
+                        index
+                    ));
+                }
+                if !(0. ..0.9).contains(&orbit.eccentricity) {
+                    return Err(format!(
+                        "body {} orbit eccentricity must be in [0, 0.9)",
+                        index
+                    ));
+                }
+            }
+            (None, Some(path)) => {
+                if path.points.len() < 2 {
+                    return Err(format!("body {} path needs at least two points", index));
+                }
+                if path.period <= 0. {
+                    return Err(format!("body {} path needs a positive period", index));
+                }
+            }
+            (None, None) => {}
+        }
+        return Ok(());
+    }
+}
+
+// Barrier the balls bounce on, going from one point to the other
+#[derive(Clone, Debug, Deserialize)]
+pub struct CapsuleDefinition {
+    pub from: [f32; 2],
+    pub to: [f32; 2],
+    pub radius: f32,
+    // White when missing
+    #[serde(default)]
+    pub color: Option<[f32; 4]>,
+    #[serde(default)]
+    pub material: BallMaterial,
+}
+
+impl CapsuleDefinition {
+    pub fn get_capsule(&self) -> Capsule {
+        let mut capsule = Capsule::new(
+            to_vec2(self.from),
+            to_vec2(self.to),
+            self.radius,
+            self.color.map_or(WHITE, to_color),
+        );
+        capsule.material = self.material;
+        return capsule;
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct OrbitBand {
+    // Index of the body to orbit, the whole system of bodies when missing
+    #[serde(default)]
+    pub body: Option<usize>,
+    pub min: f32,
+    pub max: f32,
+}
+
+// Where the balls of a population appear
+#[derive(Clone, Copy, PartialEq, Debug, Default, Deserialize)]
+pub enum SpawnKind {
+    // On a circular orbit within the band
+    #[default]
+    Orbit,
+    // At the edge of the screen, falling in on an orbit that comes down to the band
+    OffScreen,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PopulationDefinition {
+    pub ball_type: BallType,
+    pub count: usize,
+    pub radius: f32,
+    pub mass: f32,
+    pub orbit: OrbitBand,
+    // Random pastel color when missing
+    #[serde(default)]
+    pub color: Option<[f32; 4]>,
+    #[serde(default)]
+    pub texture: Option<String>,
+    // Balls bounce without losing speed nor rubbing on each other when missing
+    #[serde(default)]
+    pub material: BallMaterial,
+    #[serde(default)]
+    pub spawn: SpawnKind,
+}
+
+// How the waves grow, the counts of their populations are multiplied by the curve value
+#[derive(Clone, Debug, Default, Deserialize)]
+pub enum DifficultyCurve {
+    #[default]
+    Constant,
+    // Adds step to the multiplier at every wave
+    Linear {
+        step: f32,
+    },
+    // Multiplies the multiplier by rate at every wave
+    Exponential {
+        rate: f32,
+    },
+    // Multiplier of each wave, the last one being kept for the next waves
+    Steps {
+        multipliers: Vec<f32>,
+    },
+}
+
+impl DifficultyCurve {
+    // Wave is 0 for the first one
+    pub fn get_multiplier(&self, wave: usize) -> f32 {
+        return match self {
+            DifficultyCurve::Constant => 1.,
+            DifficultyCurve::Linear { step } => 1. + step * wave as f32,
+            DifficultyCurve::Exponential { rate } => rate.powi(wave as i32),
+            DifficultyCurve::Steps { multipliers } => multipliers[wave.min(multipliers.len() - 1)],
+        };
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let valid = match self {
+            DifficultyCurve::Constant => true,
+            DifficultyCurve::Linear { step } => *step >= 0.,
+            DifficultyCurve::Exponential { rate } => *rate >= 1.,
+            DifficultyCurve::Steps { multipliers } => {
+                !multipliers.is_empty() && multipliers.iter().all(|value| *value >= 0.)
+            }
+        };
+        match valid {
+            true => return Ok(()),
+            false => {
+                return Err(format!(
+                    "{:?} difficulty curve can not go down nor be empty",
+                    self
+                ))
+            }
+        }
+    }
+}
+
+// Balls added to the garden while it is played. Times are in seconds from the start of the level.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WaveDefinition {
+    // Time of the first wave, then between two waves
+    pub delay: f32,
+    pub interval: f32,
+    // Waves keep coming until the garden dies when missing
+    #[serde(default)]
+    pub count: Option<usize>,
+    #[serde(default)]
+    pub curve: DifficultyCurve,
+    // Points of every wave that came while the garden was alive
+    #[serde(default)]
+    pub score_per_wave: i32,
+    pub populations: Vec<PopulationDefinition>,
+}
+
+impl WaveDefinition {
+    fn validate(&self) -> Result<(), String> {
+        if self.delay < 0. || self.interval <= 0. {
+            return Err("waves need a positive delay and interval".to_owned());
+        }
+        if self.count == Some(0) || self.populations.is_empty() {
+            return Err("waves need at least one wave and one population".to_owned());
+        }
+        return self.curve.validate();
+    }
+}
+
+// Content of a garden, as described in the level files
+#[derive(Clone, Debug, Deserialize)]
+pub struct LevelDefinition {
+    pub background_color: [f32; 4],
+    pub flower_texture: String,
+    pub orbit_trap: f32,
+    pub orbit_trap_size: f32,
+    // How balls close to each other are found, a quad tree when missing
+    #[serde(default)]
+    pub broadphase: BroadphaseKind,
+    pub player: PlayerDefinition,
+    // The garden is played with a paddle when there is one
+    #[serde(default)]
+    pub paddle: Option<PaddleDefinition>,
+    #[serde(default)]
+    pub weapon: WeaponDefinition,
+    pub rules: RulesDefinition,
+    #[serde(default)]
+    pub growth: GrowthDefinition,
+    pub bodies: Vec<BodyDefinition>,
+    #[serde(default)]
+    pub capsules: Vec<CapsuleDefinition>,
+    pub populations: Vec<PopulationDefinition>,
+    // Balls coming once the level started, none when missing
+    #[serde(default)]
+    pub waves: Option<WaveDefinition>,
+    // What balls do when touching each other or a body, on top of the default behaviour
+    #[serde(default)]
+    pub interactions: Vec<InteractionRule>,
+}
+
+impl LevelDefinition {
+    pub fn parse(text: &str, origin: &str) -> Result<LevelDefinition, String> {
+        let definition: LevelDefinition =
+            toml::from_str(text).map_err(|e| format!("{}: {}", origin, e))?;
+
+        if definition.bodies.is_empty() {
+            return Err(format!("{}: a level needs at least one body", origin));
+        }
+
+        definition
+            .player
+            .validate()
+            .map_err(|e| format!("{}: {}", origin, e))?;
+
+        match definition.paddle {
+            Some(ref paddle) => paddle
+                .validate()
+                .map_err(|e| format!("{}: {}", origin, e))?,
+            None => {}
+        }
+
+        definition
+            .weapon
+            .validate()
+            .map_err(|e| format!("{}: {}", origin, e))?;
+
+        definition
+            .rules
+            .validate()
+            .map_err(|e| format!("{}: {}", origin, e))?;
+
+        definition
+            .growth
+            .validate()
+            .map_err(|e| format!("{}: {}", origin, e))?;
+
+        for (index, body) in definition.bodies.iter().enumerate() {
+            body.validate(index)
+                .map_err(|e| format!("{}: {}", origin, e))?;
+        }
+
+        for (index, capsule) in definition.capsules.iter().enumerate() {
+            if capsule.radius <= 0. {
+                return Err(format!(
+                    "{}: capsule {} needs a positive radius",
+                    origin, index
+                ));
+            }
+        }
+
+        match definition.waves {
+            Some(ref waves) => waves.validate().map_err(|e| format!("{}: {}", origin, e))?,
+            None => {}
+        }
+
+        for population in definition.get_all_populations() {
+            let material = population.material;
+            if !(0. ..=1.).contains(&material.restitution) || material.friction < 0. {
+                return Err(format!(
+                    "{}: {:?} population needs a restitution in [0, 1] and a positive friction",
+                    origin, population.ball_type
+                ));
+            }
+        }
+
+        for rule in &definition.interactions {
+            let spawns_body = rule.outcomes.iter().any(|outcome| match outcome {
+                Outcome::Convert { into } => into == &BallType::Body,
+                Outcome::Spawn { ball_type, count } => ball_type == &BallType::Body || count == &0,
+                _ => false,
+            });
+            if rule.ball == BallType::Body || spawns_body {
+                return Err(format!(
+                    "{}: {:?} {:?} interaction can not turn balls into bodies nor spawn nothing",
+                    origin, rule.ball, rule.other
+                ));
+            }
+        }
+
+        match definition.get_all_populations().find_map(|population| {
+            population
+                .orbit
+                .body
+                .filter(|body| body >= &definition.bodies.len())
+                .map(|body| (population.ball_type, body))
+        }) {
+            Some((ball_type, body)) => {
+                return Err(format!(
+                    "{}: {:?} population orbits around missing body {}",
+                    origin, ball_type, body
+                ))
+            }
+            None => {}
+        }
+
+        return Ok(definition);
+    }
+
+    pub fn load(path: &str) -> Result<LevelDefinition, String> {
+        let text = read_level_file(path)?;
+        return LevelDefinition::parse(&text, path);
+    }
+
+    // Populations of the start of the level followed by the ones of the waves
+    pub fn get_all_populations(&self) -> impl Iterator<Item = &PopulationDefinition> {
+        return self
+            .populations
+            .iter()
+            .chain(self.waves.iter().flat_map(|waves| waves.populations.iter()));
+    }
+}
+
+pub fn to_vec2(value: [f32; 2]) -> Vec2 {
+    return Vec2::new(value[0], value[1]);
+}
+
+pub fn to_color(value: [f32; 4]) -> Color {
+    return Color::new(value[0], value[1], value[2], value[3]);
+}
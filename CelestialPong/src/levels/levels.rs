@@ -1,11 +1,14 @@
 use macroquad::math::Vec2;
 
-use crate::levels::{game_over::*, garden_level::*, sandbox_level::*, title_screen::*};
+use crate::levels::{
+    game_over::*, garden_level::*, sandbox_level::*, stage_select::*, title_screen::*,
+};
 
 use super::tutorial::Tutorial;
 
 pub enum Level {
     TitleScreen(TitleScreen),
+    StageSelect(StageSelect),
     SandboxLevel(SandboxLevel),
     GardenLevel(GardenLevel),
     GameOver(GameOver),
@@ -26,9 +29,20 @@ impl Level {
         }
     }
 
+    // Inputs read once per drawn frame, however many updates run during it
+    pub fn read_frame_inputs(&mut self) {
+        match self {
+            Level::GardenLevel(level) => {
+                level.read_frame_inputs();
+            }
+            _ => {}
+        }
+    }
+
     pub fn update(&mut self) -> Level {
         match self {
             Level::TitleScreen(title) => title.update(),
+            Level::StageSelect(screen) => screen.update(),
             Level::SandboxLevel(level) => level.update(),
             Level::GardenLevel(level) => level.update(),
             Level::GameOver(level) => level.update(),
@@ -42,6 +56,9 @@ impl Level {
             Level::TitleScreen(screen) => {
                 screen.draw();
             }
+            Level::StageSelect(screen) => {
+                screen.draw();
+            }
             Level::SandboxLevel(level) => {
                 level.draw();
             }
@@ -63,4 +80,6 @@ impl Level {
 pub struct LevelParameters {
     pub window_size: [f32; 2],
     pub play_area_size: Vec2,
+    // Seed of the random generator owned by the levels, shown in game so runs can be replayed
+    pub seed: u64,
 }
@@ -1,6 +1,17 @@
+pub mod campaign;
+pub mod flowers;
 pub mod game_over;
 pub mod garden_level;
+pub mod high_scores;
+pub mod level_definition;
 pub mod levels;
+pub mod objectives;
+pub mod paddle;
+pub mod player;
+pub mod replay;
 pub mod sandbox_level;
+pub mod stage_select;
 pub mod title_screen;
 pub mod tutorial;
+pub mod waves;
+pub mod weapon;
@@ -0,0 +1,228 @@
+use serde::Deserialize;
+
+use crate::{
+    levels::level_definition::RulesDefinition,
+    simulation::ball::{Ball, BallType},
+};
+
+// Goals of a garden, all of them have to be met to win it. Times are in seconds.
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+pub enum Objective {
+    // No ball of the type remains, the level is lost when it takes longer than the time limit
+    Clear {
+        ball_type: BallType,
+        #[serde(default)]
+        time_limit: Option<f32>,
+    },
+    Survive {
+        seconds: f32,
+    },
+    // Flowers planted since the start, whether they are still alive or not
+    Plant {
+        flowers: usize,
+    },
+    // Part of the flowers from the start, in [0, 1], the level is lost when fewer remain
+    KeepAlive {
+        fraction: f32,
+    },
+}
+
+// Ways to lose a garden on top of failing its objectives
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+pub enum LoseCondition {
+    // No ball of the type remains
+    Cleared { ball_type: BallType },
+    // The level lasted this long without meeting its objectives
+    TimeLimit { seconds: f32 },
+    // No flower orbits the garden anymore
+    NoFlowersLeft,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DefeatReason {
+    Cleared(BallType),
+    TimeUp,
+    FlowersLost,
+    OutOfLives,
+}
+
+// How a garden ended
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LevelOutcome {
+    Victory,
+    Defeat(DefeatReason),
+}
+
+impl LevelOutcome {
+    pub fn is_victory(&self) -> bool {
+        return self == &LevelOutcome::Victory;
+    }
+
+    pub fn get_message(&self) -> String {
+        return match self {
+            LevelOutcome::Victory => "Victory".to_owned(),
+            LevelOutcome::Defeat(DefeatReason::Cleared(ball_type)) => {
+                format!("Defeat, no {:?} left", ball_type)
+            }
+            LevelOutcome::Defeat(DefeatReason::TimeUp) => "Defeat, out of time".to_owned(),
+            LevelOutcome::Defeat(DefeatReason::FlowersLost) => {
+                "Defeat, too many flowers lost".to_owned()
+            }
+            LevelOutcome::Defeat(DefeatReason::OutOfLives) => "Defeat, out of lives".to_owned(),
+        };
+    }
+}
+
+// What the objectives are checked against, times are from the start of the level
+pub struct GardenProgress<'a> {
+    pub elapsed: f32,
+    pub balls: &'a [Ball],
+    // Flowers orbiting the garden, at the start of the level and now
+    pub initial_flowers: usize,
+    pub planted_flowers: usize,
+    pub out_of_lives: bool,
+    // Waves still to come hold the victory back
+    pub waves_left: bool,
+}
+
+impl<'a> GardenProgress<'a> {
+    fn count(&self, ball_type: BallType) -> usize {
+        return self
+            .balls
+            .iter()
+            .filter(|ball| ball.ball_type == ball_type)
+            .count();
+    }
+
+    fn count_flowers(&self) -> usize {
+        return self
+            .balls
+            .iter()
+            .filter(|ball| ball.ball_type.is_flower())
+            .count();
+    }
+
+    fn get_alive_fraction(&self) -> f32 {
+        if self.initial_flowers == 0 {
+            return 1.;
+        }
+        return self.count_flowers() as f32 / self.initial_flowers as f32;
+    }
+}
+
+impl Objective {
+    pub fn is_met(&self, progress: &GardenProgress) -> bool {
+        return match *self {
+            Objective::Clear { ball_type, .. } => progress.count(ball_type) == 0,
+            Objective::Survive { seconds } => progress.elapsed >= seconds,
+            Objective::Plant { flowers } => progress.planted_flowers >= flowers,
+            Objective::KeepAlive { fraction } => progress.get_alive_fraction() >= fraction,
+        };
+    }
+
+    // Objectives that can no longer be met lose the level
+    pub fn get_failure(&self, progress: &GardenProgress) -> Option<DefeatReason> {
+        return match *self {
+            Objective::Clear {
+                time_limit: Some(time_limit),
+                ..
+            } if progress.elapsed > time_limit && !self.is_met(progress) => {
+                Some(DefeatReason::TimeUp)
+            }
+            Objective::KeepAlive { .. } if !self.is_met(progress) => {
+                Some(DefeatReason::FlowersLost)
+            }
+            _ => None,
+        };
+    }
+
+    // Only keeping flowers alive is met from the start, it needs another objective to end the level
+    pub fn completes_level(&self) -> bool {
+        return match self {
+            Objective::KeepAlive { .. } => false,
+            _ => true,
+        };
+    }
+
+    // Short line for the HUD
+    pub fn describe(&self, progress: &GardenProgress) -> String {
+        return match *self {
+            Objective::Clear {
+                ball_type,
+                time_limit,
+            } => match time_limit {
+                Some(time_limit) => format!(
+                    "clear {:?} {} left {:.0}s",
+                    ball_type,
+                    progress.count(ball_type),
+                    (time_limit - progress.elapsed).max(0.)
+                ),
+                None => format!("clear {:?} {} left", ball_type, progress.count(ball_type)),
+            },
+            Objective::Survive { seconds } => {
+                format!(
+                    "survive {:.0}/{:.0}s",
+                    progress.elapsed.min(seconds),
+                    seconds
+                )
+            }
+            Objective::Plant { flowers } => {
+                format!("plant {}/{} flowers", progress.planted_flowers, flowers)
+            }
+            Objective::KeepAlive { fraction } => format!(
+                "keep {:.0}% flowers alive, {:.0}%",
+                fraction * 100.,
+                progress.get_alive_fraction() * 100.
+            ),
+        };
+    }
+}
+
+impl LoseCondition {
+    fn get_defeat(&self, progress: &GardenProgress) -> Option<DefeatReason> {
+        return match *self {
+            LoseCondition::Cleared { ball_type } if progress.count(ball_type) == 0 => {
+                Some(DefeatReason::Cleared(ball_type))
+            }
+            LoseCondition::TimeLimit { seconds } if progress.elapsed > seconds => {
+                Some(DefeatReason::TimeUp)
+            }
+            LoseCondition::NoFlowersLeft if progress.count_flowers() == 0 => {
+                Some(DefeatReason::FlowersLost)
+            }
+            _ => None,
+        };
+    }
+}
+
+// The outcome once the garden is over, defeats coming before victories
+pub fn get_outcome(rules: &RulesDefinition, progress: &GardenProgress) -> Option<LevelOutcome> {
+    if progress.out_of_lives {
+        return Some(LevelOutcome::Defeat(DefeatReason::OutOfLives));
+    }
+
+    let defeat = rules
+        .lose_conditions
+        .iter()
+        .find_map(|condition| condition.get_defeat(progress))
+        .or_else(|| {
+            rules
+                .objectives
+                .iter()
+                .find_map(|objective| objective.get_failure(progress))
+        });
+    match defeat {
+        Some(reason) => return Some(LevelOutcome::Defeat(reason)),
+        None => {}
+    }
+
+    match !progress.waves_left
+        && rules
+            .objectives
+            .iter()
+            .all(|objective| objective.is_met(progress))
+    {
+        true => return Some(LevelOutcome::Victory),
+        false => return None,
+    }
+}
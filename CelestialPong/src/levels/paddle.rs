@@ -0,0 +1,118 @@
+use macroquad::{color::colors, math::Vec2};
+
+use crate::{
+    levels::level_definition::PaddleDefinition,
+    simulation::{ball::BallType, capsule::Capsule},
+};
+
+// Room left between the paddle and the projectiles launched from it
+const LAUNCH_CLEARANCE: f32 = 12.;
+// Distance past the paddle at which a ball is lost
+const ESCAPE_MARGIN: f32 = 20.;
+
+// Shortest signed angle going from one azimut to the other
+fn get_angle_difference(from: f32, to: f32) -> f32 {
+    return (to - from + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU)
+        - std::f32::consts::PI;
+}
+
+// Flowers and seeds are the balls the paddle has to keep in the garden
+pub fn is_kept_by_paddle(ball_type: BallType) -> bool {
+    return ball_type.is_flower() || ball_type == BallType::GoodBall;
+}
+
+// Paddle the player moves along its orbit, it is a capsule of the world bouncing the balls back
+pub struct Paddle {
+    pub azimut: f32,
+    // Left and right keys, -1, 0 or 1
+    pub steering: f32,
+    // Azimut the paddle heads to, following the mouse
+    pub target: Option<f32>,
+    pub lives: u32,
+    // Garden balls sent back into the garden
+    pub returns: usize,
+    // Index of the paddle in the world capsules
+    pub capsule_index: usize,
+
+    orbiting_center: Vec2,
+    orbiting_radius: f32,
+    definition: PaddleDefinition,
+}
+
+impl Paddle {
+    pub fn new(
+        definition: PaddleDefinition,
+        orbiting_center: Vec2,
+        orbiting_radius: f32,
+        capsule_index: usize,
+    ) -> Paddle {
+        return Paddle {
+            azimut: 0.,
+            steering: 0.,
+            target: None,
+            lives: definition.lives,
+            returns: 0,
+            capsule_index,
+            orbiting_center,
+            orbiting_radius,
+            definition,
+        };
+    }
+
+    pub fn get_capsule(&self) -> Capsule {
+        let (p1, p2) = self.get_ends();
+        let mut capsule = Capsule::new(p1, p2, self.definition.radius, colors::GOLD);
+        capsule.deflection = self.definition.max_deflection.to_radians();
+        return capsule;
+    }
+
+    pub fn get_score(&self) -> i32 {
+        return self.returns as i32 * self.definition.score_per_return;
+    }
+
+    pub fn reset(&mut self) {
+        self.lives = self.definition.lives;
+        self.returns = 0;
+    }
+
+    // Across the orbit, centered on it
+    fn get_ends(&self) -> (Vec2, Vec2) {
+        let direction = Vec2::from_angle(self.azimut);
+        let center = self.orbiting_center + direction * self.orbiting_radius;
+        let half = direction.perp() * self.definition.length / 2.;
+        return (center - half, center + half);
+    }
+
+    // Where projectiles are launched from, inside the orbit so that they do not hit the paddle
+    pub fn get_launch_position(&self) -> Vec2 {
+        return self.orbiting_center
+            + Vec2::from_angle(self.azimut)
+                * (self.orbiting_radius - self.definition.radius - LAUNCH_CLEARANCE);
+    }
+
+    pub fn get_azimut_toward(&self, position: Vec2) -> f32 {
+        return Vec2::X.angle_between(position - self.orbiting_center);
+    }
+
+    // Whether a ball at this position got past the paddle
+    pub fn is_past(&self, position: Vec2, radius: f32) -> bool {
+        let escape_radius = self.orbiting_radius + self.definition.radius + radius + ESCAPE_MARGIN;
+        return position.distance_squared(self.orbiting_center) > escape_radius * escape_radius;
+    }
+
+    // Moves the paddle along the orbit at its speed, the keys taking over the mouse
+    pub fn update(&mut self, capsule: &mut Capsule, dt: f32) {
+        let max_move = self.definition.speed * dt;
+        let movement = match (self.steering != 0., self.target) {
+            (true, _) => self.steering * max_move,
+            (false, Some(target)) => {
+                get_angle_difference(self.azimut, target).clamp(-max_move, max_move)
+            }
+            (false, None) => 0.,
+        };
+        self.azimut = (self.azimut + movement).rem_euclid(std::f32::consts::TAU);
+
+        let (p1, p2) = self.get_ends();
+        capsule.move_to(p1, p2, dt);
+    }
+}
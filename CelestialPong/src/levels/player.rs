@@ -0,0 +1,102 @@
+use macroquad::{color::colors, math::Vec2, shapes::draw_poly};
+
+use crate::{
+    controls::PlayerControl,
+    levels::level_definition::{OrbitControlsDefinition, PlayerDefinition},
+};
+
+// Launcher orbiting the garden. The controls change the speed it should orbit at and the radius
+// it should orbit on, which it reaches with inertia.
+pub struct Player {
+    pub position: Vec2,
+    pub orientation: f32,
+    pub orbiting_center: Vec2,
+    pub orbiting_radius: f32,
+    pub azimut: f32,
+    // Radians per second
+    pub azimut_speed: f32,
+    // Pixels per second, away from the orbiting center
+    pub radial_speed: f32,
+    pub target_azimut_speed: f32,
+    pub target_radius: f32,
+
+    // Radius of the level orbit, the player can go controls.radius_range in or out of it
+    base_radius: f32,
+    controls: OrbitControlsDefinition,
+}
+
+impl Player {
+    pub fn new(definition: &PlayerDefinition, orbiting_center: Vec2) -> Player {
+        let mut player = Player {
+            position: Vec2::ZERO,
+            orientation: 0.,
+            orbiting_center,
+            orbiting_radius: definition.orbiting_radius,
+            azimut: 0.,
+            azimut_speed: definition.azimut_speed,
+            radial_speed: 0.,
+            target_azimut_speed: definition.azimut_speed,
+            target_radius: definition.orbiting_radius,
+            base_radius: definition.orbiting_radius,
+            controls: definition.controls,
+        };
+        player.update(0.);
+        return player;
+    }
+
+    pub fn apply_control(&mut self, control: PlayerControl) {
+        let controls = &self.controls;
+        match control {
+            PlayerControl::Clockwise => {
+                self.target_azimut_speed =
+                    (self.target_azimut_speed + controls.speed_step).min(controls.max_azimut_speed);
+            }
+            PlayerControl::AntiClockwise => {
+                self.target_azimut_speed = (self.target_azimut_speed - controls.speed_step)
+                    .max(-controls.max_azimut_speed);
+            }
+            PlayerControl::Reverse => {
+                self.target_azimut_speed = -self.target_azimut_speed;
+            }
+            PlayerControl::RadiusOut => {
+                self.target_radius = (self.target_radius + controls.radius_step)
+                    .min(self.base_radius + controls.radius_range);
+            }
+            PlayerControl::RadiusIn => {
+                self.target_radius = (self.target_radius - controls.radius_step)
+                    .max(self.base_radius - controls.radius_range);
+            }
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let max_change = self.controls.azimut_acceleration * dt;
+        self.azimut_speed = self.azimut_speed
+            + (self.target_azimut_speed - self.azimut_speed).clamp(-max_change, max_change);
+        self.azimut = self.azimut + self.azimut_speed * dt;
+
+        // Fastest radial speed that can still stop on the target radius
+        let acceleration = self.controls.radial_acceleration;
+        let offset = self.target_radius - self.orbiting_radius;
+        let wanted_speed = offset.signum()
+            * f32::sqrt(2. * acceleration * offset.abs()).min(self.controls.max_radial_speed);
+        let max_change = acceleration * dt;
+        self.radial_speed =
+            self.radial_speed + (wanted_speed - self.radial_speed).clamp(-max_change, max_change);
+        self.orbiting_radius = self.orbiting_radius + self.radial_speed * dt;
+
+        self.position = self.orbiting_center
+            + Vec2::from_angle(self.azimut).rotate(Vec2::X) * self.orbiting_radius;
+    }
+
+    pub fn draw(&self) {
+        draw_poly(
+            self.position.x,
+            self.position.y,
+            3,
+            10.,
+            self.orientation,
+            colors::GOLD,
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use macroquad::math::Vec2;
+
+use crate::{controls::PlayerControl, levels::weapon::ShotType};
+
+const REPLAY_HEADER: &str = "celestial-pong-replay";
+const REPLAY_VERSION: u32 = 4;
+
+pub const LAST_REPLAY_PATH: &str = "last_garden.replay";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlayerAction {
+    // Aim is the world space vector from the player to the cursor, charge goes from 0 to 1
+    Launch {
+        aim: Vec2,
+        charge: f32,
+        shot: ShotType,
+    },
+    // Change of the player orbit
+    Control {
+        control: PlayerControl,
+    },
+    // Paddle keys, -1 to go left, 1 to go right and 0 to stop
+    SteerPaddle {
+        steering: f32,
+    },
+    // Azimut of the mouse around the paddle orbit
+    MovePaddle {
+        azimut: f32,
+    },
+    TogglePause,
+    Reset,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RecordedAction {
+    pub frame: u64,
+    pub action: PlayerAction,
+}
+
+// Every player action of a garden session, along with what is needed to play it back.
+// Saved as a text file :
+//   celestial-pong-replay <version>
+//   seed <seed>
+//   stage <campaign stage>
+//   mode <normal | endless>
+//   end <frame> <world state hash>
+//   <frame> launch <x> <y> <charge> <shot> | <frame> control <name> | <frame> steer <steering>
+//   | <frame> paddle <azimut> | <frame> pause | <frame> reset
+#[derive(Clone, PartialEq, Debug)]
+pub struct Recording {
+    pub seed: u64,
+    pub stage: usize,
+    // Played in endless mode
+    pub endless: bool,
+    pub actions: Vec<RecordedAction>,
+    pub end_frame: u64,
+    pub end_hash: u64,
+}
+
+impl Recording {
+    pub fn new(seed: u64, stage: usize, endless: bool) -> Recording {
+        return Recording {
+            seed,
+            stage,
+            endless,
+            actions: Vec::new(),
+            end_frame: 0,
+            end_hash: 0,
+        };
+    }
+
+    pub fn push(&mut self, frame: u64, action: PlayerAction) {
+        self.actions.push(RecordedAction { frame, action });
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut text = format!("{} {}\n", REPLAY_HEADER, REPLAY_VERSION);
+        text.push_str(&format!("seed {}\n", self.seed));
+        text.push_str(&format!("stage {}\n", self.stage));
+        let mode = match self.endless {
+            true => "endless",
+            false => "normal",
+        };
+        text.push_str(&format!("mode {}\n", mode));
+        text.push_str(&format!("end {} {:016x}\n", self.end_frame, self.end_hash));
+
+        for recorded in &self.actions {
+            let line = match recorded.action {
+                PlayerAction::Launch { aim, charge, shot } => format!(
+                    "{} launch {} {} {} {}\n",
+                    recorded.frame,
+                    aim.x,
+                    aim.y,
+                    charge,
+                    shot.get_name()
+                ),
+                PlayerAction::Control { control } => {
+                    format!("{} control {}\n", recorded.frame, control.get_name())
+                }
+                PlayerAction::SteerPaddle { steering } => {
+                    format!("{} steer {}\n", recorded.frame, steering)
+                }
+                PlayerAction::MovePaddle { azimut } => {
+                    format!("{} paddle {}\n", recorded.frame, azimut)
+                }
+                PlayerAction::TogglePause => format!("{} pause\n", recorded.frame),
+                PlayerAction::Reset => format!("{} reset\n", recorded.frame),
+            };
+            text.push_str(&line);
+        }
+
+        return std::fs::write(path, text).map_err(|e| format!("{}: {}", path, e));
+    }
+
+    pub fn load(path: &str) -> Result<Recording, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut lines = text.lines().enumerate();
+        let error = |line: usize, message: &str| format!("{}:{}: {}", path, line + 1, message);
+
+        let mut next_fields = |expected: &str| -> Result<(usize, Vec<&str>), String> {
+            match lines.next() {
+                Some((line, content)) => {
+                    let fields: Vec<&str> = content.split_whitespace().collect();
+                    if fields.first() != Some(&expected) {
+                        return Err(error(line, &format!("expected `{}`", expected)));
+                    }
+                    Ok((line, fields))
+                }
+                None => Err(format!("{}: missing `{}`", path, expected)),
+            }
+        };
+
+        let (line, fields) = next_fields(REPLAY_HEADER)?;
+        let version = fields.get(1).and_then(|v| v.parse::<u32>().ok());
+        if version != Some(REPLAY_VERSION) {
+            return Err(error(line, "unsupported replay version"));
+        }
+
+        let (line, fields) = next_fields("seed")?;
+        let seed = fields
+            .get(1)
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| error(line, "invalid seed"))?;
+
+        let (line, fields) = next_fields("stage")?;
+        let stage = fields
+            .get(1)
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| error(line, "invalid stage"))?;
+
+        let (line, fields) = next_fields("mode")?;
+        let endless = match fields.get(1) {
+            Some(&"normal") => false,
+            Some(&"endless") => true,
+            _ => return Err(error(line, "invalid mode")),
+        };
+
+        let (line, fields) = next_fields("end")?;
+        let end_frame = fields
+            .get(1)
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| error(line, "invalid end frame"))?;
+        let end_hash = fields
+            .get(2)
+            .and_then(|v| u64::from_str_radix(v, 16).ok())
+            .ok_or_else(|| error(line, "invalid end hash"))?;
+
+        let mut recording = Recording {
+            seed,
+            stage,
+            endless,
+            actions: Vec::new(),
+            end_frame,
+            end_hash,
+        };
+
+        for (line, content) in lines {
+            let fields: Vec<&str> = content.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+
+            let frame = fields[0]
+                .parse()
+                .map_err(|_| error(line, "invalid frame"))?;
+            let float = |index: usize| fields.get(index).and_then(|v| v.parse::<f32>().ok());
+            let action = match fields.get(1) {
+                Some(&"launch") => match (
+                    float(2),
+                    float(3),
+                    float(4),
+                    fields.get(5).and_then(|name| ShotType::from_name(name)),
+                ) {
+                    (Some(x), Some(y), Some(charge), Some(shot)) => PlayerAction::Launch {
+                        aim: Vec2::new(x, y),
+                        charge,
+                        shot,
+                    },
+                    _ => return Err(error(line, "invalid launch")),
+                },
+                Some(&"control") => match fields
+                    .get(2)
+                    .and_then(|name| PlayerControl::from_name(name))
+                {
+                    Some(control) => PlayerAction::Control { control },
+                    None => return Err(error(line, "unknown control")),
+                },
+                Some(&"steer") => match float(2) {
+                    Some(steering) => PlayerAction::SteerPaddle { steering },
+                    None => return Err(error(line, "invalid paddle steering")),
+                },
+                Some(&"paddle") => match float(2) {
+                    Some(azimut) => PlayerAction::MovePaddle { azimut },
+                    None => return Err(error(line, "invalid paddle azimut")),
+                },
+                Some(&"pause") => PlayerAction::TogglePause,
+                Some(&"reset") => PlayerAction::Reset,
+                _ => return Err(error(line, "unknown action")),
+            };
+            recording.push(frame, action);
+        }
+
+        return Ok(recording);
+    }
+}
@@ -4,12 +4,11 @@
 use macroquad::{
     color::{self, colors},
     prelude::*,
-    rand::{srand, RandomRange},
 };
 
 use crate::{
     levels::{levels::*, title_screen::*},
-    simulation::{ball::*, gravity::*, quad_tree::*},
+    simulation::{ball::*, capsule::Capsule, gravity::*, random::*, world::*},
 };
 use crate::{simulation::quad_tree, SIMULATION_DT};
 
@@ -18,7 +17,20 @@ const RADII: f32 = 3.;
 const BALL_MASS: f32 = 40.;
 
 const BODY_MASS: f32 = 5000000.;
-// const BODY_BOUNCYNESS: f32 = 0.9;
+const BODY_BOUNCYNESS: f32 = 0.9;
+
+// Balls losing some speed and rubbing on each other, turning their spin into motion
+const ROUGH_MATERIAL: BallMaterial = BallMaterial {
+    restitution: 0.8,
+    friction: 0.4,
+};
+
+// Bar turning across the ring of balls, toggled with C
+const SWEEPER_ORBIT: f32 = 296.;
+const SWEEPER_LENGTH: f32 = 120.;
+const SWEEPER_RADIUS: f32 = 4.;
+// Radians per second
+const SWEEPER_SPIN: f32 = 1.5;
 
 const ORBIT_TRAP: f32 = 10.0;
 const ORBIT_TRAP_SIZE: f32 = RADII * RADII;
@@ -26,24 +38,40 @@ const ORBIT_TRAP_SIZE: f32 = RADII * RADII;
 const MIN_START_ORBIT: f32 = 290.;
 const MAX_START_ORBIT: f32 = 301.;
 
+// N-body mode, a wider and heavier disk of balls pulling on each other
+const N_BODY_NB_BALLS: usize = 2000;
+const N_BODY_BALL_MASS: f32 = 400.;
+const N_BODY_MIN_START_ORBIT: f32 = 120.;
+const N_BODY_MAX_START_ORBIT: f32 = 450.;
+
 const TRACE_SIZE: usize = 1000;
 
-fn random_orbital_pos(center: Vec2, min_radius: f32, max_radius: f32) -> Vec2 {
-    let angle = RandomRange::gen_range(0., std::f32::consts::PI * 2.);
-    let result = Vec2::from((angle.cos(), angle.sin()));
-    let rad = RandomRange::gen_range(min_radius, max_radius);
-    let result = center + result * rad;
-    return result;
+// The sweeper turns on itself, on the ring of balls
+fn get_sweeper_ends(time: f64) -> (Vec2, Vec2) {
+    let half = Vec2::from_angle(time as f32 * SWEEPER_SPIN) * SWEEPER_LENGTH / 2.;
+    let center = Vec2::new(SWEEPER_ORBIT, 0.);
+    return (center - half, center + half);
 }
 
-fn reset_balls(balls: &mut Vec<Ball>, static_bodies: &Vec<Ball>) {
-    balls.clear();
-
-    for index in 0..NB_BALLS {
-        let position =
-            random_orbital_pos(static_bodies[0].position, MIN_START_ORBIT, MAX_START_ORBIT);
-
-        let color = match index < NB_BALLS / 2 {
+fn reset_balls(world: &mut World, material: BallMaterial, random: &mut Random) {
+    world.clear_balls();
+    let system = get_barycenter_body(&world.static_bodies);
+    let n_body = world.n_body;
+
+    let (nb_balls, mass, min_orbit, max_orbit) = match n_body {
+        true => (
+            N_BODY_NB_BALLS,
+            N_BODY_BALL_MASS,
+            N_BODY_MIN_START_ORBIT,
+            N_BODY_MAX_START_ORBIT,
+        ),
+        false => (NB_BALLS, BALL_MASS, MIN_START_ORBIT, MAX_START_ORBIT),
+    };
+
+    for index in 0..nb_balls {
+        let position = random_orbital_pos(random, system.position, min_orbit, max_orbit);
+
+        let color = match index < nb_balls / 2 {
             true => Color {
                 r: 0.9,
                 g: 0.16,
@@ -62,35 +90,48 @@ fn reset_balls(balls: &mut Vec<Ball>, static_bodies: &Vec<Ball>) {
             position,
             Vec2::ZERO,
             RADII,
-            BALL_MASS,
+            mass,
             color,
             0.0,
             0.0,
             BallType::Ball,
         );
+        ball.material = material;
 
-        let ball_speed = get_orbital_velocity(&ball, &static_bodies[0]);
+        // In N-body mode the balls closer to the center also pull, roughly as if they were at the center
+        let inner_mass = match n_body {
+            true => {
+                let distance = (position - system.position).length();
+                mass * nb_balls as f32 * (distance - min_orbit) / (max_orbit - min_orbit)
+            }
+            false => 0.,
+        };
+        let ball_speed = get_orbital_velocity(
+            &ball,
+            &Ball {
+                mass: system.mass + inner_mass,
+                ..system
+            },
+        );
 
         ball.set_velocity(ball_speed, SIMULATION_DT);
-        balls.push(ball);
+        world.balls.push(ball);
     }
 }
 
 pub struct SandboxLevel {
     paused: bool,
     drawing_enabled: bool,
-    balls: Vec<Ball>,
-    static_bodies: Vec<Ball>,
-    tree_area: quad_tree::Rect,
-    quad_tree: QuadTree,
+    world: World,
+    random: Random,
 
     main_camera: Camera2D,
-    collided_balls: Vec<usize>,
-    balls_marked_for_delete: Vec<usize>,
-    selected_ball: Option<usize>,
     traces: [Vec2; TRACE_SIZE],
     trace_index: usize,
     ball_under: Option<usize>,
+    ball_material: BallMaterial,
+    // Diagnostics of the first step since the last reset, drifts are shown relative to them
+    diagnostics_reference: Option<WorldDiagnostics>,
     level_parameters: LevelParameters,
 }
 
@@ -103,13 +144,14 @@ impl SandboxLevel {
             level_parameters.play_area_size.x * 4.,
         );
 
+        let mut world = World::new(tree_area, ORBIT_TRAP, ORBIT_TRAP_SIZE);
+        world.balls.reserve(N_BODY_NB_BALLS);
+
         return SandboxLevel {
             paused: false,
             drawing_enabled: true,
-            balls: Vec::with_capacity(NB_BALLS),
-            static_bodies: Vec::new(),
-            tree_area,
-            quad_tree: QuadTree::new(tree_area),
+            world,
+            random: Random::new(level_parameters.seed),
 
             main_camera: Camera2D {
                 zoom: Vec2::from((
@@ -119,18 +161,17 @@ impl SandboxLevel {
                 ..Default::default()
             },
 
-            collided_balls: Vec::with_capacity(NB_BALLS),
-            balls_marked_for_delete: Vec::with_capacity(NB_BALLS),
-            selected_ball: None,
             traces: [Vec2::ZERO; TRACE_SIZE],
             trace_index: 0,
             ball_under: None,
+            ball_material: BallMaterial::default(),
+            diagnostics_reference: None,
             level_parameters,
         };
     }
 
     pub fn init(&mut self) {
-        self.static_bodies.push(Ball::new(
+        self.world.static_bodies.push(Ball::new(
             Vec2::new(0., 0.),
             Vec2::ZERO,
             30.,
@@ -141,7 +182,7 @@ impl SandboxLevel {
             BallType::Body,
         ));
 
-        reset_balls(&mut self.balls, &self.static_bodies);
+        reset_balls(&mut self.world, self.ball_material, &mut self.random);
     }
 
     pub fn update(&mut self) -> Level {
@@ -149,28 +190,83 @@ impl SandboxLevel {
             self.paused = !self.paused;
         }
 
+        if is_key_pressed(KeyCode::B) {
+            self.world
+                .set_broadphase(self.world.get_broadphase_kind().next());
+        }
+
+        if is_key_pressed(KeyCode::I) {
+            self.world.integrator = self.world.integrator.next();
+            self.diagnostics_reference = None;
+        }
+
+        if is_key_pressed(KeyCode::E) {
+            self.world.diagnostics_enabled = !self.world.diagnostics_enabled;
+            self.diagnostics_reference = None;
+        }
+
+        if is_key_pressed(KeyCode::K) {
+            self.world.body_bounciness = match self.world.body_bounciness {
+                Some(_) => None,
+                None => Some(BODY_BOUNCYNESS),
+            };
+        }
+
+        if is_key_pressed(KeyCode::F) {
+            self.ball_material = match self.ball_material == ROUGH_MATERIAL {
+                true => BallMaterial::default(),
+                false => ROUGH_MATERIAL,
+            };
+            for ball in &mut self.world.balls {
+                ball.material = self.ball_material;
+            }
+        }
+
+        if is_key_pressed(KeyCode::C) {
+            match self.world.capsules.is_empty() {
+                true => {
+                    let (p1, p2) = get_sweeper_ends(self.world.time);
+                    self.world
+                        .capsules
+                        .push(Capsule::new(p1, p2, SWEEPER_RADIUS, colors::ORANGE));
+                }
+                false => self.world.capsules.clear(),
+            }
+        }
+
         if is_key_pressed(KeyCode::V) {
             self.drawing_enabled = !self.drawing_enabled;
         }
 
+        // Switching to N-body restarts with a disk sized for it
+        if is_key_pressed(KeyCode::N) {
+            self.world.n_body = !self.world.n_body;
+            self.diagnostics_reference = None;
+            reset_balls(&mut self.world, self.ball_material, &mut self.random);
+        }
+
         if is_key_down(KeyCode::S) {
-            for ball in &mut self.balls {
+            for ball in &mut self.world.balls {
                 ball.set_velocity(ball.velocity * 0.5, SIMULATION_DT);
             }
         }
 
         if is_key_down(KeyCode::R) {
-            self.selected_ball = None;
-            srand(1);
-            reset_balls(&mut self.balls, &self.static_bodies);
+            self.diagnostics_reference = None;
+            self.random = Random::new(self.random.seed());
+            reset_balls(&mut self.world, self.ball_material, &mut self.random);
         }
 
         if is_key_down(KeyCode::O) {
-            for ball in &mut self.balls {
-                ball.set_velocity(
-                    get_orbital_velocity(ball, &self.static_bodies[0]),
-                    SIMULATION_DT,
-                );
+            for index in 0..self.world.balls.len() {
+                let ball = self.world.balls[index];
+                match self.world.get_trap_body(ball.position) {
+                    Some(trap_body) => {
+                        self.world.balls[index]
+                            .set_velocity(get_orbital_velocity(&ball, &trap_body), SIMULATION_DT);
+                    }
+                    None => {}
+                }
             }
         }
 
@@ -182,165 +278,52 @@ impl SandboxLevel {
         let dt = SIMULATION_DT;
 
         if !self.paused {
-            self.quad_tree = QuadTree::new(self.tree_area);
-            // Updating ball position
-            self.collided_balls.clear();
-            let nb_balls = self.balls.len();
-            for index in 0..nb_balls {
-                let ball = self.balls.get(index).unwrap();
-                self.quad_tree.add(QuadTreeEntry::new(ball.position, index));
-                let mut local_force = Vec2::ZERO;
-
-                // Comuting gravity
-                if self.selected_ball == None || self.selected_ball.unwrap() != index {
-                    for body in &self.static_bodies {
-                        local_force = local_force + get_gravity_force(ball, body)
-                    }
-                }
-
-                // Trapping ball in the nearest body
-                match self.static_bodies.iter().min_by(|body, other| {
-                    (body.position - ball.position)
-                        .length_squared()
-                        .total_cmp(&(other.position - ball.position).length_squared())
-                }) {
-                    Some(closest_body) => {
-                        let ideal_velocity = get_orbital_velocity(ball, closest_body);
-                        let delta = if ideal_velocity.dot(ball.velocity) > 0. {
-                            ideal_velocity - ball.velocity
-                        } else {
-                            ideal_velocity * -1. - ball.velocity
-                        };
-                        // If the ball velocity differ from the ideal orbit, nudge the ball toward that velocity
-                        if delta.length_squared() > ORBIT_TRAP_SIZE {
-                            local_force = local_force + (delta / delta.length()) * ORBIT_TRAP;
-                        }
-                    }
-                    _ => {}
-                }
-
-                let ball = self.balls.get_mut(index).unwrap();
-                // ball.update(dt, local_force);
-                ball.update_verlet(dt, local_force);
-
-                // Recode previous positions
-                self.traces[self.trace_index] = ball.position;
-                self.trace_index = (self.trace_index + 1) % self.traces.len();
+            let (p1, p2) = get_sweeper_ends(self.world.time + dt as f64);
+            for capsule in self.world.capsules.iter_mut() {
+                capsule.move_to(p1, p2, dt);
             }
 
-            // Colliding balls
-            for index in 0..self.balls.len() {
-                // Has ball already collided this frame
-                if self.collided_balls.iter().any(|c| c == &index) {
-                    continue;
-                }
-
-                let zone_check = self.balls[index].get_collision_area();
-                let mut near_balls = Vec::new();
-                self.quad_tree.query_entries(&zone_check, &mut near_balls);
-                for entry in near_balls {
-                    if entry.payload == index
-                        || self.collided_balls.iter().any(|c| c == &entry.payload)
-                    {
-                        continue;
-                    }
-
-                    let other_ball_index = entry.payload;
-
-                    if self.balls[index].check_collision(&self.balls[other_ball_index]) {
-                        if index > other_ball_index {
-                            let (left, right) = self.balls.split_at_mut(index);
-                            right[0].collide(&mut left[other_ball_index], dt);
-                        } else {
-                            let (left, right) = self.balls.split_at_mut(other_ball_index);
-                            right[0].collide(&mut left[index], dt);
-                        }
+            self.world.step(dt);
 
-                        self.collided_balls.push(index);
-                        self.collided_balls.push(other_ball_index);
-                    }
-                }
+            if self.world.diagnostics_enabled && self.diagnostics_reference.is_none() {
+                self.diagnostics_reference = Some(self.world.diagnostics);
             }
 
-            // Bounce of static bodies
-            for body in self.static_bodies.iter_mut() {
-                let query = body.get_collision_area();
-                let mut near_objects = Vec::new();
-                self.quad_tree.query_entries(&query, &mut near_objects);
-                for near in near_objects {
-                    let ball = self.balls.get_mut(near.payload).unwrap();
-                    if body.check_collision(&ball) {
-                        // BOUNCE
-                        // let delta = ball.position - body.position;
-                        // if delta.dot(ball.velocity) < 0.
-                        //     && ball.velocity.length_squared() > 0.001
-                        // {
-                        //     let delta = delta.normalize();
-                        //     ball.position = body.position + delta * (body.radius + ball.radius);
-                        //     ball.set_velocity(
-                        //         (ball.velocity - 2. * delta.dot(ball.velocity) * delta)
-                        //             * BODY_BOUNCYNESS,
-                        //         dt,
-                        //     );
-                        // }
-
-                        // DELETE
-                        if !self.balls_marked_for_delete.contains(&near.payload) {
-                            self.balls_marked_for_delete.push(near.payload);
-                        }
-                    }
-                }
-            }
-
-            self.balls_marked_for_delete.sort_unstable();
-            for index in self.balls_marked_for_delete.iter().rev() {
-                self.balls.remove(*index);
-                match self.selected_ball {
-                    Some(selected) => {
-                        if index == &selected {
-                            self.selected_ball = None;
-                        } else if index < &selected {
-                            self.selected_ball = Some(selected - 1);
-                        }
-                    }
-                    None => {}
-                }
+            // Recode previous positions
+            for ball in &self.world.balls {
+                self.traces[self.trace_index] = ball.position;
+                self.trace_index = (self.trace_index + 1) % self.traces.len();
             }
-
-            self.balls_marked_for_delete.clear();
         }
 
         let (spx, spy) = mouse_position();
         let mouse_pos = Vec2::new(spx, spy);
         let mouse_pos = self.main_camera.screen_to_world(mouse_pos);
         let dist_check = RADII * RADII * 10.;
-        let mut near_balls = Vec::new();
-        self.quad_tree.query_entries(
-            &quad_tree::Rect::new(mouse_pos.x, mouse_pos.y, dist_check, dist_check),
-            &mut near_balls,
-        );
-
-        self.ball_under = near_balls
-            .into_iter()
-            .find(|b| (self.balls[b.payload].position - mouse_pos).length_squared() < dist_check)
-            .map(|b| b.payload);
+        self.ball_under = self
+            .world
+            .broadphase
+            .nearest(mouse_pos)
+            .filter(|b| (b.position - mouse_pos).length_squared() < dist_check)
+            .map(|b| b.payload)
+            .filter(|index| *index < self.world.balls.len());
 
         if is_mouse_button_pressed(MouseButton::Left) {
             match self.ball_under {
                 Some(entry) => {
-                    self.selected_ball = Some(entry);
+                    self.world.grabbed_ball = Some(entry);
                 }
                 _ => {}
             }
         }
 
         if is_mouse_button_released(MouseButton::Left) {
-            self.selected_ball = None;
+            self.world.grabbed_ball = None;
         }
 
-        match self.selected_ball {
+        match self.world.grabbed_ball {
             Some(ball_index) => {
-                let ball = self.balls.get_mut(ball_index).unwrap();
+                let ball = self.world.balls.get_mut(ball_index).unwrap();
                 let force = damping(ball.position, mouse_pos, dt, 0.05 * dt);
                 ball.set_velocity(force, dt);
             }
@@ -354,7 +337,7 @@ impl SandboxLevel {
         if self.drawing_enabled {
             set_camera(&self.main_camera);
 
-            for ball in &self.balls {
+            for ball in &self.world.balls {
                 ball.draw(None);
 
                 // ball.get_collision_area().debug_draw(1., ball.color);
@@ -385,10 +368,14 @@ impl SandboxLevel {
                 // let v = get_orbital_velocity_compensated(ball, &static_bodies[0], dt);
             }
 
-            for body in &self.static_bodies {
+            for body in &self.world.static_bodies {
                 body.draw(None);
             }
 
+            for capsule in &self.world.capsules {
+                capsule.debug_draw();
+            }
+
             // quad_tree.debug_draw();
 
             // Draw trace objects
@@ -398,7 +385,7 @@ impl SandboxLevel {
 
             match self.ball_under {
                 Some(entry) => {
-                    let b = self.balls[entry];
+                    let b = self.world.balls[entry];
                     draw_circle_lines(b.position.x, b.position.y, b.radius + 3., 2., colors::GOLD);
                 }
                 _ => {}
@@ -406,5 +393,67 @@ impl SandboxLevel {
         }
 
         set_default_camera();
+
+        draw_text_ex(
+            &format!(
+                "seed {} tick {} hash {:016x}",
+                self.random.seed(),
+                self.world.tick,
+                self.world.state_hash()
+            ),
+            7.,
+            17.,
+            TextParams {
+                font_size: 10,
+                ..Default::default()
+            },
+        );
+        draw_text_ex(
+            &format!(
+                "integrator {} broadphase {} bodies {} balls {}",
+                self.world.integrator.get_name(),
+                self.world.get_broadphase_kind().get_name(),
+                match self.world.body_bounciness {
+                    Some(_) => "bounce",
+                    None => "absorb",
+                },
+                match self.ball_material == ROUGH_MATERIAL {
+                    true => "rough",
+                    false => "smooth",
+                }
+            ),
+            7.,
+            27.,
+            TextParams {
+                font_size: 10,
+                ..Default::default()
+            },
+        );
+
+        match (self.world.diagnostics_enabled, self.diagnostics_reference) {
+            (true, Some(reference)) => {
+                let diagnostics = self.world.diagnostics;
+                let drift = |value: f32, reference: f32| match reference == 0. {
+                    true => 0.,
+                    false => (value - reference) / reference.abs() * 100.,
+                };
+                draw_text_ex(
+                    &format!(
+                        "energy {:.4e} ({:+.3}%) angular momentum {:.4e} ({:+.3}%)",
+                        diagnostics.get_total_energy(),
+                        drift(diagnostics.get_total_energy(), reference.get_total_energy()),
+                        diagnostics.angular_momentum,
+                        drift(diagnostics.angular_momentum, reference.angular_momentum),
+                    ),
+                    7.,
+                    37.,
+                    TextParams {
+                        font_size: 10,
+                        ..Default::default()
+                    },
+                );
+            }
+            _ => {}
+        }
     }
 }
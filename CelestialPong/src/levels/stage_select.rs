@@ -0,0 +1,112 @@
+use macroquad::{
+    color::colors,
+    math::vec2,
+    prelude::*,
+    text::{draw_text_ex, TextParams},
+    ui::{root_ui, Skin},
+    window,
+};
+
+use crate::levels::{campaign::*, levels::*, title_screen::*};
+
+const STAGE_SPACING: f32 = 60.;
+// Offset of the endless mode buttons from the stage ones
+const ENDLESS_OFFSET: f32 = 240.;
+
+pub struct StageSelect {
+    level_parameters: LevelParameters,
+    stages: Vec<StageDefinition>,
+    progress: CampaignProgress,
+    button_skin: Skin,
+}
+
+impl StageSelect {
+    pub fn new(level_parameters: LevelParameters) -> StageSelect {
+        let stages = match Campaign::load() {
+            Ok(campaign) => campaign.stages,
+            Err(e) => {
+                error!("Could not load campaign {}", e);
+                Vec::new()
+            }
+        };
+
+        return StageSelect {
+            level_parameters,
+            stages,
+            progress: CampaignProgress::load(),
+            button_skin: get_menu_skin(),
+        };
+    }
+
+    fn get_stage_position(&self, stage: usize) -> Vec2 {
+        let top = window::screen_height() / 2. - STAGE_SPACING * self.stages.len() as f32 / 2.;
+        return vec2(
+            window::screen_width() / 2. - 100.,
+            top + STAGE_SPACING * stage as f32,
+        );
+    }
+
+    pub fn update(&self) -> Level {
+        // Stage and whether it is played in endless mode
+        let mut selected_stage = None;
+
+        root_ui().push_skin(&self.button_skin.clone());
+        for (stage, definition) in self.stages.iter().enumerate() {
+            let position = self.get_stage_position(stage);
+            if self.progress.is_unlocked(stage)
+                && root_ui().button(position, definition.name.as_str())
+            {
+                selected_stage = Some((stage, false));
+            }
+
+            // Endless mode opens once the stage is won
+            if definition.endless
+                && self.progress.is_unlocked(stage + 1)
+                && root_ui().button(position + vec2(ENDLESS_OFFSET, 0.), "Endless")
+            {
+                selected_stage = Some((stage, true));
+            }
+        }
+        let back = root_ui().button(vec2(30., window::screen_height() - 60.), "Back");
+        root_ui().pop_skin();
+
+        if is_key_pressed(KeyCode::Backspace) || back {
+            return Level::TitleScreen(TitleScreen::new(self.level_parameters));
+        }
+
+        match selected_stage {
+            Some((stage, endless)) => return start_stage(self.level_parameters, stage, endless),
+            None => return Level::None,
+        }
+    }
+
+    pub fn draw(&self) {
+        clear_background(colors::BLACK);
+
+        draw_text_ex(
+            "Stages",
+            window::screen_width() / 2. - 100.,
+            self.get_stage_position(0).y - 40.,
+            TextParams {
+                font_size: 42,
+                ..Default::default()
+            },
+        );
+
+        for (stage, definition) in self.stages.iter().enumerate() {
+            if !self.progress.is_unlocked(stage) {
+                let position = self.get_stage_position(stage);
+                draw_text_ex(
+                    &format!("{} (locked)", definition.name),
+                    position.x + 15.,
+                    position.y + 30.,
+                    TextParams {
+                        font_size: 20,
+                        color: colors::GRAY,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+}
@@ -9,7 +9,7 @@ use macroquad::{
 
 use crate::levels::levels::*;
 
-use super::{sandbox_level::SandboxLevel, tutorial::Tutorial};
+use super::{sandbox_level::SandboxLevel, stage_select::StageSelect, tutorial::Tutorial};
 
 pub struct TitleScreen {
     level_parameters: LevelParameters,
@@ -17,22 +17,30 @@ pub struct TitleScreen {
     button_skin: Skin,
 }
 
+// Button style shared by the menu screens
+pub fn get_menu_skin() -> Skin {
+    let button_style = root_ui()
+        .style_builder()
+        .font_size(20)
+        .margin(RectOffset {
+            top: 15.,
+            right: 15.,
+            bottom: 15.,
+            left: 15.,
+        })
+        .color(colors::LIGHTGRAY)
+        .color_hovered(colors::GRAY)
+        .color_clicked(colors::BEIGE)
+        .build();
+
+    return Skin {
+        button_style,
+        ..root_ui().default_skin()
+    };
+}
+
 impl TitleScreen {
     pub fn new(level_parameters: LevelParameters) -> TitleScreen {
-        let button_style = root_ui()
-            .style_builder()
-            .font_size(20)
-            .margin(RectOffset {
-                top: 15.,
-                right: 15.,
-                bottom: 15.,
-                left: 15.,
-            })
-            .color(colors::LIGHTGRAY)
-            .color_hovered(colors::GRAY)
-            .color_clicked(colors::BEIGE)
-            .build();
-
         let bg_texture = Texture2D::from_file_with_format(
             include_bytes!("..\\..\\textures\\title_screen.png"),
             None,
@@ -41,10 +49,7 @@ impl TitleScreen {
         return TitleScreen {
             level_parameters,
             bg_texture,
-            button_skin: Skin {
-                button_style,
-                ..root_ui().default_skin()
-            },
+            button_skin: get_menu_skin(),
         };
     }
 
@@ -57,6 +62,13 @@ impl TitleScreen {
             ),
             "Start",
         );
+        let stage_select = root_ui().button(
+            vec2(
+                window::screen_width() / 2. - 22.,
+                window::screen_height() / 2. + 220.,
+            ),
+            "Stages",
+        );
         let sandbox_level = root_ui().button(
             vec2(window::screen_width() - 100., window::screen_height() - 60.),
             "Sandbox",
@@ -65,6 +77,8 @@ impl TitleScreen {
 
         if sky_level {
             return Level::Tutorial(Tutorial::new(self.level_parameters));
+        } else if stage_select {
+            return Level::StageSelect(StageSelect::new(self.level_parameters));
         } else if sandbox_level {
             return Level::SandboxLevel(SandboxLevel::new(self.level_parameters));
         }
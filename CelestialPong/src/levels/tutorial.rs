@@ -8,7 +8,7 @@ use macroquad::{
 };
 
 use super::{
-    garden_level::GardenLevel,
+    campaign::start_stage,
     levels::{Level, LevelParameters},
 };
 
@@ -30,7 +30,7 @@ impl Tutorial {
 
     pub fn update(&mut self) -> Level {
         if is_mouse_button_pressed(window::miniquad::MouseButton::Left) {
-            return Level::GardenLevel(GardenLevel::new(self.level_parameters));
+            return start_stage(self.level_parameters, 0, false);
         }
 
         Level::None
@@ -0,0 +1,171 @@
+use macroquad::{color::hsl_to_rgb, math::Vec2};
+
+use crate::{
+    levels::level_definition::{to_color, PopulationDefinition, SpawnKind, WaveDefinition},
+    simulation::{
+        ball::Ball,
+        gravity::{get_barycenter_body, get_orbital_velocity},
+        quad_tree::Rect,
+        random::{random_orbital_pos, Random},
+    },
+    SIMULATION_DT,
+};
+
+// Room left between the balls coming from off screen and the edge, so that they are not lost
+const SCREEN_EDGE_MARGIN: f32 = 4.;
+
+// Radius of the biggest circle around the center that fits in the area. Balls starting on it
+// at the far end of their orbit go inward, where starting in a corner would take them out.
+fn get_inner_radius(area: &Rect, center: Vec2) -> f32 {
+    return (area.right - center.x)
+        .min(center.x - area.left)
+        .min(area.down - center.y)
+        .min(center.y - area.up);
+}
+
+// Adds count balls of the population, screen being the visible area of the garden
+pub fn spawn_population(
+    population: &PopulationDefinition,
+    count: usize,
+    bodies: &[Ball],
+    screen: &Rect,
+    random: &mut Random,
+    balls: &mut Vec<Ball>,
+) {
+    let body = match population.orbit.body {
+        Some(body) => bodies[body],
+        None => get_barycenter_body(bodies),
+    };
+    for _ in 0..count {
+        let band_position = random_orbital_pos(
+            random,
+            body.position,
+            population.orbit.min,
+            population.orbit.max,
+        );
+        let position = match population.spawn {
+            SpawnKind::Orbit => band_position,
+            SpawnKind::OffScreen => {
+                let direction = (band_position - body.position).normalize();
+                let distance = get_inner_radius(screen, body.position)
+                    - population.radius
+                    - SCREEN_EDGE_MARGIN;
+                body.position + direction * distance
+            }
+        };
+
+        let color = match population.color {
+            Some(color) => to_color(color),
+            None => hsl_to_rgb(
+                random.gen_range(0., 1.),
+                random.gen_range(0.45, 0.95),
+                random.gen_range(0.65, 0.99),
+            ),
+        };
+
+        let mut ball = Ball::new(
+            position,
+            Vec2::ZERO,
+            population.radius,
+            population.mass,
+            color,
+            random.gen_range(-std::f32::consts::PI, std::f32::consts::PI),
+            random.gen_range(-std::f32::consts::PI * 2., std::f32::consts::PI * 2.),
+            population.ball_type,
+        );
+        ball.material = population.material;
+
+        // Balls from off screen start at the far end of an orbit coming down to the band
+        let speed_ratio = match population.spawn {
+            SpawnKind::Orbit => 1.,
+            SpawnKind::OffScreen => {
+                let apoapsis = (position - body.position).length();
+                let periapsis = (band_position - body.position).length().min(apoapsis);
+                (2. * periapsis / (apoapsis + periapsis)).sqrt()
+            }
+        };
+        let ball_speed = get_orbital_velocity(&ball, &body) * speed_ratio + body.velocity;
+
+        ball.set_velocity(ball_speed, SIMULATION_DT);
+        balls.push(ball);
+    }
+}
+
+// Sends the waves of a level, each one bigger than the previous as set by the difficulty curve
+pub struct WaveSpawner {
+    definition: WaveDefinition,
+    // Waves keep coming past the count of the definition
+    endless: bool,
+    // Waves sent so far
+    pub wave: usize,
+}
+
+impl WaveSpawner {
+    pub fn new(definition: WaveDefinition, endless: bool) -> WaveSpawner {
+        return WaveSpawner {
+            definition,
+            endless,
+            wave: 0,
+        };
+    }
+
+    pub fn reset(&mut self) {
+        self.wave = 0;
+    }
+
+    pub fn is_endless(&self) -> bool {
+        return self.endless || self.definition.count.is_none();
+    }
+
+    // Every wave was sent
+    pub fn is_over(&self) -> bool {
+        return match self.get_count() {
+            Some(count) => self.wave >= count,
+            None => false,
+        };
+    }
+
+    // Seconds from the start of the level, none once every wave was sent
+    pub fn get_next_wave_time(&self) -> Option<f32> {
+        return match self.is_over() {
+            true => None,
+            false => Some(self.definition.delay + self.definition.interval * self.wave as f32),
+        };
+    }
+
+    pub fn get_count(&self) -> Option<usize> {
+        return match self.is_endless() {
+            true => None,
+            false => self.definition.count,
+        };
+    }
+
+    pub fn get_score(&self) -> i32 {
+        return self.wave as i32 * self.definition.score_per_wave;
+    }
+
+    // Sends the waves due by the elapsed time, returns whether any was
+    pub fn update(
+        &mut self,
+        elapsed: f32,
+        bodies: &[Ball],
+        screen: &Rect,
+        random: &mut Random,
+        balls: &mut Vec<Ball>,
+    ) -> bool {
+        let mut sent = false;
+        while self
+            .get_next_wave_time()
+            .map_or(false, |time| elapsed >= time)
+        {
+            let multiplier = self.definition.curve.get_multiplier(self.wave);
+            for population in &self.definition.populations {
+                let count = (population.count as f32 * multiplier).round() as usize;
+                spawn_population(population, count, bodies, screen, random, balls);
+            }
+            self.wave = self.wave + 1;
+            sent = true;
+        }
+        return sent;
+    }
+}
@@ -0,0 +1,197 @@
+use macroquad::{color::colors, math::Vec2};
+use serde::Deserialize;
+
+use crate::{
+    levels::level_definition::WeaponDefinition,
+    simulation::ball::{Ball, BallType, ImpactEffect},
+};
+
+const BALL_RADII: f32 = 7.;
+const BALL_MASS: f32 = 40.;
+
+// Uncharged normal shot, its speed is in pixels per step
+const SHOT_RADIUS: f32 = BALL_RADII * 1.2;
+const SHOT_MASS: f32 = BALL_MASS * 2.;
+const SHOT_SPEED: f32 = 7.;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum ShotType {
+    Normal,
+    // Slow, big and heavy, knocks balls further
+    Heavy,
+    // Breaks into three on the first ball it touches
+    Splitting,
+    // Pulls the balls around the first one it touches toward it
+    GravityBomb,
+}
+
+impl ShotType {
+    pub fn get_name(&self) -> &'static str {
+        return match self {
+            ShotType::Normal => "normal",
+            ShotType::Heavy => "heavy",
+            ShotType::Splitting => "splitting",
+            ShotType::GravityBomb => "gravity_bomb",
+        };
+    }
+
+    pub fn from_name(name: &str) -> Option<ShotType> {
+        return [
+            ShotType::Normal,
+            ShotType::Heavy,
+            ShotType::Splitting,
+            ShotType::GravityBomb,
+        ]
+        .into_iter()
+        .find(|shot| shot.get_name() == name);
+    }
+
+    // Ammo used by a shot
+    pub fn get_cost(&self) -> u32 {
+        return match self {
+            ShotType::Normal => 1,
+            ShotType::Heavy => 2,
+            ShotType::Splitting => 2,
+            ShotType::GravityBomb => 3,
+        };
+    }
+
+    // Uncharged projectile, launched from the position toward the direction
+    fn create_projectile(&self, position: Vec2, direction: Vec2) -> Ball {
+        let (radius, mass, speed, color) = match self {
+            ShotType::Normal => (SHOT_RADIUS, SHOT_MASS, SHOT_SPEED, colors::BLUE),
+            ShotType::Heavy => (
+                SHOT_RADIUS * 1.6,
+                SHOT_MASS * 4.,
+                SHOT_SPEED * 0.7,
+                colors::DARKBLUE,
+            ),
+            ShotType::Splitting => (SHOT_RADIUS, SHOT_MASS, SHOT_SPEED, colors::SKYBLUE),
+            ShotType::GravityBomb => (SHOT_RADIUS, SHOT_MASS, SHOT_SPEED, colors::VIOLET),
+        };
+
+        let mut ball = Ball::new(
+            position,
+            direction * speed,
+            radius,
+            mass,
+            color,
+            0.0,
+            0.0,
+            BallType::Projectil,
+        );
+        ball.effect = match self {
+            ShotType::Splitting => Some(ImpactEffect::Split { pieces: 3 }),
+            ShotType::GravityBomb => Some(ImpactEffect::Blast {
+                radius: 120.,
+                impulse: -4000.,
+            }),
+            _ => None,
+        };
+        return ball;
+    }
+}
+
+// Launches the projectiles of the player, holding the launch charges the shot.
+// Times are simulation seconds, so that replays launch the same shots.
+pub struct Weapon {
+    // Shots left, no limit when missing
+    pub ammo: Option<u32>,
+    pub shot: ShotType,
+    // Time the launch button was pressed at, while it is held
+    charge_start: Option<f64>,
+    last_shot_time: Option<f64>,
+    definition: WeaponDefinition,
+}
+
+impl Weapon {
+    pub fn new(definition: WeaponDefinition) -> Weapon {
+        return Weapon {
+            ammo: definition.ammo,
+            shot: definition
+                .shots
+                .first()
+                .copied()
+                .unwrap_or(ShotType::Normal),
+            charge_start: None,
+            last_shot_time: None,
+            definition,
+        };
+    }
+
+    pub fn reset(&mut self) {
+        self.ammo = self.definition.ammo;
+        self.charge_start = None;
+        self.last_shot_time = None;
+    }
+
+    pub fn get_shots(&self) -> &[ShotType] {
+        return &self.definition.shots;
+    }
+
+    pub fn is_charging(&self) -> bool {
+        return self.charge_start.is_some();
+    }
+
+    pub fn start_charge(&mut self, time: f64) {
+        self.charge_start = Some(time);
+    }
+
+    // From 0 when the launch was just pressed to 1 once fully charged
+    pub fn get_charge(&self, time: f64) -> f32 {
+        return match self.charge_start {
+            Some(start) => ((time - start) as f32 / self.definition.charge_time).clamp(0., 1.),
+            None => 0.,
+        };
+    }
+
+    // Charge of the shot being released
+    pub fn release_charge(&mut self, time: f64) -> f32 {
+        let charge = self.get_charge(time);
+        self.charge_start = None;
+        return charge;
+    }
+
+    // Part of the cooldown left, 0 once the weapon can fire again
+    pub fn get_cooldown(&self, time: f64) -> f32 {
+        return match self.last_shot_time {
+            Some(last) if self.definition.cooldown > 0. => {
+                (1. - (time - last) as f32 / self.definition.cooldown).max(0.)
+            }
+            _ => 0.,
+        };
+    }
+
+    pub fn has_ammo_for(&self, shot: ShotType) -> bool {
+        return self.ammo.map_or(true, |ammo| ammo >= shot.get_cost());
+    }
+
+    // Projectile of the shot if the weapon is ready and has ammo for it. A full charge multiplies
+    // its speed and mass by the ones of the weapon.
+    pub fn fire(
+        &mut self,
+        shot: ShotType,
+        position: Vec2,
+        direction: Vec2,
+        charge: f32,
+        time: f64,
+    ) -> Option<Ball> {
+        if self.get_cooldown(time) > 0.
+            || !self.has_ammo_for(shot)
+            || !self.definition.shots.contains(&shot)
+        {
+            return None;
+        }
+
+        self.ammo = self.ammo.map(|ammo| ammo - shot.get_cost());
+        self.last_shot_time = Some(time);
+
+        let mut ball = shot.create_projectile(position, direction);
+        let speed_factor = 1. + charge * (self.definition.max_charge_speed - 1.);
+        let velocity = ball.velocity * speed_factor;
+        ball.velocity = velocity;
+        ball.prev_position = position - velocity;
+        ball.mass = ball.mass * (1. + charge * (self.definition.max_charge_mass - 1.));
+        return Some(ball);
+    }
+}
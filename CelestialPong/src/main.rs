@@ -5,8 +5,13 @@ mod levels;
 mod simulation;
 mod visual;
 mod audio;
+mod controls;
+mod storage;
 
+use levels::campaign::Campaign;
+use levels::garden_level::GardenLevel;
 use levels::levels::{Level, LevelParameters};
+use levels::replay::Recording;
 use levels::title_screen::TitleScreen;
 
 use macroquad::{prelude::*, window};
@@ -25,15 +30,59 @@ fn window_config() -> Conf {
     }
 }
 
+fn get_arg(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    return args
+        .iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1).cloned());
+}
+
+// Seed can be forced with `--seed <value>`, otherwise it comes from the clock
+fn get_seed() -> u64 {
+    match get_arg("--seed").and_then(|seed| seed.parse().ok()) {
+        Some(seed) => return seed,
+        None => {}
+    }
+
+    return (miniquad::date::now() * 1000.) as u64;
+}
+
+// A garden session recorded in a file can be played back with `--replay <path>`
+fn get_first_level(level_parameters: LevelParameters) -> Level {
+    let replay = get_arg("--replay").map(|path| {
+        Recording::load(&path).and_then(|replay| {
+            Campaign::load()
+                .and_then(|campaign| campaign.load_stage(replay.stage))
+                .map(|definition| (definition, replay))
+        })
+    });
+
+    match replay {
+        Some(Ok((definition, replay))) => {
+            return Level::GardenLevel(GardenLevel::from_replay(
+                level_parameters,
+                definition,
+                replay,
+            ));
+        }
+        Some(Err(e)) => error!("Could not load replay {}", e),
+        None => {}
+    }
+
+    return Level::TitleScreen(TitleScreen::new(level_parameters));
+}
+
 #[macroquad::main(window_config)]
 async fn main() {
     let play_area_size = Vec2::new(window::screen_width(), window::screen_height());
     let level_parameters = LevelParameters {
         play_area_size,
         window_size: WINDOW_SIZE,
+        seed: get_seed(),
     };
 
-    let mut level = Level::TitleScreen(TitleScreen::new(level_parameters));
+    let mut level = get_first_level(level_parameters);
     level.init();
 
     let mut frame_per_frame: usize = 1;
@@ -56,6 +105,7 @@ async fn main() {
         fps[fps_index] = dt;
         fps_index = (fps_index + 1) % FPS_FRAMES;
 
+        level.read_frame_inputs();
         let mut next_level = Level::None;
         for _frame in 0..frame_per_frame {
             next_level = level.update();
@@ -1,14 +1,84 @@
 use macroquad::prelude::*;
+use serde::Deserialize;
 
-use crate::simulation::quad_tree;
+use crate::simulation::{capsule::distance_point_segment_squared, quad_tree};
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+// Balls moving more than this part of their radius in a step can jump over a small ball
+const FAST_MOTION_RATIO: f32 = 0.5;
+
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
 pub enum BallType {
     Body,
     Ball,
     BadBall,
     GoodBall,
     Projectil,
+    // Breaks into smaller balls on its first contact with another ball
+    Splitter,
+    // Pulls the balls around it
+    Magnet,
+    // Flower surviving a spike hit, losing its shield in the process
+    ShieldedBall,
+    // Pushes the balls around it away on its first contact with another ball
+    Bomb,
+}
+
+impl BallType {
+    // Flowers of the garden, with or without a shield
+    pub fn is_flower(&self) -> bool {
+        return self == &BallType::Ball || self == &BallType::ShieldedBall;
+    }
+
+    // Impact effect the balls of the type are created with
+    pub fn get_impact_effect(&self) -> Option<ImpactEffect> {
+        return match self {
+            BallType::Splitter => Some(ImpactEffect::Split { pieces: 2 }),
+            BallType::Bomb => Some(ImpactEffect::Blast {
+                radius: 100.,
+                impulse: 6000.,
+            }),
+            _ => None,
+        };
+    }
+}
+
+// How a ball bounces on others, missing values in level files are the default ones
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[serde(default)]
+pub struct BallMaterial {
+    // Part of the closing speed kept when bouncing, 1 bounces without losing energy
+    pub restitution: f32,
+    // Tangential impulse over normal impulse allowed at a contact, 0 lets balls slide freely
+    pub friction: f32,
+}
+
+impl Default for BallMaterial {
+    fn default() -> BallMaterial {
+        return BallMaterial {
+            restitution: 1.,
+            friction: 0.,
+        };
+    }
+}
+
+impl BallMaterial {
+    // Materials of two balls touching each other, the bounciest one wins
+    pub fn combine(&self, other: &BallMaterial) -> BallMaterial {
+        return BallMaterial {
+            restitution: self.restitution.max(other.restitution),
+            friction: (self.friction * other.friction).sqrt(),
+        };
+    }
+}
+
+// What a ball does on its first contact with another ball, it is removed in the process
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+pub enum ImpactEffect {
+    // Breaks into smaller balls sharing its mass, fanned around its velocity
+    Split { pieces: u32 },
+    // Pushes the balls within radius away, less the further they are. A negative impulse pulls
+    // them in instead.
+    Blast { radius: f32, impulse: f32 },
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -20,9 +90,12 @@ pub struct Ball {
     pub mass: f32,
     pub color: Color,
     pub rotation: f32,
+    // Angular velocity, in radians per second
     pub spin: f32,
     pub ball_type: BallType,
     pub double_radius: f32,
+    pub material: BallMaterial,
+    pub effect: Option<ImpactEffect>,
 }
 
 impl Ball {
@@ -47,6 +120,8 @@ impl Ball {
             spin,
             ball_type,
             double_radius: radius * 2.0,
+            material: BallMaterial::default(),
+            effect: ball_type.get_impact_effect(),
         }
     }
 
@@ -71,7 +146,7 @@ impl Ball {
                     },
                 );
 
-                if self.ball_type == BallType::Ball {
+                if self.ball_type.is_flower() {
                     let pos = self.position;
                     draw_circle(pos.x, pos.y, self.radius * 0.3, self.color);
                 }
@@ -83,7 +158,7 @@ impl Ball {
         }
     }
 
-    #[allow(dead_code)]
+    // Semi-implicit Euler
     pub fn update(&mut self, dt: f32, acc: Vec2) {
         self.velocity += acc * dt;
         let pos = self.position;
@@ -103,54 +178,62 @@ impl Ball {
         self.rotation = self.rotation + self.spin * dt;
     }
 
+    // End of a step computed by an integrator
+    pub fn move_to(&mut self, position: Vec2, velocity: Vec2, dt: f32) {
+        self.prev_position = self.position;
+        self.position = position;
+        self.velocity = velocity;
+
+        self.rotation = self.rotation + self.spin * dt;
+    }
+
     pub fn set_velocity(&mut self, velocity: Vec2, dt: f32) {
         self.prev_position = self.position + -velocity * dt;
         self.velocity = velocity;
     }
 
+    // Moves the ball without changing its velocity
+    pub fn shift(&mut self, offset: Vec2) {
+        self.position = self.position + offset;
+        self.prev_position = self.prev_position + offset;
+    }
+
     pub fn check_collision(&self, other: &Ball) -> bool {
         other.position.distance(self.position) <= other.radius + self.radius
     }
 
-    // Does collision effect for both self and the other object
-    // Based on https://www.vobarian.com/collisions/2dcollisions2.pdf
-    // The individual steps from the document are commented
-    pub fn collide(&mut self, other: &mut Ball, dt: f32) {
-        const HEAT_DISIPATION: f32 = 1.0;
-        let pos_diff = self.position - other.position;
-
-        // 1
-        let unit_normal = pos_diff.normalize();
-        let unit_tangent = Vec2::from((-unit_normal.y, unit_normal.x));
-
-        // 3
-        let v1n = self.velocity.dot(unit_normal);
-        let v1t = self.velocity.dot(unit_tangent);
-        let v2n = other.velocity.dot(unit_normal);
-        let v2t = other.velocity.dot(unit_tangent);
-
-        // 5
-        let new_v1n = (v1n * (self.mass - other.mass) + 2. * other.mass * v2n)
-            / (self.mass + other.mass)
-            * HEAT_DISIPATION;
-        let new_v2n = (v2n * (other.mass - self.mass) + 2. * self.mass * v1n)
-            / (self.mass + other.mass)
-            * HEAT_DISIPATION;
-
-        // 6
-        let final_v1n = new_v1n * unit_normal;
-        let final_v1t = v1t * unit_tangent;
-        let final_v2n = new_v2n * unit_normal;
-        let final_v2t = v2t * unit_tangent;
-
-        // 7
-        let final_v1 = final_v1n + final_v1t;
-        let final_v2 = final_v2n + final_v2t;
-
-        // The if statement makes them not get stuck in each other
-        if (self.velocity - other.velocity).dot(self.position - other.position) < 0. {
-            self.set_velocity(final_v1, dt);
-            other.set_velocity(final_v2, dt);
+    // Moment of inertia of a disc
+    pub fn get_inertia(&self) -> f32 {
+        return 0.5 * self.mass * self.radius * self.radius;
+    }
+
+    // Moved far enough in the last step to need sweeping, projectiles always do
+    pub fn is_fast(&self) -> bool {
+        let motion = self.position - self.prev_position;
+        let fast_motion = self.radius * FAST_MOTION_RATIO;
+        return self.ball_type == BallType::Projectil
+            || motion.length_squared() > fast_motion * fast_motion;
+    }
+
+    // Part of the last step at which the balls started touching, if they were apart before it.
+    // Both balls are taken as moving in a straight line from their previous position, so in the
+    // frame of the other ball this one sweeps a capsule.
+    pub fn get_time_of_impact(&self, other: &Ball) -> Option<f32> {
+        let start = self.prev_position - other.prev_position;
+        let end = self.position - other.position;
+        let reach = self.radius + other.radius;
+        if start.length_squared() <= reach * reach
+            || distance_point_segment_squared(start, end, Vec2::ZERO, Vec2::ZERO) > reach * reach
+        {
+            return None;
         }
+
+        // First time at which |start + motion * t| = reach
+        let motion = end - start;
+        let a = motion.length_squared();
+        let b = start.dot(motion);
+        let c = start.length_squared() - reach * reach;
+        let discriminant = (b * b - a * c).max(0.);
+        return Some(((-b - discriminant.sqrt()) / a).clamp(0., 1.));
     }
 }
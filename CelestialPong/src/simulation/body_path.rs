@@ -0,0 +1,88 @@
+use macroquad::math::Vec2;
+
+use crate::simulation::{ball::Ball, gravity::get_kepler_period};
+
+// Newton iterations used to solve Kepler's equation, enough for eccentricities below 0.9
+const KEPLER_ITERATIONS: usize = 8;
+
+// Rails a body moves on. Positions only depend on the world time, so they never drift and a
+// replay puts the bodies at the same place.
+#[derive(Clone, PartialEq, Debug)]
+pub enum BodyPath {
+    Static,
+    // Elliptic orbit with the parent body at its focus, the parent must come before in the body list.
+    // Angles are in radians, phase is the mean anomaly at time 0.
+    Kepler {
+        parent: usize,
+        semi_major_axis: f32,
+        eccentricity: f32,
+        periapsis_angle: f32,
+        phase: f32,
+        retrograde: bool,
+    },
+    // Closed loop going through the points, smoothed with a Catmull-Rom spline
+    Scripted {
+        points: Vec<Vec2>,
+        period: f32,
+    },
+}
+
+impl BodyPath {
+    // Position of the body at the given time, the bodies before it in the list already being placed.
+    // Static bodies stay where they are.
+    pub fn get_position(&self, time: f64, placed_bodies: &[Ball]) -> Option<Vec2> {
+        match self {
+            BodyPath::Static => return None,
+            BodyPath::Kepler {
+                parent,
+                semi_major_axis,
+                eccentricity,
+                periapsis_angle,
+                phase,
+                retrograde,
+            } => {
+                let parent = placed_bodies[*parent];
+                let period = get_kepler_period(*semi_major_axis, parent.mass) as f64;
+                let turns = (time / period).rem_euclid(1.) as f32;
+                let mean_anomaly = phase + turns * std::f32::consts::TAU;
+
+                // Kepler's equation : mean_anomaly = anomaly - eccentricity * sin(anomaly)
+                let mut anomaly = mean_anomaly;
+                for _ in 0..KEPLER_ITERATIONS {
+                    anomaly = anomaly
+                        - (anomaly - eccentricity * anomaly.sin() - mean_anomaly)
+                            / (1. - eccentricity * anomaly.cos());
+                }
+
+                let semi_minor_axis = semi_major_axis * (1. - eccentricity * eccentricity).sqrt();
+                let mut offset = Vec2::new(
+                    semi_major_axis * (anomaly.cos() - eccentricity),
+                    semi_minor_axis * anomaly.sin(),
+                );
+                if *retrograde {
+                    offset.y = -offset.y;
+                }
+
+                return Some(parent.position + Vec2::from_angle(*periapsis_angle).rotate(offset));
+            }
+            BodyPath::Scripted { points, period } => {
+                let count = points.len();
+                let progress = (time / *period as f64).rem_euclid(1.) as f32 * count as f32;
+                let segment = (progress as usize).min(count - 1);
+                let t = progress - segment as f32;
+
+                let p0 = points[(segment + count - 1) % count];
+                let p1 = points[segment];
+                let p2 = points[(segment + 1) % count];
+                let p3 = points[(segment + 2) % count];
+
+                return Some(
+                    0.5 * (p1 * 2.
+                        + (p2 - p0) * t
+                        + (p0 * 2. - p1 * 5. + p2 * 4. - p3) * t * t
+                        + (p1 * 3. - p0 - p2 * 3. + p3) * t * t * t),
+                );
+            }
+        }
+    }
+}
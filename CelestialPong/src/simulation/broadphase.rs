@@ -0,0 +1,209 @@
+use macroquad::math::Vec2;
+use serde::Deserialize;
+
+use crate::simulation::{
+    ball::Ball,
+    hash_grid::HashGrid,
+    quad_tree::{sort_by_distance, QuadTree, QuadTreeEntry, Rect},
+    sweep_and_prune::SweepAndPrune,
+};
+
+// Half size of the first square searched for the k nearest entries, doubled until enough are found
+#[allow(dead_code)]
+const K_NEAREST_FIRST_REACH: f32 = 32.;
+
+// Finds the balls that may touch, before the exact collision checks.
+// Every backend must give the same candidates for the same balls, only their speed differs.
+pub trait Broadphase {
+    // Brings the backend up to date with the balls, payloads being their index
+    fn update(&mut self, balls: &[Ball]);
+
+    // Number of entries
+    #[allow(dead_code)]
+    fn len(&self) -> usize;
+
+    // Entries whose position is in the query area
+    fn query_entries(&self, query: &Rect, result: &mut Vec<QuadTreeEntry>);
+
+    // Closest entry to the position, the one with the lowest payload on a tie
+    fn nearest(&self, position: Vec2) -> Option<QuadTreeEntry>;
+
+    // Entries at most radius away from the center, the closest first. The result is cleared before.
+    fn query_circle(&self, center: Vec2, radius: f32, result: &mut Vec<QuadTreeEntry>) {
+        result.clear();
+        // Query areas do not contain their right and bottom edges
+        let size = radius * 2. + 1.;
+        self.query_entries(&Rect::new(center.x, center.y, size, size), result);
+        result.retain(|entry| entry.position.distance_squared(center) <= radius * radius);
+        sort_by_distance(result, center);
+    }
+
+    // The k entries closest to the position, the closest first. The result is cleared before.
+    // Squares growing around the position are searched until k entries are inside the circle they
+    // contain, entries out of it may be closer than the ones in the corners.
+    #[allow(dead_code)]
+    fn k_nearest(&self, position: Vec2, k: usize, result: &mut Vec<QuadTreeEntry>) {
+        result.clear();
+        if k == 0 {
+            return;
+        }
+
+        let mut reach = K_NEAREST_FIRST_REACH;
+        loop {
+            result.clear();
+            self.query_entries(
+                &Rect::new(position.x, position.y, reach * 2., reach * 2.),
+                result,
+            );
+            if result.len() < self.len() {
+                result.retain(|entry| entry.position.distance_squared(position) <= reach * reach);
+                if result.len() < k {
+                    reach = reach * 2.;
+                    continue;
+                }
+            }
+
+            sort_by_distance(result, position);
+            result.truncate(k);
+            return;
+        }
+    }
+
+    // Pairs of balls whose collision areas overlap, in any order and possibly repeated
+    fn find_pairs(&mut self, balls: &[Ball], pairs: &mut Vec<(usize, usize)>);
+
+    // Pairs of balls whose collision areas overlap, the lowest index first, sorted and unique
+    fn get_candidate_pairs(&mut self, balls: &[Ball], pairs: &mut Vec<(usize, usize)>) {
+        pairs.clear();
+        self.find_pairs(balls, pairs);
+        for pair in pairs.iter_mut() {
+            if pair.0 > pair.1 {
+                *pair = (pair.1, pair.0);
+            }
+        }
+        pairs.sort_unstable();
+        pairs.dedup();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default, Deserialize)]
+pub enum BroadphaseKind {
+    #[default]
+    QuadTree,
+    HashGrid,
+    SweepAndPrune,
+}
+
+impl BroadphaseKind {
+    pub fn next(&self) -> BroadphaseKind {
+        return match self {
+            BroadphaseKind::QuadTree => BroadphaseKind::HashGrid,
+            BroadphaseKind::HashGrid => BroadphaseKind::SweepAndPrune,
+            BroadphaseKind::SweepAndPrune => BroadphaseKind::QuadTree,
+        };
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        return match self {
+            BroadphaseKind::QuadTree => "quad tree",
+            BroadphaseKind::HashGrid => "hash grid",
+            BroadphaseKind::SweepAndPrune => "sweep and prune",
+        };
+    }
+
+    // Area is the one covered by the quad tree, the other backends are unbounded
+    pub fn create(&self, area: Rect) -> Box<dyn Broadphase> {
+        return match self {
+            BroadphaseKind::QuadTree => Box::new(QuadTreeBroadphase::new(area)),
+            BroadphaseKind::HashGrid => Box::new(HashGrid::new()),
+            BroadphaseKind::SweepAndPrune => Box::new(SweepAndPrune::new()),
+        };
+    }
+}
+
+// Quad tree kept from a step to the next, balls being moved from where they were to their
+// current position
+pub struct QuadTreeBroadphase {
+    pub tree: QuadTree,
+    // Position of each ball in the tree
+    positions: Vec<Vec2>,
+    max_radius: f32,
+    // Entries around the ball whose pairs are being found, kept from a call to the next
+    near_balls: Vec<QuadTreeEntry>,
+}
+
+impl QuadTreeBroadphase {
+    pub fn new(area: Rect) -> QuadTreeBroadphase {
+        return QuadTreeBroadphase {
+            tree: QuadTree::new(area),
+            positions: Vec::new(),
+            max_radius: 0.,
+            near_balls: Vec::new(),
+        };
+    }
+}
+
+impl Broadphase for QuadTreeBroadphase {
+    fn update(&mut self, balls: &[Ball]) {
+        for index in (balls.len()..self.positions.len()).rev() {
+            self.tree.remove(self.positions[index], index);
+        }
+        self.positions.truncate(balls.len());
+
+        for (index, ball) in balls.iter().enumerate() {
+            let entry = QuadTreeEntry::new(ball.position, index, ball.mass);
+            match self.positions.get_mut(index) {
+                Some(position) => {
+                    self.tree.move_entry(*position, entry);
+                    *position = ball.position;
+                }
+                None => {
+                    self.tree.add(entry);
+                    self.positions.push(ball.position);
+                }
+            }
+        }
+
+        self.max_radius = balls.iter().map(|ball| ball.radius).fold(0., f32::max);
+    }
+
+    fn len(&self) -> usize {
+        return self.positions.len();
+    }
+
+    fn query_entries(&self, query: &Rect, result: &mut Vec<QuadTreeEntry>) {
+        self.tree.query_entries(query, result);
+    }
+
+    fn nearest(&self, position: Vec2) -> Option<QuadTreeEntry> {
+        return self.tree.nearest(position);
+    }
+
+    fn query_circle(&self, center: Vec2, radius: f32, result: &mut Vec<QuadTreeEntry>) {
+        self.tree.query_circle(center, radius, result);
+    }
+
+    fn k_nearest(&self, position: Vec2, k: usize, result: &mut Vec<QuadTreeEntry>) {
+        self.tree.k_nearest(position, k, result);
+    }
+
+    fn find_pairs(&mut self, balls: &[Ball], pairs: &mut Vec<(usize, usize)>) {
+        for (index, ball) in balls.iter().enumerate() {
+            // Other areas can reach up to twice the biggest radius past this one, with a margin
+            // as query areas do not contain their right and bottom edges
+            let area = ball.get_collision_area();
+            let reach = (area.half_width + self.max_radius * 2. + 1.) * 2.;
+            self.near_balls.clear();
+            self.tree.query_entries(
+                &Rect::new(area.x, area.y, reach, reach),
+                &mut self.near_balls,
+            );
+
+            for near in &self.near_balls {
+                if near.payload > index && area.overlap(&balls[near.payload].get_collision_area()) {
+                    pairs.push((index, near.payload));
+                }
+            }
+        }
+    }
+}
@@ -1,19 +1,34 @@
 use macroquad::prelude::*;
 
-#[derive(Clone, Copy, Debug)]
-struct Capsule {
-    p1: Vec2,
-    p2: Vec2,
-    radius: f32,
-    color: Color,
+use crate::simulation::{
+    ball::{Ball, BallMaterial, BallType},
+    quad_tree,
+};
+
+// Halvings of the step used to find where a fast ball first touched a capsule
+const TIME_OF_IMPACT_ITERATIONS: usize = 12;
+
+// Segment with a thickness, the balls bounce on it without moving it.
+// Used for barriers and paddles, which are moved by the level.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Capsule {
+    pub p1: Vec2,
+    pub p2: Vec2,
+    pub radius: f32,
+    pub color: Color,
+    // Velocity of the capsule during the last step, given to the balls bouncing on it
+    pub velocity: Vec2,
+    pub material: BallMaterial,
+    // Pong style bounce, balls are sent away from the capsule turned toward the end they hit by
+    // up to this angle in radians. Plain bounces when 0.
+    pub deflection: f32,
 }
 
 // From : https://arrowinmyknee.com/2021/03/15/some-math-about-capsule-collision/
 // Computes closest points C1 and C2 of S1(s)=P1+s*(Q1-P1) and
 // S2(t)=P2+t*(Q2-P2), returning s and t. Function result is squared
 // distance between between S1(s) and S2(t)
-#[allow(dead_code)]
-fn distance_point_segment_squared(p1: Vec2, q1: Vec2, p2: Vec2, q2: Vec2) -> f32 {
+pub fn distance_point_segment_squared(p1: Vec2, q1: Vec2, p2: Vec2, q2: Vec2) -> f32 {
     let d1 = q1 - p1; // Direction vector of segment S1
     let d2 = q2 - p2; // Direction vector of segment S2
     let r = p1 - p2;
@@ -75,21 +90,143 @@ fn distance_point_segment_squared(p1: Vec2, q1: Vec2, p2: Vec2, q2: Vec2) -> f32
 }
 
 impl Capsule {
-    #[allow(dead_code)]
     pub fn new(p1: Vec2, p2: Vec2, r: f32, color: Color) -> Capsule {
         return Capsule {
             p1: p1,
             p2: p2,
             radius: r,
             color: color,
+            velocity: Vec2::ZERO,
+            material: BallMaterial::default(),
+            deflection: 0.,
         };
     }
 
-    #[allow(dead_code)]
+    // Moves the capsule to its new place, its velocity is the move over the step
+    pub fn move_to(&mut self, p1: Vec2, p2: Vec2, dt: f32) {
+        self.velocity = (p1 + p2 - self.p1 - self.p2) / 2. / dt;
+        self.p1 = p1;
+        self.p2 = p2;
+    }
+
+    // Bounding box of the capsule grown by margin on every side
+    pub fn get_collision_area(&self, margin: f32) -> quad_tree::Rect {
+        let reach = self.radius + margin;
+        let min = self.p1.min(self.p2) - Vec2::splat(reach);
+        let max = self.p1.max(self.p2) + Vec2::splat(reach);
+        let center = (min + max) / 2.;
+        return quad_tree::Rect::new(center.x, center.y, max.x - min.x, max.y - min.y);
+    }
+
+    pub fn get_closest_point(&self, position: Vec2) -> Vec2 {
+        let segment = self.p2 - self.p1;
+        let length_squared = segment.length_squared();
+        if length_squared <= f32::EPSILON {
+            return self.p1;
+        }
+        let t = ((position - self.p1).dot(segment) / length_squared).clamp(0., 1.);
+        return self.p1 + segment * t;
+    }
+
+    // Where the closest point to the position is along the capsule, from -1 at p1 to 1 at p2
+    pub fn get_hit_offset(&self, position: Vec2) -> f32 {
+        let segment = self.p2 - self.p1;
+        let length_squared = segment.length_squared();
+        if length_squared <= f32::EPSILON {
+            return 0.;
+        }
+        let t = ((position - self.p1).dot(segment) / length_squared).clamp(0., 1.);
+        return t * 2. - 1.;
+    }
+
+    pub fn check_collision(&self, ball: &Ball) -> bool {
+        let reach = self.radius + ball.radius;
+        return (self.get_closest_point(ball.position) - ball.position).length_squared()
+            <= reach * reach;
+    }
+
+    // Part of the last step at which the ball started touching the capsule, if it was apart
+    // before it. The capsule is taken as still during the step.
+    pub fn get_time_of_impact(&self, ball: &Ball) -> Option<f32> {
+        let reach = self.radius + ball.radius;
+        let start = Ball {
+            position: ball.prev_position,
+            ..*ball
+        };
+        if self.check_collision(&start)
+            || distance_point_segment_squared(ball.prev_position, ball.position, self.p1, self.p2)
+                > reach * reach
+        {
+            return None;
+        }
+
+        // The ball has not touched the capsule yet at min and has at max. Its path so far is
+        // checked rather than its position, a fast ball may already be through at the middle.
+        let (mut min, mut max) = (0., 1.);
+        for _ in 0..TIME_OF_IMPACT_ITERATIONS {
+            let middle = (min + max) / 2.;
+            let position = ball.prev_position.lerp(ball.position, middle);
+            match distance_point_segment_squared(ball.prev_position, position, self.p1, self.p2)
+                <= reach * reach
+            {
+                true => max = middle,
+                false => min = middle,
+            }
+        }
+        return Some(max);
+    }
+
+    // The part of the capsule the ball touches, seen as a body the ball can bounce on
+    pub fn get_contact_body(&self, position: Vec2) -> Ball {
+        let mut body = Ball::new(
+            self.get_closest_point(position),
+            Vec2::ZERO,
+            self.radius,
+            f32::INFINITY,
+            self.color,
+            0.,
+            0.,
+            BallType::Body,
+        );
+        body.velocity = self.velocity;
+        body.material = self.material;
+        return body;
+    }
+
+    // Sends a ball that bounced on the capsule straight away from it, turned toward the end it hit
+    // by up to deflection, keeping its speed
+    pub fn deflect(&self, ball: &mut Ball, dt: f32) {
+        let axis = (self.p2 - self.p1).try_normalize().unwrap_or(Vec2::X);
+        let normal = match axis
+            .perp()
+            .dot(ball.position - self.get_closest_point(ball.position))
+            < 0.
+        {
+            true => -axis.perp(),
+            false => axis.perp(),
+        };
+        let angle = self.get_hit_offset(ball.position) * self.deflection;
+        let direction = normal * angle.cos() + axis * angle.sin();
+        ball.set_velocity(direction * ball.velocity.length(), dt);
+    }
+
     pub fn draw(&self) {
+        draw_circle(self.p1.x, self.p1.y, self.radius, self.color);
+        draw_circle(self.p2.x, self.p2.y, self.radius, self.color);
+        draw_line(
+            self.p1.x,
+            self.p1.y,
+            self.p2.x,
+            self.p2.y,
+            self.radius * 2.,
+            self.color,
+        );
+    }
+
+    pub fn debug_draw(&self) {
         draw_circle_lines(self.p1.x, self.p1.y, self.radius, 2., self.color);
         draw_circle_lines(self.p2.x, self.p2.y, self.radius, 2., self.color);
-        let dir = (self.p2 - self.p1).normalize();
+        let dir = (self.p2 - self.p1).normalize_or_zero();
         let cr = vec2(dir.y, -dir.x) * self.radius;
         draw_line(
             self.p1.x + cr.x,
@@ -107,6 +244,7 @@ impl Capsule {
             2.,
             self.color,
         );
+        self.get_collision_area(0.).debug_draw(1., self.color);
     }
 
     #[allow(dead_code)]
@@ -0,0 +1,219 @@
+use macroquad::math::Vec2;
+
+use crate::simulation::ball::{Ball, BallMaterial};
+
+// Passes over every contact, piles of balls need a few to pass pushes along
+const VELOCITY_ITERATIONS: usize = 8;
+// Passes pushing overlapping balls apart
+const POSITION_ITERATIONS: usize = 3;
+// Share of the overlap removed by each position pass, removing all of it at once overshoots when
+// a ball touches several others
+const POSITION_CORRECTION: f32 = 0.4;
+// Overlap left alone, so that balls resting on each other keep touching instead of jittering
+const PENETRATION_SLOP: f32 = 0.05;
+// Closing speed under which balls do not bounce back, so that piles settle
+const RESTING_SPEED: f32 = 5.;
+
+// Two balls touching each other
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Contact {
+    pub first: usize,
+    pub second: usize,
+    // From the first ball to the second
+    pub normal: Vec2,
+    material: BallMaterial,
+    // Speed along the normal the balls should part at
+    target_speed: f32,
+    // Impulses applied so far in this step, along the normal and along the tangent
+    impulse: f32,
+    tangent_impulse: f32,
+}
+
+// Impulse stopping the sliding of b on a at their contact point, given the normal impulse
+// between them. The tangent is the normal turned a quarter left. A spinning ball slides against
+// the other one even when their centers do not, so friction turns spin into motion and back.
+// The impulse is returned for b, a gets the opposite one.
+fn get_friction_impulse(a: &Ball, b: &Ball, normal: Vec2, inverse_mass: f32) -> f32 {
+    let tangent = normal.perp();
+    let sliding_speed =
+        (b.velocity - a.velocity).dot(tangent) - a.spin * a.radius - b.spin * b.radius;
+    // Pushing on the edge of a disc spins it, which takes as much as moving it twice over
+    return -sliding_speed / (inverse_mass * 3.);
+}
+
+// Impulse along the tangent pushes the velocity and turns both balls the same way
+fn apply_friction_impulse(a: &mut Ball, b: &mut Ball, normal: Vec2, impulse: f32) {
+    let tangent = normal.perp();
+    a.velocity -= tangent * (impulse / a.mass);
+    b.velocity += tangent * (impulse / b.mass);
+    a.spin -= a.radius * impulse / a.get_inertia();
+    b.spin -= b.radius * impulse / b.get_inertia();
+}
+
+// Iterative impulse solver, every contact of a step is solved together.
+// Velocities are fixed first, one contact after the other with the velocities left by the
+// previous ones until they settle, then the remaining overlaps are pushed apart.
+// How balls bounce and rub on each other comes from their materials.
+pub struct ContactSolver {
+    pub velocity_iterations: usize,
+    pub position_iterations: usize,
+    contacts: Vec<Contact>,
+}
+
+impl ContactSolver {
+    pub fn new() -> ContactSolver {
+        return ContactSolver {
+            velocity_iterations: VELOCITY_ITERATIONS,
+            position_iterations: POSITION_ITERATIONS,
+            contacts: Vec::new(),
+        };
+    }
+
+    pub fn get_contacts(&self) -> &[Contact] {
+        return &self.contacts;
+    }
+
+    // Keeps the candidate pairs whose balls touch
+    pub fn find_contacts(&mut self, balls: &[Ball], pairs: &[(usize, usize)]) {
+        self.contacts.clear();
+        for (first, second) in pairs.iter().copied() {
+            let (a, b) = (&balls[first], &balls[second]);
+            if !a.check_collision(b) {
+                continue;
+            }
+
+            // Balls at the exact same place are pushed apart along x
+            let normal = (b.position - a.position).try_normalize().unwrap_or(Vec2::X);
+            let material = a.material.combine(&b.material);
+            let closing_speed = (a.velocity - b.velocity).dot(normal);
+            let target_speed = match closing_speed > RESTING_SPEED {
+                true => closing_speed * material.restitution,
+                false => 0.,
+            };
+
+            self.contacts.push(Contact {
+                first,
+                second,
+                normal,
+                material,
+                target_speed,
+                impulse: 0.,
+                tangent_impulse: 0.,
+            });
+        }
+    }
+
+    pub fn solve(&mut self, balls: &mut [Ball], dt: f32) {
+        if self.contacts.is_empty() {
+            return;
+        }
+
+        self.solve_velocities(balls, dt);
+        self.solve_positions(balls);
+    }
+
+    fn solve_velocities(&mut self, balls: &mut [Ball], dt: f32) {
+        for _ in 0..self.velocity_iterations {
+            for contact in self.contacts.iter_mut() {
+                let (a, b) = (balls[contact.first], balls[contact.second]);
+                let inverse_mass = 1. / a.mass + 1. / b.mass;
+                let separating_speed = (b.velocity - a.velocity).dot(contact.normal);
+
+                // The total impulse of the step can push the balls apart but never pull them
+                // together, a contact can give back what it pushed too much in an earlier pass
+                let impulse = (contact.target_speed - separating_speed) / inverse_mass;
+                let total_impulse = (contact.impulse + impulse).max(0.);
+                let impulse = contact.normal * (total_impulse - contact.impulse);
+                contact.impulse = total_impulse;
+
+                balls[contact.first].velocity -= impulse / a.mass;
+                balls[contact.second].velocity += impulse / b.mass;
+
+                // Friction can not hold more than the normal impulse allows
+                if contact.material.friction > 0. {
+                    let (a, b) = (balls[contact.first], balls[contact.second]);
+                    let max_impulse = contact.impulse * contact.material.friction;
+                    let impulse = get_friction_impulse(&a, &b, contact.normal, inverse_mass);
+                    let total_impulse =
+                        (contact.tangent_impulse + impulse).clamp(-max_impulse, max_impulse);
+                    let impulse = total_impulse - contact.tangent_impulse;
+                    contact.tangent_impulse = total_impulse;
+
+                    let (left, right) = balls.split_at_mut(contact.second);
+                    apply_friction_impulse(
+                        &mut left[contact.first],
+                        &mut right[0],
+                        contact.normal,
+                        impulse,
+                    );
+                }
+            }
+        }
+
+        // Verlet integration reads the velocity from the previous position
+        for contact in &self.contacts {
+            for index in [contact.first, contact.second] {
+                let velocity = balls[index].velocity;
+                balls[index].set_velocity(velocity, dt);
+            }
+        }
+    }
+
+    fn solve_positions(&self, balls: &mut [Ball]) {
+        for _ in 0..self.position_iterations {
+            for contact in &self.contacts {
+                let (a, b) = (balls[contact.first], balls[contact.second]);
+                let offset = b.position - a.position;
+                let distance = offset.length();
+                let overlap = a.radius + b.radius - distance;
+                if overlap <= PENETRATION_SLOP {
+                    continue;
+                }
+
+                let normal = match distance > 0. {
+                    true => offset / distance,
+                    false => contact.normal,
+                };
+                // Lighter balls move more
+                let inverse_mass = 1. / a.mass + 1. / b.mass;
+                let correction =
+                    normal * ((overlap - PENETRATION_SLOP) * POSITION_CORRECTION / inverse_mass);
+
+                balls[contact.first].shift(-correction / a.mass);
+                balls[contact.second].shift(correction / b.mass);
+            }
+        }
+    }
+}
+
+// Bounces a ball touching a body, which is too heavy to be pushed back. The ball is moved out of
+// the body and the part of its speed going into the body is reflected, scaled by bounciness.
+// Returns whether the ball was going into the body.
+pub fn bounce_on_body(ball: &mut Ball, body: &Ball, bounciness: f32, dt: f32) -> bool {
+    let normal = (ball.position - body.position)
+        .try_normalize()
+        .unwrap_or(Vec2::X);
+    ball.shift(body.position + normal * (body.radius + ball.radius) - ball.position);
+
+    let closing_speed = (body.velocity - ball.velocity).dot(normal);
+    if closing_speed <= 0. {
+        return false;
+    }
+
+    let impulse = closing_speed * (1. + bounciness) * ball.mass;
+    ball.velocity += normal * (impulse / ball.mass);
+
+    if ball.material.friction > 0. {
+        // Body spin is only drawn, its surface is taken as still
+        let still_body = Ball { spin: 0., ..*body };
+        let max_impulse = impulse * ball.material.friction;
+        let tangent_impulse = get_friction_impulse(&still_body, ball, normal, 1. / ball.mass)
+            .clamp(-max_impulse, max_impulse);
+        ball.velocity += normal.perp() * (tangent_impulse / ball.mass);
+        ball.spin -= ball.radius * tangent_impulse / ball.get_inertia();
+    }
+
+    let velocity = ball.velocity;
+    ball.set_velocity(velocity, dt);
+    return true;
+}
@@ -4,16 +4,64 @@ use macroquad::{
     shapes::draw_line,
 };
 
-use crate::simulation::ball::Ball;
+use crate::simulation::ball::{Ball, BallType};
 use crate::SIMULATION_DT;
 
 const GRAVITY: f32 = 1.;
+// Added to squared distances between balls, so that two balls nearly at the same place do not
+// fling each other away
+const BALL_GRAVITY_SOFTENING: f32 = 16.;
 
 pub fn get_gravity_force(ball: &Ball, body: &Ball) -> Vec2 {
     let delta = body.position - ball.position;
     return delta.normalize() * (body.mass) / delta.length().powf(2.) * GRAVITY;
 }
 
+// Pull of a point mass, softened for the ball to ball gravity
+pub fn get_point_gravity_force(position: Vec2, mass_position: Vec2, mass: f32) -> Vec2 {
+    let delta = mass_position - position;
+    let distance_squared = delta.length_squared() + BALL_GRAVITY_SOFTENING;
+    return delta * (mass * GRAVITY / (distance_squared * distance_squared.sqrt()));
+}
+
+// Potential energy per unit of mass of a ball pulled by a body
+pub fn get_gravity_potential(ball: &Ball, body: &Ball) -> f32 {
+    return -body.mass * GRAVITY / (body.position - ball.position).length();
+}
+
+pub fn get_point_gravity_potential(position: Vec2, mass_position: Vec2, mass: f32) -> f32 {
+    let distance_squared = (mass_position - position).length_squared() + BALL_GRAVITY_SOFTENING;
+    return -mass * GRAVITY / distance_squared.sqrt();
+}
+
+// Body standing for a group of bodies seen from afar : their total mass moving with their barycenter
+pub fn get_barycenter_body(bodies: &[Ball]) -> Ball {
+    let mass: f32 = bodies.iter().map(|body| body.mass).sum();
+    let position = bodies
+        .iter()
+        .fold(Vec2::ZERO, |sum, body| sum + body.position * body.mass)
+        / mass;
+    let velocity = bodies
+        .iter()
+        .fold(Vec2::ZERO, |sum, body| sum + body.velocity * body.mass)
+        / mass;
+    let radius = bodies
+        .iter()
+        .map(|body| (body.position - position).length() + body.radius)
+        .fold(0., f32::max);
+
+    return Ball::new(
+        position,
+        velocity,
+        radius,
+        mass,
+        colors::WHITE,
+        0.0,
+        0.0,
+        BallType::Body,
+    );
+}
+
 #[allow(dead_code)]
 pub fn get_gravity_radius_over_threshold(mass: f32, threshold: f32) -> f32 {
     return (mass * GRAVITY / threshold).sqrt();
@@ -26,6 +74,11 @@ pub fn get_orbital_velocity(b1: &Ball, body: &Ball) -> Vec2 {
     return Vec2::from((delta.y, -delta.x)).normalize() * speed;
 }
 
+// Time in seconds to go around a body of that mass on an orbit of that semi major axis
+pub fn get_kepler_period(semi_major_axis: f32, mass: f32) -> f32 {
+    return std::f32::consts::TAU * (semi_major_axis.powf(3.) / (GRAVITY * mass)).sqrt();
+}
+
 #[allow(dead_code)]
 pub fn get_orbital_period(ball: &Ball, body: &Ball) -> f32 {
     let delta = body.position - ball.position;
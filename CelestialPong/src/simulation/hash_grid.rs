@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use macroquad::math::Vec2;
+
+use crate::simulation::{
+    ball::Ball,
+    broadphase::Broadphase,
+    quad_tree::{keep_nearest, QuadTreeEntry, Rect},
+};
+
+// Uniform grid of cells as wide as the biggest collision area, only the cells holding balls are
+// stored. Each ball is in the cell of its position.
+pub struct HashGrid {
+    cell_size: f32,
+    max_radius: f32,
+    cells: HashMap<(i32, i32), Vec<QuadTreeEntry>>,
+    count: usize,
+}
+
+impl HashGrid {
+    pub fn new() -> HashGrid {
+        return HashGrid {
+            cell_size: 1.,
+            max_radius: 0.,
+            cells: HashMap::new(),
+            count: 0,
+        };
+    }
+
+    fn get_cell(&self, x: f32, y: f32) -> (i32, i32) {
+        return (
+            (x / self.cell_size).floor() as i32,
+            (y / self.cell_size).floor() as i32,
+        );
+    }
+
+    // Entries of every cell overlapping the area
+    fn for_each_entry(&self, area: &Rect, mut action: impl FnMut(&QuadTreeEntry)) {
+        let (left, up) = self.get_cell(area.left, area.up);
+        let (right, down) = self.get_cell(area.right, area.down);
+        for y in up..=down {
+            for x in left..=right {
+                match self.cells.get(&(x, y)) {
+                    Some(entries) => entries.iter().for_each(&mut action),
+                    None => {}
+                }
+            }
+        }
+    }
+}
+
+impl Broadphase for HashGrid {
+    fn update(&mut self, balls: &[Ball]) {
+        self.max_radius = balls.iter().map(|ball| ball.radius).fold(0., f32::max);
+        self.cell_size = (self.max_radius * 4.).max(1.);
+
+        self.cells.clear();
+        self.count = balls.len();
+        for (index, ball) in balls.iter().enumerate() {
+            let cell = self.get_cell(ball.position.x, ball.position.y);
+            self.cells.entry(cell).or_default().push(QuadTreeEntry::new(
+                ball.position,
+                index,
+                ball.mass,
+            ));
+        }
+    }
+
+    fn len(&self) -> usize {
+        return self.count;
+    }
+
+    fn query_entries(&self, query: &Rect, result: &mut Vec<QuadTreeEntry>) {
+        self.for_each_entry(query, |entry| {
+            if query.contains(entry.position) {
+                result.push(*entry);
+            }
+        });
+    }
+
+    // Rings of cells around the one of the position, until the next ring can only hold farther
+    // entries. Rings bigger than the number of stored cells are not worth walking, every stored
+    // cell is then checked instead.
+    fn nearest(&self, position: Vec2) -> Option<QuadTreeEntry> {
+        let mut nearest = None;
+        let (center_x, center_y) = self.get_cell(position.x, position.y);
+        let mut seen = 0;
+        let mut ring: i32 = 0;
+        while seen < self.count {
+            if ring as usize * 8 > self.cells.len() {
+                for entry in self.cells.values().flatten() {
+                    keep_nearest(position, *entry, &mut nearest);
+                }
+                return nearest;
+            }
+
+            for y in center_y - ring..=center_y + ring {
+                for x in center_x - ring..=center_x + ring {
+                    if (x - center_x).abs() != ring && (y - center_y).abs() != ring {
+                        continue;
+                    }
+                    match self.cells.get(&(x, y)) {
+                        Some(entries) => {
+                            seen = seen + entries.len();
+                            for entry in entries {
+                                keep_nearest(position, *entry, &mut nearest);
+                            }
+                        }
+                        None => {}
+                    }
+                }
+            }
+
+            // Cells of the next ring are at least this far
+            let reach = ring as f32 * self.cell_size;
+            match nearest {
+                Some(closest) if closest.position.distance_squared(position) <= reach * reach => {
+                    return nearest;
+                }
+                _ => {}
+            }
+            ring = ring + 1;
+        }
+        return nearest;
+    }
+
+    fn find_pairs(&mut self, balls: &[Ball], pairs: &mut Vec<(usize, usize)>) {
+        for (index, ball) in balls.iter().enumerate() {
+            // Other areas can reach up to twice the biggest radius past this one
+            let area = ball.get_collision_area();
+            let reach = (area.half_width + self.max_radius * 2.) * 2.;
+            self.for_each_entry(&Rect::new(area.x, area.y, reach, reach), |near| {
+                if near.payload > index && area.overlap(&balls[near.payload].get_collision_area()) {
+                    pairs.push((index, near.payload));
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use macroquad::math::Vec2;
+
+use crate::simulation::ball::Ball;
+
+// How balls are moved forward in time, chosen per world.
+// Every integrator leaves prev_position at the position before the step, so that they can be
+// switched while the world is running.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Integrator {
+    // Position Verlet, the velocity is derived from the last two positions
+    #[default]
+    Verlet,
+    VelocityVerlet,
+    SemiImplicitEuler,
+    RungeKutta4,
+}
+
+impl Integrator {
+    pub fn next(&self) -> Integrator {
+        return match self {
+            Integrator::Verlet => Integrator::VelocityVerlet,
+            Integrator::VelocityVerlet => Integrator::SemiImplicitEuler,
+            Integrator::SemiImplicitEuler => Integrator::RungeKutta4,
+            Integrator::RungeKutta4 => Integrator::Verlet,
+        };
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        return match self {
+            Integrator::Verlet => "Verlet",
+            Integrator::VelocityVerlet => "velocity Verlet",
+            Integrator::SemiImplicitEuler => "semi-implicit Euler",
+            Integrator::RungeKutta4 => "RK4",
+        };
+    }
+
+    // Moves the ball by dt. acceleration gives the acceleration of the ball for a position and a
+    // velocity, the other balls staying where they were at the start of the step.
+    pub fn step(&self, ball: &mut Ball, dt: f32, acceleration: impl Fn(Vec2, Vec2) -> Vec2) {
+        match self {
+            Integrator::Verlet => {
+                ball.update_verlet(dt, acceleration(ball.position, ball.velocity));
+            }
+            Integrator::SemiImplicitEuler => {
+                ball.update(dt, acceleration(ball.position, ball.velocity));
+            }
+            Integrator::VelocityVerlet => {
+                let start_acceleration = acceleration(ball.position, ball.velocity);
+                let position =
+                    ball.position + ball.velocity * dt + start_acceleration * (0.5 * dt * dt);
+                // The orbit trap depends on the velocity, guessed from the start acceleration
+                let end_acceleration =
+                    acceleration(position, ball.velocity + start_acceleration * dt);
+                let velocity = ball.velocity + (start_acceleration + end_acceleration) * (0.5 * dt);
+                ball.move_to(position, velocity, dt);
+            }
+            Integrator::RungeKutta4 => {
+                let (p1, v1) = (ball.position, ball.velocity);
+                let a1 = acceleration(p1, v1);
+
+                let (p2, v2) = (p1 + v1 * (0.5 * dt), v1 + a1 * (0.5 * dt));
+                let a2 = acceleration(p2, v2);
+
+                let (p3, v3) = (p1 + v2 * (0.5 * dt), v1 + a2 * (0.5 * dt));
+                let a3 = acceleration(p3, v3);
+
+                let (p4, v4) = (p1 + v3 * dt, v1 + a3 * dt);
+                let a4 = acceleration(p4, v4);
+
+                let position = p1 + (v1 + v2 * 2. + v3 * 2. + v4) * (dt / 6.);
+                let velocity = v1 + (a1 + a2 * 2. + a3 * 2. + a4) * (dt / 6.);
+                ball.move_to(position, velocity, dt);
+            }
+        }
+    }
+}
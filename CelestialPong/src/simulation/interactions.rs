@@ -0,0 +1,62 @@
+use serde::Deserialize;
+
+use crate::simulation::ball::BallType;
+
+// What happens to a ball touching another ball or a body
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+pub enum Outcome {
+    // Balls always bounce on each other, balls bounce on bodies only with this outcome
+    Bounce,
+    // The ball is removed at the end of the step
+    Destroy,
+    // The ball keeps going as another type
+    Convert { into: BallType },
+    // Balls of the type come out of the ball, sharing its mass. Usually paired with Destroy.
+    Spawn { ball_type: BallType, count: u32 },
+    // Points for the level, reported with an interaction event
+    Score { points: i32 },
+    // A flower grows where the ball touched the body, reported with an interaction event
+    Plant,
+}
+
+// Outcomes for a ball of a type touching another type, Body being any body
+#[derive(Clone, Debug, Deserialize)]
+pub struct InteractionRule {
+    pub ball: BallType,
+    pub other: BallType,
+    pub outcomes: Vec<Outcome>,
+}
+
+// Rules of a garden, pairs without one keep the default behaviour : balls bounce on each other,
+// are destroyed by the bodies, seeds plant a flower on the body they land on and shielded flowers
+// lose their shield to the spikes.
+#[derive(Clone, Debug, Default)]
+pub struct InteractionTable {
+    rules: Vec<InteractionRule>,
+}
+
+impl InteractionTable {
+    pub fn new(rules: Vec<InteractionRule>) -> InteractionTable {
+        return InteractionTable { rules };
+    }
+
+    pub fn get_outcomes(&self, ball: BallType, other: BallType) -> &[Outcome] {
+        match self
+            .rules
+            .iter()
+            .find(|rule| rule.ball == ball && rule.other == other)
+        {
+            Some(rule) => return &rule.outcomes,
+            None => {}
+        }
+
+        return match (ball, other) {
+            (BallType::GoodBall, BallType::Body) => &[Outcome::Destroy, Outcome::Plant],
+            (BallType::ShieldedBall, BallType::BadBall) => &[Outcome::Convert {
+                into: BallType::Ball,
+            }],
+            (_, BallType::Body) => &[Outcome::Destroy],
+            _ => &[Outcome::Bounce],
+        };
+    }
+}
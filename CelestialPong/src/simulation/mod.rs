@@ -1,4 +1,13 @@
 pub mod ball;
+pub mod body_path;
+pub mod broadphase;
 pub mod capsule;
+pub mod contact_solver;
 pub mod gravity;
+pub mod hash_grid;
+pub mod integrator;
+pub mod interactions;
 pub mod quad_tree;
+pub mod random;
+pub mod sweep_and_prune;
+pub mod world;
@@ -3,6 +3,10 @@ use macroquad::{
     prelude::*,
 };
 
+use std::cmp::Ordering;
+
+use crate::simulation::gravity::{get_point_gravity_force, get_point_gravity_potential};
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Rect {
     pub x: f32,
@@ -44,6 +48,15 @@ impl Rect {
             || self.down < other.up);
     }
 
+    // Squared distance from the position to the closest point of the rect, 0 inside
+    pub fn get_distance_squared(&self, position: Vec2) -> f32 {
+        let dx = (self.left - position.x)
+            .max(position.x - self.right)
+            .max(0.);
+        let dy = (self.up - position.y).max(position.y - self.down).max(0.);
+        return dx * dx + dy * dy;
+    }
+
     #[allow(dead_code)]
     pub fn debug_draw(&self, thickness: f32, color: Color) {
         draw_rectangle_lines(
@@ -61,121 +74,571 @@ impl Rect {
 pub struct QuadTreeEntry {
     pub position: Vec2,
     pub payload: usize,
+    pub mass: f32,
 }
 
 impl QuadTreeEntry {
-    pub fn new(position: Vec2, payload: usize) -> QuadTreeEntry {
-        return QuadTreeEntry { position, payload };
+    pub fn new(position: Vec2, payload: usize, mass: f32) -> QuadTreeEntry {
+        return QuadTreeEntry {
+            position,
+            payload,
+            mass,
+        };
+    }
+}
+
+// Orders entries from the closest to the position, ties being broken by payload so that the order
+// does not depend on how the entries were stored
+pub fn compare_distance(position: Vec2, a: &QuadTreeEntry, b: &QuadTreeEntry) -> Ordering {
+    return a
+        .position
+        .distance_squared(position)
+        .total_cmp(&b.position.distance_squared(position))
+        .then(a.payload.cmp(&b.payload));
+}
+
+pub fn sort_by_distance(entries: &mut [QuadTreeEntry], position: Vec2) {
+    entries.sort_unstable_by(|a, b| compare_distance(position, a, b));
+}
+
+// Adds the entry to the k closest ones found so far, sorted by distance
+#[allow(dead_code)]
+pub fn insert_nearest(
+    position: Vec2,
+    k: usize,
+    entry: QuadTreeEntry,
+    nearest: &mut Vec<QuadTreeEntry>,
+) {
+    let rank = nearest
+        .partition_point(|other| compare_distance(position, other, &entry) == Ordering::Less);
+    if rank < k {
+        if nearest.len() == k {
+            nearest.pop();
+        }
+        nearest.insert(rank, entry);
+    }
+}
+
+// Keeps the closest of the two entries
+pub fn keep_nearest(position: Vec2, entry: QuadTreeEntry, nearest: &mut Option<QuadTreeEntry>) {
+    match nearest {
+        Some(closest) if compare_distance(position, closest, &entry) != Ordering::Greater => {}
+        _ => *nearest = Some(entry),
     }
 }
 
-const QUADTREE_SIZE: usize = 1;
+pub const DEFAULT_BUCKET_CAPACITY: usize = 8;
+pub const DEFAULT_MAX_DEPTH: usize = 10;
 
+// Node of the tree, entries are only stored in the leaves
 #[derive(Clone, PartialEq, Debug)]
-pub struct QuadTree {
-    entries: [QuadTreeEntry; QUADTREE_SIZE],
-    number_of_entries: usize,
+struct QuadNode {
     area: Rect,
-    sub_trees: Option<Box<[QuadTree; 4]>>,
+    entries: Vec<QuadTreeEntry>,
+    sub_nodes: Option<Box<[QuadNode; 4]>>,
+
+    // Number of entries in this node and its sub nodes, their total mass and the sum of their
+    // positions weighted by their mass, used to see the whole node as a single body from afar
+    count: usize,
+    mass: f32,
+    mass_moment: Vec2,
 }
 
-impl QuadTree {
-    pub fn new(area: Rect) -> QuadTree {
-        return QuadTree {
+impl QuadNode {
+    fn new(area: Rect) -> QuadNode {
+        return QuadNode {
             area,
-            entries: [QuadTreeEntry::new(Vec2::ZERO, 0); QUADTREE_SIZE],
-            number_of_entries: 0,
-            sub_trees: Option::None,
+            entries: Vec::new(),
+            sub_nodes: None,
+            count: 0,
+            mass: 0.,
+            mass_moment: Vec2::ZERO,
         };
     }
 
-    fn is_full(&self) -> bool {
-        return self.number_of_entries >= QUADTREE_SIZE;
+    // Index of the sub node covering the position : up left, up right, down left, down right
+    fn get_quadrant(&self, position: Vec2) -> usize {
+        let right = (position.x >= self.area.x) as usize;
+        let down = (position.y >= self.area.y) as usize;
+        return right + down * 2;
     }
 
-    pub fn add(&mut self, entry: QuadTreeEntry) {
-        if !self.area.contains(entry.position) {
-            return;
+    // Recomputes the totals from the entries or the sub nodes, instead of adding and subtracting
+    // them, so that moving entries around does not accumulate rounding errors
+    fn refresh(&mut self) {
+        match self.sub_nodes {
+            Some(ref sub_nodes) => {
+                self.count = sub_nodes.iter().map(|node| node.count).sum();
+                self.mass = sub_nodes.iter().map(|node| node.mass).sum();
+                self.mass_moment = sub_nodes
+                    .iter()
+                    .fold(Vec2::ZERO, |sum, node| sum + node.mass_moment);
+            }
+            None => {
+                self.count = self.entries.len();
+                self.mass = self.entries.iter().map(|entry| entry.mass).sum();
+                self.mass_moment = self
+                    .entries
+                    .iter()
+                    .fold(Vec2::ZERO, |sum, entry| sum + entry.position * entry.mass);
+            }
         }
+    }
 
-        match self.is_full() {
-            false => {
-                self.entries[self.number_of_entries] = entry;
-                self.number_of_entries = self.number_of_entries + 1;
-                if self.is_full() {
-                    self.sub_trees = Some(Box::new([
-                        QuadTree::new(Rect::new(
-                            self.area.x - self.area.half_width / 2.,
-                            self.area.y - self.area.half_height / 2.,
-                            self.area.half_width,
-                            self.area.half_height,
-                        )),
-                        QuadTree::new(Rect::new(
-                            self.area.x + self.area.half_width / 2.,
-                            self.area.y - self.area.half_height / 2.,
-                            self.area.half_width,
-                            self.area.half_height,
-                        )),
-                        QuadTree::new(Rect::new(
-                            self.area.x - self.area.half_width / 2.,
-                            self.area.y + self.area.half_height / 2.,
-                            self.area.half_width,
-                            self.area.half_height,
-                        )),
-                        QuadTree::new(Rect::new(
-                            self.area.x + self.area.half_width / 2.,
-                            self.area.y + self.area.half_height / 2.,
-                            self.area.half_width,
-                            self.area.half_height,
-                        )),
-                    ]));
-                }
-            }
-            true => match self.sub_trees {
-                Some(ref mut sub_nodes) => {
-                    for node in sub_nodes.iter_mut() {
-                        node.add(entry);
+    fn split(&mut self) {
+        let half_width = self.area.half_width;
+        let half_height = self.area.half_height;
+        let sub_area = |right: f32, down: f32| {
+            Rect::new(
+                self.area.x + half_width / 2. * right,
+                self.area.y + half_height / 2. * down,
+                half_width,
+                half_height,
+            )
+        };
+
+        let mut sub_nodes = Box::new([
+            QuadNode::new(sub_area(-1., -1.)),
+            QuadNode::new(sub_area(1., -1.)),
+            QuadNode::new(sub_area(-1., 1.)),
+            QuadNode::new(sub_area(1., 1.)),
+        ]);
+
+        for entry in std::mem::take(&mut self.entries) {
+            sub_nodes[self.get_quadrant(entry.position)]
+                .entries
+                .push(entry);
+        }
+        for node in sub_nodes.iter_mut() {
+            node.refresh();
+        }
+
+        self.sub_nodes = Some(sub_nodes);
+    }
+
+    // Gathers the entries of the sub nodes back in this node once they fit in a single bucket
+    fn merge(&mut self) {
+        match self.sub_nodes.take() {
+            Some(mut sub_nodes) => {
+                for node in sub_nodes.iter_mut() {
+                    node.merge();
+                    self.entries.append(&mut node.entries);
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn add(&mut self, entry: QuadTreeEntry, depth: usize, settings: &QuadTreeSettings) {
+        let quadrant = self.get_quadrant(entry.position);
+        match self.sub_nodes {
+            Some(ref mut sub_nodes) => sub_nodes[quadrant].add(entry, depth + 1, settings),
+            None => {
+                self.entries.push(entry);
+                // At the maximum depth buckets grow past their capacity, which happens when
+                // many entries are at the same place
+                if self.entries.len() > settings.bucket_capacity && depth < settings.max_depth {
+                    self.split();
+                }
+            }
+        }
+        self.refresh();
+    }
+
+    fn remove(
+        &mut self,
+        position: Vec2,
+        payload: usize,
+        settings: &QuadTreeSettings,
+    ) -> Option<QuadTreeEntry> {
+        let quadrant = self.get_quadrant(position);
+        let removed = match self.sub_nodes {
+            Some(ref mut sub_nodes) => sub_nodes[quadrant].remove(position, payload, settings),
+            None => self
+                .entries
+                .iter()
+                .position(|entry| entry.payload == payload)
+                .map(|index| self.entries.swap_remove(index)),
+        };
+
+        if removed.is_some() {
+            self.refresh();
+            if self.sub_nodes.is_some() && self.count <= settings.bucket_capacity {
+                self.merge();
+            }
+        }
+        return removed;
+    }
+
+    // Moves the entry from position to its new position, updating it in place when both
+    // positions are in the same leaf
+    fn move_entry(
+        &mut self,
+        position: Vec2,
+        entry: QuadTreeEntry,
+        depth: usize,
+        settings: &QuadTreeSettings,
+    ) -> bool {
+        let quadrant = self.get_quadrant(position);
+        let new_quadrant = self.get_quadrant(entry.position);
+        let moved = match self.sub_nodes {
+            Some(ref mut sub_nodes) => match quadrant == new_quadrant {
+                true => sub_nodes[quadrant].move_entry(position, entry, depth + 1, settings),
+                false => match sub_nodes[quadrant].remove(position, entry.payload, settings) {
+                    Some(_) => {
+                        sub_nodes[new_quadrant].add(entry, depth + 1, settings);
+                        true
                     }
+                    None => false,
+                },
+            },
+            None => match self
+                .entries
+                .iter_mut()
+                .find(|other| other.payload == entry.payload)
+            {
+                Some(other) => {
+                    *other = entry;
+                    true
                 }
-                None => panic!("missing subnodes!"),
+                None => false,
             },
+        };
+
+        if moved {
+            self.refresh();
         }
+        return moved;
     }
 
-    pub fn query_entries(&self, query: &Rect, result: &mut Vec<QuadTreeEntry>) {
+    fn query_entries(&self, query: &Rect, result: &mut Vec<QuadTreeEntry>) {
         if !self.area.overlap(query) {
             return;
         }
 
-        for entry in self.entries {
-            if query.contains(entry.position) {
-                result.push(entry);
+        match self.sub_nodes {
+            Some(ref sub_nodes) => {
+                for node in sub_nodes.iter() {
+                    node.query_entries(query, result);
+                }
+            }
+            None => {
+                for entry in &self.entries {
+                    if query.contains(entry.position) {
+                        result.push(*entry);
+                    }
+                }
             }
         }
+    }
+
+    fn query_circle(&self, center: Vec2, radius_squared: f32, result: &mut Vec<QuadTreeEntry>) {
+        if self.count == 0 || self.area.get_distance_squared(center) > radius_squared {
+            return;
+        }
 
-        match self.sub_trees {
+        match self.sub_nodes {
             Some(ref sub_nodes) => {
                 for node in sub_nodes.iter() {
-                    node.query_entries(query, result);
+                    node.query_circle(center, radius_squared, result);
+                }
+            }
+            None => {
+                for entry in &self.entries {
+                    if entry.position.distance_squared(center) <= radius_squared {
+                        result.push(*entry);
+                    }
                 }
             }
-            None => {}
         }
     }
 
+    // Branch and bound search, nodes farther than the k-th closest entry found so far are skipped
     #[allow(dead_code)]
-    pub fn debug_draw(&self) {
-        let color = color::RED;
+    fn k_nearest(&self, position: Vec2, k: usize, nearest: &mut Vec<QuadTreeEntry>) {
+        if self.count == 0 {
+            return;
+        }
+        if nearest.len() == k {
+            let farthest = nearest[k - 1].position.distance_squared(position);
+            if self.area.get_distance_squared(position) > farthest {
+                return;
+            }
+        }
 
+        match self.sub_nodes {
+            Some(ref sub_nodes) => {
+                // Closest sub nodes first, the farther ones are then more likely to be skipped
+                let mut order = [0, 1, 2, 3];
+                order.sort_unstable_by(|a, b| {
+                    let a = sub_nodes[*a].area.get_distance_squared(position);
+                    let b = sub_nodes[*b].area.get_distance_squared(position);
+                    a.total_cmp(&b)
+                });
+                for index in order {
+                    sub_nodes[index].k_nearest(position, k, nearest);
+                }
+            }
+            None => {
+                for entry in &self.entries {
+                    insert_nearest(position, k, *entry, nearest);
+                }
+            }
+        }
+    }
+
+    // Same search for the closest entry only
+    fn nearest(&self, position: Vec2, nearest: &mut Option<QuadTreeEntry>) {
+        if self.count == 0 {
+            return;
+        }
+        match nearest {
+            Some(closest) => {
+                let closest = closest.position.distance_squared(position);
+                if self.area.get_distance_squared(position) > closest {
+                    return;
+                }
+            }
+            None => {}
+        }
+
+        match self.sub_nodes {
+            Some(ref sub_nodes) => {
+                let mut order = [0, 1, 2, 3];
+                order.sort_unstable_by(|a, b| {
+                    let a = sub_nodes[*a].area.get_distance_squared(position);
+                    let b = sub_nodes[*b].area.get_distance_squared(position);
+                    a.total_cmp(&b)
+                });
+                for index in order {
+                    sub_nodes[index].nearest(position, nearest);
+                }
+            }
+            None => {
+                for entry in &self.entries {
+                    keep_nearest(position, *entry, nearest);
+                }
+            }
+        }
+    }
+
+    // Sums the effect of every entry, nodes seen under a size / distance ratio lower than theta
+    // being taken as a single mass at their center of mass
+    fn sum_over_masses<T: Default + std::ops::Add<Output = T>>(
+        &self,
+        position: Vec2,
+        skipped_payload: usize,
+        theta: f32,
+        effect: &impl Fn(Vec2, f32) -> T,
+    ) -> T {
+        if self.mass <= 0. {
+            return T::default();
+        }
+
+        let mut sum = T::default();
+        match self.sub_nodes {
+            Some(ref sub_nodes) => {
+                let center_of_mass = self.mass_moment / self.mass;
+                let size = self.area.half_width * 2.;
+                let distance_squared = (center_of_mass - position).length_squared();
+                if size * size < theta * theta * distance_squared {
+                    return effect(center_of_mass, self.mass);
+                }
+
+                for node in sub_nodes.iter() {
+                    sum = sum + node.sum_over_masses(position, skipped_payload, theta, effect);
+                }
+            }
+            None => {
+                for entry in &self.entries {
+                    if entry.payload != skipped_payload {
+                        sum = sum + effect(entry.position, entry.mass);
+                    }
+                }
+            }
+        }
+
+        return sum;
+    }
+
+    fn debug_draw(&self, color: Color) {
         self.area.debug_draw(2., color);
 
-        match &self.sub_trees {
+        match &self.sub_nodes {
             Some(sub_nodes) => {
                 for node in sub_nodes.iter() {
-                    node.debug_draw();
+                    node.debug_draw(color);
                 }
             }
             None => {}
         }
     }
 }
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct QuadTreeSettings {
+    // Entries a leaf holds before being split
+    bucket_capacity: usize,
+    // Leaves at this depth are never split
+    max_depth: usize,
+}
+
+// Spatial index of the balls, payloads are their index.
+// Entries can be added, removed and moved around, so the tree can be kept from a step to the next.
+#[derive(Clone, PartialEq, Debug)]
+pub struct QuadTree {
+    root: QuadNode,
+    // Entries outside of the tree area, checked one by one
+    outside: Vec<QuadTreeEntry>,
+    settings: QuadTreeSettings,
+}
+
+impl QuadTree {
+    pub fn new(area: Rect) -> QuadTree {
+        return QuadTree::with_capacity(area, DEFAULT_BUCKET_CAPACITY, DEFAULT_MAX_DEPTH);
+    }
+
+    pub fn with_capacity(area: Rect, bucket_capacity: usize, max_depth: usize) -> QuadTree {
+        return QuadTree {
+            root: QuadNode::new(area),
+            outside: Vec::new(),
+            settings: QuadTreeSettings {
+                bucket_capacity: bucket_capacity.max(1),
+                max_depth,
+            },
+        };
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        return self.root.count + self.outside.len();
+    }
+
+    #[allow(dead_code)]
+    pub fn clear(&mut self) {
+        self.root = QuadNode::new(self.root.area);
+        self.outside.clear();
+    }
+
+    pub fn add(&mut self, entry: QuadTreeEntry) {
+        match self.root.area.contains(entry.position) {
+            true => self.root.add(entry, 0, &self.settings),
+            false => self.outside.push(entry),
+        }
+    }
+
+    // Removes the entry with this payload, position being where it was added or last moved to
+    pub fn remove(&mut self, position: Vec2, payload: usize) -> Option<QuadTreeEntry> {
+        match self.root.area.contains(position) {
+            true => return self.root.remove(position, payload, &self.settings),
+            false => {
+                return self
+                    .outside
+                    .iter()
+                    .position(|entry| entry.payload == payload)
+                    .map(|index| self.outside.swap_remove(index))
+            }
+        }
+    }
+
+    // Moves the entry with the same payload from position, returns false when it was not found
+    pub fn move_entry(&mut self, position: Vec2, entry: QuadTreeEntry) -> bool {
+        let area = self.root.area;
+        if area.contains(position) && area.contains(entry.position) {
+            return self.root.move_entry(position, entry, 0, &self.settings);
+        }
+
+        match self.remove(position, entry.payload) {
+            Some(_) => {
+                self.add(entry);
+                return true;
+            }
+            None => return false,
+        }
+    }
+
+    pub fn query_entries(&self, query: &Rect, result: &mut Vec<QuadTreeEntry>) {
+        self.root.query_entries(query, result);
+
+        for entry in &self.outside {
+            if query.contains(entry.position) {
+                result.push(*entry);
+            }
+        }
+    }
+
+    // Entries at most radius away from the center, the closest first. The result is cleared before.
+    pub fn query_circle(&self, center: Vec2, radius: f32, result: &mut Vec<QuadTreeEntry>) {
+        result.clear();
+        self.root.query_circle(center, radius * radius, result);
+
+        for entry in &self.outside {
+            if entry.position.distance_squared(center) <= radius * radius {
+                result.push(*entry);
+            }
+        }
+        sort_by_distance(result, center);
+    }
+
+    pub fn nearest(&self, position: Vec2) -> Option<QuadTreeEntry> {
+        let mut nearest = None;
+        for entry in &self.outside {
+            keep_nearest(position, *entry, &mut nearest);
+        }
+        self.root.nearest(position, &mut nearest);
+        return nearest;
+    }
+
+    // The k entries closest to the position, the closest first. The result is cleared before.
+    #[allow(dead_code)]
+    pub fn k_nearest(&self, position: Vec2, k: usize, result: &mut Vec<QuadTreeEntry>) {
+        result.clear();
+        if k == 0 {
+            return;
+        }
+
+        for entry in &self.outside {
+            insert_nearest(position, k, *entry, result);
+        }
+        self.root.k_nearest(position, k, result);
+    }
+
+    // Barnes-Hut approximation of the gravity pulling on a position, the entry with the skipped
+    // payload being the one at that position.
+    // See https://en.wikipedia.org/wiki/Barnes%E2%80%93Hut_simulation
+    pub fn get_gravity_force(&self, position: Vec2, skipped_payload: usize, theta: f32) -> Vec2 {
+        return self.sum_over_masses(position, skipped_payload, theta, &|mass_position, mass| {
+            get_point_gravity_force(position, mass_position, mass)
+        });
+    }
+
+    // Same approximation for the gravity potential at a position, per unit of mass
+    pub fn get_gravity_potential(&self, position: Vec2, skipped_payload: usize, theta: f32) -> f32 {
+        return self.sum_over_masses(position, skipped_payload, theta, &|mass_position, mass| {
+            get_point_gravity_potential(position, mass_position, mass)
+        });
+    }
+
+    fn sum_over_masses<T: Default + std::ops::Add<Output = T>>(
+        &self,
+        position: Vec2,
+        skipped_payload: usize,
+        theta: f32,
+        effect: &impl Fn(Vec2, f32) -> T,
+    ) -> T {
+        let mut sum = self
+            .root
+            .sum_over_masses(position, skipped_payload, theta, effect);
+        for entry in &self.outside {
+            if entry.payload != skipped_payload {
+                sum = sum + effect(entry.position, entry.mass);
+            }
+        }
+        return sum;
+    }
+
+    #[allow(dead_code)]
+    pub fn debug_draw(&self) {
+        self.root.debug_draw(color::RED);
+    }
+}
@@ -0,0 +1,47 @@
+use macroquad::math::Vec2;
+
+// Small seedable generator owned by each level, so that a run can be reproduced from its seed.
+// Based on splitmix64 : https://prng.di.unimi.it/splitmix64.c
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Random {
+    seed: u64,
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        return Random { seed, state: seed };
+    }
+
+    pub fn seed(&self) -> u64 {
+        return self.seed;
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        return z ^ (z >> 31);
+    }
+
+    // Uniform value in [low, high)
+    pub fn gen_range(&mut self, low: f32, high: f32) -> f32 {
+        // Keeping the 24 bits that fit in the f32 mantissa
+        let unit = (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+        return low + (high - low) * unit;
+    }
+}
+
+pub fn random_orbital_pos(
+    random: &mut Random,
+    center: Vec2,
+    min_radius: f32,
+    max_radius: f32,
+) -> Vec2 {
+    let angle = random.gen_range(0., std::f32::consts::PI * 2.);
+    let result = Vec2::from((angle.cos(), angle.sin()));
+    let rad = random.gen_range(min_radius, max_radius);
+    let result = center + result * rad;
+    return result;
+}
@@ -0,0 +1,118 @@
+use macroquad::math::Vec2;
+
+use crate::simulation::{
+    ball::Ball,
+    broadphase::Broadphase,
+    quad_tree::{keep_nearest, QuadTreeEntry, Rect},
+};
+
+// Balls sorted along x by the left edge of their collision area. The order is kept from a step to
+// the next, balls barely moving between two steps makes sorting it again cheap.
+pub struct SweepAndPrune {
+    order: Vec<usize>,
+    // Collision areas, in the sorted order
+    areas: Vec<Rect>,
+    entries: Vec<QuadTreeEntry>,
+    max_radius: f32,
+}
+
+impl SweepAndPrune {
+    pub fn new() -> SweepAndPrune {
+        return SweepAndPrune {
+            order: Vec::new(),
+            areas: Vec::new(),
+            entries: Vec::new(),
+            max_radius: 0.,
+        };
+    }
+}
+
+impl Broadphase for SweepAndPrune {
+    fn update(&mut self, balls: &[Ball]) {
+        if self.order.len() != balls.len() {
+            self.order = (0..balls.len()).collect();
+        }
+
+        // Insertion sort, close to linear on an almost sorted list
+        for sorted in 1..self.order.len() {
+            let index = self.order[sorted];
+            let left = balls[index].get_collision_area().left;
+            let mut position = sorted;
+            while position > 0 && balls[self.order[position - 1]].get_collision_area().left > left {
+                self.order[position] = self.order[position - 1];
+                position = position - 1;
+            }
+            self.order[position] = index;
+        }
+
+        self.areas.clear();
+        self.entries.clear();
+        for index in &self.order {
+            let ball = &balls[*index];
+            self.areas.push(ball.get_collision_area());
+            self.entries
+                .push(QuadTreeEntry::new(ball.position, *index, ball.mass));
+        }
+
+        self.max_radius = balls.iter().map(|ball| ball.radius).fold(0., f32::max);
+    }
+
+    fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    fn query_entries(&self, query: &Rect, result: &mut Vec<QuadTreeEntry>) {
+        // Positions are at most twice the biggest radius right of the area left edge
+        let start = self
+            .areas
+            .partition_point(|area| area.left < query.left - self.max_radius * 2.);
+        for (area, entry) in self.areas[start..].iter().zip(&self.entries[start..]) {
+            if area.left > query.right {
+                break;
+            }
+            if query.contains(entry.position) {
+                result.push(*entry);
+            }
+        }
+    }
+
+    // Walks away from the position along x on both sides, until the x distance alone is more than
+    // the closest distance found
+    fn nearest(&self, position: Vec2) -> Option<QuadTreeEntry> {
+        let mut nearest: Option<QuadTreeEntry> = None;
+        let is_beaten = |nearest: &Option<QuadTreeEntry>, dx: f32| match nearest {
+            Some(closest) => dx > 0. && dx * dx > closest.position.distance_squared(position),
+            None => false,
+        };
+
+        let start = self.areas.partition_point(|area| area.left < position.x);
+        for (area, entry) in self.areas[start..].iter().zip(&self.entries[start..]) {
+            // Positions are right of their area left edge
+            if is_beaten(&nearest, area.left - position.x) {
+                break;
+            }
+            keep_nearest(position, *entry, &mut nearest);
+        }
+        for (area, entry) in self.areas[..start].iter().zip(&self.entries[..start]).rev() {
+            // and at most twice the biggest radius right of it
+            if is_beaten(&nearest, position.x - area.left - self.max_radius * 2.) {
+                break;
+            }
+            keep_nearest(position, *entry, &mut nearest);
+        }
+        return nearest;
+    }
+
+    fn find_pairs(&mut self, _balls: &[Ball], pairs: &mut Vec<(usize, usize)>) {
+        for (sorted, area) in self.areas.iter().enumerate() {
+            for (other_sorted, other) in self.areas.iter().enumerate().skip(sorted + 1) {
+                if other.left > area.right {
+                    break;
+                }
+                if area.overlap(other) {
+                    pairs.push((self.order[sorted], self.order[other_sorted]));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,806 @@
+use macroquad::prelude::*;
+
+use crate::simulation::{
+    ball::*,
+    body_path::BodyPath,
+    broadphase::*,
+    capsule::Capsule,
+    contact_solver::{bounce_on_body, ContactSolver},
+    gravity::*,
+    integrator::Integrator,
+    interactions::{InteractionTable, Outcome},
+    quad_tree,
+};
+
+// Distance to the system barycenter, relative to the system radius, after which balls are
+// trapped around the whole system instead of a single body
+const SYSTEM_TRAP_RATIO: f32 = 2.;
+// Barnes-Hut opening angle of the N-body mode, lower is more accurate but slower
+const BARNES_HUT_THETA: f32 = 0.5;
+// Angle in radians between the pieces of a split ball
+const SPLIT_SPREAD: f32 = 0.35;
+// Distance at which magnets start pulling the balls, and how hard they pull them when touching
+pub const MAGNET_RADIUS: f32 = 90.;
+const MAGNET_ACCELERATION: f32 = 400.;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WorldEvent {
+    // Two balls touched each other, indices are the ones from before the removals of the step
+    Collision {
+        first: usize,
+        second: usize,
+    },
+    // A ball touched a body, it will be removed at the end of the step
+    BodyImpact {
+        body_index: usize,
+        body: Ball,
+        ball: Ball,
+    },
+    // A ball with an impact effect touched another ball and went off, it will be removed at the
+    // end of the step
+    EffectTriggered {
+        ball: Ball,
+    },
+    // A ball bounced on a capsule, ball being as it left it
+    CapsuleHit {
+        capsule_index: usize,
+        ball_index: usize,
+        ball: Ball,
+    },
+    // An interaction rule gave points or planted a flower, other is the ball or the body that was
+    // touched, with its index when it is a body
+    Interaction {
+        ball: Ball,
+        other: Ball,
+        body_index: Option<usize>,
+        outcome: Outcome,
+    },
+    // A ball was removed from the world, reported from the highest index to the lowest
+    Removed {
+        index: usize,
+        ball: Ball,
+    },
+}
+
+// Conserved quantities of the balls, to see how the integrator and the orbit trap change them.
+// Angular momentum is taken around the barycenter of the bodies.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct WorldDiagnostics {
+    pub kinetic_energy: f32,
+    pub potential_energy: f32,
+    pub angular_momentum: f32,
+}
+
+impl WorldDiagnostics {
+    pub fn get_total_energy(&self) -> f32 {
+        return self.kinetic_energy + self.potential_energy;
+    }
+}
+
+// Two balls touching at the end of a step, with the types their interaction rules were applied for
+#[derive(Clone, Copy, PartialEq)]
+struct TouchingPair {
+    first: usize,
+    second: usize,
+    first_type: BallType,
+    second_type: BallType,
+}
+
+// Headless simulation of balls orbiting bodies moving on rails.
+// Does not read inputs nor draw anything, levels drive it and react to the reported events.
+pub struct World {
+    // Levels may push new balls, removing or replacing them goes through the world so that the
+    // indices it keeps follow
+    pub balls: Vec<Ball>,
+    pub static_bodies: Vec<Ball>,
+    // Path of each body, bodies without one stay in place
+    pub body_paths: Vec<BodyPath>,
+    // Barriers and paddles, moved by the level
+    pub capsules: Vec<Capsule>,
+    // Finds the balls close to each other or to a position, payloads are the ball indices
+    pub broadphase: Box<dyn Broadphase>,
+
+    pub orbit_trap: f32,
+    pub orbit_trap_size: f32,
+    pub kill_distance_squared: f32,
+    // Bad balls leaving this area are removed
+    pub bad_ball_area: Option<quad_tree::Rect>,
+    // Ball held by the player, not affected by gravity
+    pub grabbed_ball: Option<usize>,
+    // When set, balls also pull on each other with their own mass
+    pub n_body: bool,
+    pub integrator: Integrator,
+    pub contact_solver: ContactSolver,
+    // What balls do when touching each other or a body
+    pub interactions: InteractionTable,
+    // Balls touching a body bounce on it with this bounciness, whatever the interactions are
+    pub body_bounciness: Option<f32>,
+    // When set, diagnostics are measured at the start of each step
+    pub diagnostics_enabled: bool,
+    pub diagnostics: WorldDiagnostics,
+    // Number of steps done since the world creation
+    pub tick: u64,
+    // Seconds simulated since the world creation, bodies are placed from it
+    pub time: f64,
+
+    broadphase_kind: BroadphaseKind,
+    // Tree of the ball masses used by the N-body mode, whatever the broadphase is
+    n_body_tree: QuadTreeBroadphase,
+    tree_area: quad_tree::Rect,
+    candidate_pairs: Vec<(usize, usize)>,
+    near_balls: Vec<quad_tree::QuadTreeEntry>,
+    balls_marked_for_delete: Vec<usize>,
+    // Pairs touching in the last step, sorted, their rules are not applied again while they stay
+    touching_pairs: Vec<TouchingPair>,
+    events: Vec<WorldEvent>,
+}
+
+impl World {
+    pub fn new(tree_area: quad_tree::Rect, orbit_trap: f32, orbit_trap_size: f32) -> World {
+        return World {
+            balls: Vec::new(),
+            static_bodies: Vec::new(),
+            body_paths: Vec::new(),
+            capsules: Vec::new(),
+            broadphase: BroadphaseKind::default().create(tree_area),
+            orbit_trap,
+            orbit_trap_size,
+            kill_distance_squared: f32::MAX,
+            bad_ball_area: None,
+            grabbed_ball: None,
+            n_body: false,
+            integrator: Integrator::default(),
+            contact_solver: ContactSolver::new(),
+            interactions: InteractionTable::default(),
+            body_bounciness: None,
+            diagnostics_enabled: false,
+            diagnostics: WorldDiagnostics::default(),
+            tick: 0,
+            time: 0.,
+            broadphase_kind: BroadphaseKind::default(),
+            n_body_tree: QuadTreeBroadphase::new(tree_area),
+            tree_area,
+            candidate_pairs: Vec::new(),
+            near_balls: Vec::new(),
+            balls_marked_for_delete: Vec::new(),
+            touching_pairs: Vec::new(),
+            events: Vec::new(),
+        };
+    }
+
+    // Acceleration of a ball if it was at that position with that velocity
+    fn get_local_force(&self, index: usize, position: Vec2, velocity: Vec2) -> Vec2 {
+        let ball = &Ball {
+            position,
+            velocity,
+            ..self.balls[index]
+        };
+        let mut local_force = Vec2::ZERO;
+
+        // Comuting gravity
+        if self.grabbed_ball != Some(index) {
+            for body in &self.static_bodies {
+                local_force = local_force + get_gravity_force(ball, body)
+            }
+
+            if self.n_body {
+                local_force = local_force
+                    + self.n_body_tree.tree.get_gravity_force(
+                        ball.position,
+                        index,
+                        BARNES_HUT_THETA,
+                    );
+            }
+        }
+
+        // Trapping ball in the orbit of the body it is bound to
+        match self.get_trap_body(ball.position) {
+            Some(trap_body) => {
+                // Orbits are followed in the frame of the body, which may be moving
+                let ideal_velocity = get_orbital_velocity(ball, &trap_body);
+                let relative_velocity = ball.velocity - trap_body.velocity;
+                let delta = if ideal_velocity.dot(relative_velocity) > 0. {
+                    ideal_velocity - relative_velocity
+                } else {
+                    ideal_velocity * -1. - relative_velocity
+                };
+                // If the ball velocity differ from the ideal orbit, nudge the ball toward that velocity
+                if delta.length_squared() > self.orbit_trap_size {
+                    local_force = local_force + (delta / delta.length()) * self.orbit_trap;
+                }
+            }
+            _ => {}
+        }
+
+        return local_force;
+    }
+
+    // Body whose orbit a ball at this position is nudged toward.
+    // Close to the bodies it is the one pulling the hardest, so balls are handed off when they
+    // cross to another body side. Far from them it is the whole system, seen as a single body.
+    pub fn get_trap_body(&self, position: Vec2) -> Option<Ball> {
+        if self.static_bodies.is_empty() {
+            return None;
+        }
+
+        let system = get_barycenter_body(&self.static_bodies);
+        if (system.position - position).length() > system.radius * SYSTEM_TRAP_RATIO {
+            return Some(system);
+        }
+
+        return self
+            .static_bodies
+            .iter()
+            .max_by(|body, other| {
+                let pull = body.mass / (body.position - position).length_squared();
+                let other_pull = other.mass / (other.position - position).length_squared();
+                pull.total_cmp(&other_pull)
+            })
+            .copied();
+    }
+
+    pub fn get_broadphase_kind(&self) -> BroadphaseKind {
+        return self.broadphase_kind;
+    }
+
+    pub fn set_broadphase(&mut self, kind: BroadphaseKind) {
+        self.broadphase_kind = kind;
+        self.broadphase = kind.create(self.tree_area);
+        self.broadphase.update(&self.balls);
+    }
+
+    fn get_diagnostics(&self) -> WorldDiagnostics {
+        let mut diagnostics = WorldDiagnostics::default();
+        let center = match self.static_bodies.is_empty() {
+            true => Vec2::ZERO,
+            false => get_barycenter_body(&self.static_bodies).position,
+        };
+
+        for (index, ball) in self.balls.iter().enumerate() {
+            diagnostics.kinetic_energy =
+                diagnostics.kinetic_energy + 0.5 * ball.mass * ball.velocity.length_squared();
+            diagnostics.angular_momentum = diagnostics.angular_momentum
+                + ball.mass * (ball.position - center).perp_dot(ball.velocity);
+
+            for body in &self.static_bodies {
+                diagnostics.potential_energy =
+                    diagnostics.potential_energy + ball.mass * get_gravity_potential(ball, body);
+            }
+
+            // Each pair of balls is seen from both sides
+            if self.n_body {
+                diagnostics.potential_energy = diagnostics.potential_energy
+                    + 0.5
+                        * ball.mass
+                        * self.n_body_tree.tree.get_gravity_potential(
+                            ball.position,
+                            index,
+                            BARNES_HUT_THETA,
+                        );
+            }
+        }
+
+        return diagnostics;
+    }
+
+    // Removes every ball, along with the grabbed ball and the pairs that were touching
+    pub fn clear_balls(&mut self) {
+        self.balls.clear();
+        self.grabbed_ball = None;
+        self.touching_pairs.clear();
+        self.balls_marked_for_delete.clear();
+    }
+
+    // Removes balls between steps the way the world removes them at the end of one, reporting
+    // them in Removed events
+    pub fn remove_balls(&mut self, indices: &[usize]) -> &[WorldEvent] {
+        self.events.clear();
+        for index in indices {
+            self.mark_for_delete(*index);
+        }
+        self.remove_marked_balls();
+
+        return &self.events;
+    }
+
+    // Adds a body moving along the path, placed where the path puts it at the current time.
+    // dt is the duration of a step, giving the body its starting velocity.
+    pub fn add_body(&mut self, body: Ball, path: BodyPath, dt: f32) {
+        self.static_bodies.push(body);
+        self.body_paths.push(path);
+        self.move_bodies(self.time, dt);
+    }
+
+    fn place_bodies(&mut self, time: f64) {
+        for index in 0..self.static_bodies.len() {
+            let (placed, current) = self.static_bodies.split_at_mut(index);
+            match self
+                .body_paths
+                .get(index)
+                .and_then(|path| path.get_position(time, placed))
+            {
+                Some(position) => current[0].position = position,
+                None => {}
+            }
+        }
+    }
+
+    // Moves the bodies along their path from time - dt to time, their velocity being the one of that move
+    fn move_bodies(&mut self, time: f64, dt: f32) {
+        self.place_bodies(time - dt as f64);
+        for body in self.static_bodies.iter_mut() {
+            body.prev_position = body.position;
+        }
+
+        self.place_bodies(time);
+        for body in self.static_bodies.iter_mut() {
+            body.velocity = (body.position - body.prev_position) / dt;
+        }
+    }
+
+    fn mark_for_delete(&mut self, index: usize) -> bool {
+        if self.balls_marked_for_delete.contains(&index) {
+            return false;
+        }
+
+        self.balls_marked_for_delete.push(index);
+        return true;
+    }
+
+    // Biggest ball radius, and longest move of a ball over the last step
+    fn get_ball_extents(&self) -> (f32, f32) {
+        let mut max_radius: f32 = 0.;
+        let mut max_motion: f32 = 0.;
+        for ball in &self.balls {
+            max_radius = max_radius.max(ball.radius);
+            max_motion = max_motion.max((ball.position - ball.prev_position).length());
+        }
+        return (max_radius, max_motion);
+    }
+
+    // Bounces the ball on the capsule if it touches it. Fast balls are first moved back to where
+    // they started touching it, so that they can not go through thin capsules.
+    fn bounce_on_capsule(&mut self, index: usize, capsule: &Capsule, dt: f32) -> bool {
+        let mut ball = self.balls[index];
+        if ball.is_fast() {
+            match capsule.get_time_of_impact(&ball) {
+                Some(time) => {
+                    ball.shift(ball.prev_position.lerp(ball.position, time) - ball.position);
+                }
+                None => {}
+            }
+        }
+        if !capsule.check_collision(&ball) {
+            return false;
+        }
+
+        let body = capsule.get_contact_body(ball.position);
+        let bounciness = ball.material.combine(&capsule.material).restitution;
+        let bounced = bounce_on_body(&mut ball, &body, bounciness, dt);
+        if bounced && capsule.deflection > 0. {
+            capsule.deflect(&mut ball, dt);
+        }
+        self.balls[index] = ball;
+        return bounced;
+    }
+
+    // Adds pieces sharing the mass and the area of the ball, starting inside of it. They are
+    // fanned out a bit faster than the ball so that they keep its momentum.
+    fn push_pieces(&mut self, ball: &Ball, pieces: u32, ball_type: BallType, dt: f32) {
+        let pieces = pieces.max(1);
+        let radius = ball.radius / (pieces as f32).sqrt();
+        let middle = (pieces - 1) as f32 / 2.;
+        let direction = ball.velocity.try_normalize().unwrap_or(Vec2::X);
+        let turns: Vec<Vec2> = (0..pieces)
+            .map(|piece| Vec2::from_angle((piece as f32 - middle) * SPLIT_SPREAD))
+            .collect();
+        let speed_factor = pieces as f32 / turns.iter().map(|turn| turn.x).sum::<f32>();
+        for turn in turns {
+            let mut piece = Ball {
+                position: ball.position + turn.rotate(direction) * (ball.radius - radius),
+                radius,
+                double_radius: radius * 2.,
+                mass: ball.mass / pieces as f32,
+                ball_type,
+                effect: None,
+                ..*ball
+            };
+            piece.set_velocity(turn.rotate(ball.velocity) * speed_factor, dt);
+            self.balls.push(piece);
+        }
+    }
+
+    // Applies the interaction rules of a ball touching another ball or a body. Balls already
+    // removed during the step are left alone.
+    fn apply_interaction(&mut self, index: usize, other: Ball, body_index: Option<usize>, dt: f32) {
+        if self.balls_marked_for_delete.contains(&index) {
+            return;
+        }
+
+        let ball = self.balls[index];
+        let outcome_count = self
+            .interactions
+            .get_outcomes(ball.ball_type, other.ball_type)
+            .len();
+        for outcome_index in 0..outcome_count {
+            let outcome = self
+                .interactions
+                .get_outcomes(ball.ball_type, other.ball_type)[outcome_index];
+            match outcome {
+                Outcome::Bounce => match body_index {
+                    Some(_) => {
+                        let restitution = ball.material.restitution;
+                        bounce_on_body(&mut self.balls[index], &other, restitution, dt);
+                    }
+                    // Touching balls were already bounced by the contact solver
+                    None => {}
+                },
+                Outcome::Destroy => {
+                    if self.mark_for_delete(index) {
+                        match body_index {
+                            Some(body_index) => self.events.push(WorldEvent::BodyImpact {
+                                body_index,
+                                body: other,
+                                ball,
+                            }),
+                            None => {}
+                        }
+                    }
+                }
+                Outcome::Convert { into } => {
+                    self.balls[index].ball_type = into;
+                }
+                Outcome::Spawn { ball_type, count } => {
+                    self.push_pieces(&ball, count, ball_type, dt);
+                }
+                Outcome::Score { .. } | Outcome::Plant => {
+                    self.events.push(WorldEvent::Interaction {
+                        ball,
+                        other,
+                        body_index,
+                        outcome,
+                    });
+                }
+            }
+        }
+    }
+
+    // Applies the rules of the balls that started touching, or that changed type while touching,
+    // so that balls resting on each other do not score or spawn again every step
+    fn apply_contact_interactions(&mut self, dt: f32) {
+        let previous_pairs = std::mem::take(&mut self.touching_pairs);
+        for contact_index in 0..self.contact_solver.get_contacts().len() {
+            let contact = self.contact_solver.get_contacts()[contact_index];
+            let first = self.balls[contact.first];
+            let second = self.balls[contact.second];
+            let pair = TouchingPair {
+                first: contact.first,
+                second: contact.second,
+                first_type: first.ball_type,
+                second_type: second.ball_type,
+            };
+            self.touching_pairs.push(pair);
+
+            let was_touching = match previous_pairs
+                .binary_search_by_key(&(pair.first, pair.second), |p| (p.first, p.second))
+            {
+                Ok(index) => previous_pairs[index] == pair,
+                Err(_) => false,
+            };
+            if was_touching {
+                continue;
+            }
+
+            self.apply_interaction(contact.first, second, None, dt);
+            self.apply_interaction(contact.second, first, None, dt);
+        }
+        self.touching_pairs
+            .sort_unstable_by_key(|pair| (pair.first, pair.second));
+    }
+
+    // Moves the touching pairs to the ball indices left after the removals of the step
+    fn forget_removed_touching_pairs(&mut self) {
+        let removed = &self.balls_marked_for_delete;
+        self.touching_pairs.retain(|pair| {
+            removed.binary_search(&pair.first).is_err()
+                && removed.binary_search(&pair.second).is_err()
+        });
+        for pair in &mut self.touching_pairs {
+            pair.first = pair.first - removed.partition_point(|index| *index < pair.first);
+            pair.second = pair.second - removed.partition_point(|index| *index < pair.second);
+        }
+    }
+
+    // Magnets pull the balls around them, more as they get closer
+    fn pull_toward_magnets(&mut self, dt: f32) {
+        for index in 0..self.balls.len() {
+            let magnet = self.balls[index];
+            if magnet.ball_type != BallType::Magnet {
+                continue;
+            }
+
+            self.broadphase
+                .query_circle(magnet.position, MAGNET_RADIUS, &mut self.near_balls);
+            for near_index in 0..self.near_balls.len() {
+                let other_index = self.near_balls[near_index].payload;
+                let other = &mut self.balls[other_index];
+                let offset = magnet.position - other.position;
+                let distance = offset.length();
+                if other_index == index || distance == 0. || distance >= MAGNET_RADIUS {
+                    continue;
+                }
+
+                let pull = MAGNET_ACCELERATION * (1. - distance / MAGNET_RADIUS) * dt;
+                let velocity = other.velocity + offset / distance * pull;
+                other.set_velocity(velocity, dt);
+            }
+        }
+    }
+
+    // Replaces a split ball by its pieces, or pushes the balls around a blast
+    fn trigger_effect(&mut self, index: usize, effect: ImpactEffect, dt: f32) {
+        let ball = self.balls[index];
+        match effect {
+            ImpactEffect::Split { pieces } => {
+                self.push_pieces(&ball, pieces, ball.ball_type, dt);
+            }
+            ImpactEffect::Blast { radius, impulse } => {
+                self.broadphase
+                    .query_circle(ball.position, radius, &mut self.near_balls);
+                for near_index in 0..self.near_balls.len() {
+                    let other_index = self.near_balls[near_index].payload;
+                    let other = &mut self.balls[other_index];
+                    let offset = other.position - ball.position;
+                    if other_index == index || offset == Vec2::ZERO {
+                        continue;
+                    }
+
+                    let falloff = 1. - offset.length() / radius;
+                    let velocity =
+                        other.velocity + offset.normalize() * (impulse * falloff / other.mass);
+                    other.set_velocity(velocity, dt);
+                }
+            }
+        }
+    }
+
+    // Balls with an impact effect go off on their first contact with another ball
+    fn trigger_effects(&mut self, dt: f32) {
+        for contact_index in 0..self.contact_solver.get_contacts().len() {
+            let contact = self.contact_solver.get_contacts()[contact_index];
+            for index in [contact.first, contact.second] {
+                match self.balls[index].effect {
+                    Some(effect) => {
+                        if self.mark_for_delete(index) {
+                            self.events.push(WorldEvent::EffectTriggered {
+                                ball: self.balls[index],
+                            });
+                            self.trigger_effect(index, effect, dt);
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    // Fast balls could jump over a ball in a single step, they are swept along their step instead.
+    // A fast ball that went through a ball is moved back to where it first touched it, keeping its
+    // velocity, and the pair is added to the candidate ones so that they bounce.
+    fn sweep_fast_balls(&mut self) {
+        if !self.balls.iter().any(|ball| ball.is_fast()) {
+            return;
+        }
+        let (max_radius, max_motion) = self.get_ball_extents();
+
+        let pair_count = self.candidate_pairs.len();
+        for index in 0..self.balls.len() {
+            let ball = self.balls[index];
+            if !ball.is_fast() {
+                continue;
+            }
+
+            // Balls touched along the step are this close to the middle of the sweep. The
+            // broadphase has the positions from before any ball was moved back, that can be up
+            // to a step of motion away.
+            let middle = (ball.prev_position + ball.position) / 2.;
+            let reach =
+                (ball.position - middle).length() + ball.radius + max_radius + max_motion * 2.;
+            self.broadphase
+                .query_circle(middle, reach, &mut self.near_balls);
+
+            let mut first_hit: Option<(f32, usize)> = None;
+            for near in &self.near_balls {
+                if near.payload == index {
+                    continue;
+                }
+                match ball.get_time_of_impact(&self.balls[near.payload]) {
+                    Some(time) if first_hit.map_or(true, |(first_time, _)| time < first_time) => {
+                        first_hit = Some((time, near.payload));
+                    }
+                    _ => {}
+                }
+            }
+
+            match first_hit {
+                Some((time, other_index)) => {
+                    let other = self.balls[other_index];
+                    if !ball.check_collision(&other) {
+                        let offset = (ball.prev_position - other.prev_position)
+                            .lerp(ball.position - other.position, time);
+                        self.balls[index].shift(other.position + offset - ball.position);
+                    }
+                    self.candidate_pairs
+                        .push((index.min(other_index), index.max(other_index)));
+                }
+                None => {}
+            }
+        }
+
+        if self.candidate_pairs.len() > pair_count {
+            self.candidate_pairs.sort_unstable();
+            self.candidate_pairs.dedup();
+        }
+    }
+
+    pub fn step(&mut self, dt: f32) -> &[WorldEvent] {
+        self.events.clear();
+
+        self.time = self.time + dt as f64;
+        self.move_bodies(self.time, dt);
+
+        if self.n_body {
+            self.n_body_tree.update(&self.balls);
+        }
+
+        if self.diagnostics_enabled {
+            self.diagnostics = self.get_diagnostics();
+        }
+
+        // Updating ball position
+        for index in 0..self.balls.len() {
+            let mut moved_ball = self.balls[index];
+            self.integrator
+                .step(&mut moved_ball, dt, |position, velocity| {
+                    self.get_local_force(index, position, velocity)
+                });
+            self.balls[index] = moved_ball;
+
+            let ball = self.balls.get_mut(index).unwrap();
+
+            // Delete balls that have gone too far
+            let position = ball.position;
+            let ball_type = ball.ball_type;
+            if position.length_squared() > self.kill_distance_squared {
+                self.mark_for_delete(index);
+            } else if ball_type == BallType::BadBall {
+                match self.bad_ball_area {
+                    Some(area) if !area.contains(position) => {
+                        self.mark_for_delete(index);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        self.broadphase.update(&self.balls);
+        self.pull_toward_magnets(dt);
+
+        // Colliding balls, every touching pair is solved together
+        self.broadphase
+            .get_candidate_pairs(&self.balls, &mut self.candidate_pairs);
+        self.sweep_fast_balls();
+        self.contact_solver
+            .find_contacts(&self.balls, &self.candidate_pairs);
+        self.contact_solver.solve(&mut self.balls, dt);
+        for contact in self.contact_solver.get_contacts() {
+            self.events.push(WorldEvent::Collision {
+                first: contact.first,
+                second: contact.second,
+            });
+        }
+        self.trigger_effects(dt);
+        self.apply_contact_interactions(dt);
+
+        // Bouncing on capsules. The broadphase has the positions from before the contacts
+        // were solved and the fast balls moved back, which are at most a step of motion away.
+        if !self.capsules.is_empty() {
+            let (max_radius, max_motion) = self.get_ball_extents();
+            for capsule_index in 0..self.capsules.len() {
+                let capsule = self.capsules[capsule_index];
+                let area = capsule.get_collision_area(max_radius + max_motion * 2. + 1.);
+                self.near_balls.clear();
+                self.broadphase.query_entries(&area, &mut self.near_balls);
+                for near_index in 0..self.near_balls.len() {
+                    let index = self.near_balls[near_index].payload;
+                    if self.bounce_on_capsule(index, &capsule, dt) {
+                        self.events.push(WorldEvent::CapsuleHit {
+                            capsule_index,
+                            ball_index: index,
+                            ball: self.balls[index],
+                        });
+                    }
+                }
+            }
+        }
+
+        // Hitting bodies, the closest balls first
+        for body_index in 0..self.static_bodies.len() {
+            let body = self.static_bodies[body_index];
+            self.broadphase
+                .query_circle(body.position, body.radius * 2., &mut self.near_balls);
+            for near_index in 0..self.near_balls.len() {
+                let near = self.near_balls[near_index];
+                let ball = self.balls[near.payload];
+
+                if !body.check_collision(&ball) {
+                    continue;
+                }
+
+                match self.body_bounciness {
+                    Some(bounciness) => {
+                        bounce_on_body(&mut self.balls[near.payload], &body, bounciness, dt);
+                    }
+                    None => {
+                        self.apply_interaction(near.payload, body, Some(body_index), dt);
+                    }
+                }
+            }
+        }
+
+        self.remove_marked_balls();
+        self.tick = self.tick + 1;
+
+        return &self.events;
+    }
+
+    // Removes the balls marked during the step, the grabbed ball and the touching pairs
+    // following the balls left
+    fn remove_marked_balls(&mut self) {
+        self.balls_marked_for_delete.sort_unstable();
+        for index in self.balls_marked_for_delete.iter().rev() {
+            let ball = self.balls.remove(*index);
+            self.events.push(WorldEvent::Removed {
+                index: *index,
+                ball,
+            });
+
+            match self.grabbed_ball {
+                Some(grabbed) => {
+                    if index == &grabbed {
+                        self.grabbed_ball = None;
+                    } else if index < &grabbed {
+                        self.grabbed_ball = Some(grabbed - 1);
+                    }
+                }
+                None => {}
+            }
+        }
+
+        self.forget_removed_touching_pairs();
+        self.balls_marked_for_delete.clear();
+    }
+
+    // FNV-1a hash of the balls state, two runs with the same seed and inputs must give the same value
+    pub fn state_hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut feed = |value: u32| {
+            for byte in value.to_le_bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        };
+
+        for ball in self.balls.iter().chain(self.static_bodies.iter()) {
+            feed(ball.position.x.to_bits());
+            feed(ball.position.y.to_bits());
+            feed(ball.prev_position.x.to_bits());
+            feed(ball.prev_position.y.to_bits());
+            feed(ball.velocity.x.to_bits());
+            feed(ball.velocity.y.to_bits());
+            feed(ball.rotation.to_bits());
+            feed(ball.ball_type as u32);
+        }
+
+        return hash;
+    }
+}
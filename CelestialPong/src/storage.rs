@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+const GAME_FOLDER: &str = "CelestialPong";
+
+// Folder of the user data where the game keeps its save files
+fn get_data_directory() -> PathBuf {
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| {
+            PathBuf::from(home)
+                .join("Library")
+                .join("Application Support")
+        })
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .map(|home| PathBuf::from(home).join(".local").join("share"))
+            })
+    };
+
+    // Falling back on the working directory when the platform gives nothing
+    return base.unwrap_or_default().join(GAME_FOLDER);
+}
+
+pub fn read_save_file(file_name: &str) -> Option<String> {
+    return std::fs::read_to_string(get_data_directory().join(file_name)).ok();
+}
+
+pub fn write_save_file(file_name: &str, content: &str) -> Result<(), String> {
+    let directory = get_data_directory();
+    let path = directory.join(file_name);
+    return std::fs::create_dir_all(&directory)
+        .and_then(|_| std::fs::write(&path, content))
+        .map_err(|e| format!("{}: {}", path.display(), e));
+}
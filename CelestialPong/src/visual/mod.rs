@@ -1,2 +1,3 @@
 pub mod radial_gradiant;
+pub mod textures;
 pub mod ui_textures;
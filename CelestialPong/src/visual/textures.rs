@@ -0,0 +1,33 @@
+use macroquad::prelude::*;
+
+const TEXTURE_FOLDER: &str = "textures";
+
+fn get_embedded_texture(name: &str) -> Option<&'static [u8]> {
+    let bytes: &'static [u8] = match name {
+        "planet.png" => include_bytes!("..\\..\\textures\\planet.png"),
+        "planet2.png" => include_bytes!("..\\..\\textures\\planet2.png"),
+        "flower_white.png" => include_bytes!("..\\..\\textures\\flower_white.png"),
+        "flower_white2.png" => include_bytes!("..\\..\\textures\\flower_white2.png"),
+        "flower_sproute.png" => include_bytes!("..\\..\\textures\\flower_sproute.png"),
+        "small_flower.png" => include_bytes!("..\\..\\textures\\small_flower.png"),
+        "flower4.png" => include_bytes!("..\\..\\textures\\flower4.png"),
+        "flower5.png" => include_bytes!("..\\..\\textures\\flower5.png"),
+        "seed_v2.png" => include_bytes!("..\\..\\textures\\seed_v2.png"),
+        "spike_v2.png" => include_bytes!("..\\..\\textures\\spike_v2.png"),
+        _ => return None,
+    };
+    return Some(bytes);
+}
+
+// Textures referenced by name in level files.
+// The ones shipped with the game are embedded, others are read from the textures folder.
+pub fn load_texture_by_name(name: &str) -> Result<Texture2D, String> {
+    match get_embedded_texture(name) {
+        Some(bytes) => return Ok(Texture2D::from_file_with_format(bytes, None)),
+        None => {}
+    }
+
+    let path = format!("{}/{}", TEXTURE_FOLDER, name);
+    let bytes = std::fs::read(&path).map_err(|e| format!("{}: {}", path, e))?;
+    return Ok(Texture2D::from_file_with_format(&bytes, None));
+}
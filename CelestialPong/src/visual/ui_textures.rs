@@ -2,7 +2,6 @@ use macroquad::color::colors;
 use macroquad::prelude::*;
 use macroquad::ui::{root_ui, Skin};
 
-#[allow(dead_code)]
 fn get_circle_arrow_material() -> Material {
     let material = load_material(
         ShaderSource::Glsl {
@@ -18,7 +17,6 @@ fn get_circle_arrow_material() -> Material {
     return material;
 }
 
-#[allow(dead_code)]
 fn render_material_to_texture(
     material: Material,
     width: u32,
@@ -47,7 +45,6 @@ fn render_material_to_texture(
     return render_target.texture;
 }
 
-#[allow(dead_code)]
 pub fn get_circe_arrow(width: u32, height: u32, color: Color) -> Texture2D {
     let render_target = render_target(width, height);
     render_target.texture.set_filter(FilterMode::Nearest);
@@ -58,7 +55,6 @@ pub fn get_circe_arrow(width: u32, height: u32, color: Color) -> Texture2D {
     return render_material_to_texture(material, width, height, color);
 }
 
-#[allow(dead_code)]
 pub fn get_circe_arrow_flipped(width: u32, height: u32, color: Color) -> Texture2D {
     let render_target = render_target(width, height);
     render_target.texture.set_filter(FilterMode::Nearest);
@@ -69,7 +65,6 @@ pub fn get_circe_arrow_flipped(width: u32, height: u32, color: Color) -> Texture
     return render_material_to_texture(material, width, height, color);
 }
 
-#[allow(dead_code)]
 fn get_skin(width: f32, height: f32, base: Image, hovered: Image, clicked: Image) -> Skin {
     let style = root_ui()
         .style_builder()
@@ -91,7 +86,6 @@ fn get_skin(width: f32, height: f32, base: Image, hovered: Image, clicked: Image
     };
 }
 
-#[allow(dead_code)]
 pub fn get_anti_clockwise_skin(width: f32, height: f32) -> Skin {
     let texture = get_circe_arrow(width as u32, height as u32, colors::WHITE).get_texture_data();
     let hovered =
@@ -101,7 +95,6 @@ pub fn get_anti_clockwise_skin(width: f32, height: f32) -> Skin {
     return get_skin(width, height, texture, hovered, clicked);
 }
 
-#[allow(dead_code)]
 pub fn get_clockwise_skin(width: f32, height: f32) -> Skin {
     let texture =
         get_circe_arrow_flipped(width as u32, height as u32, colors::WHITE).get_texture_data();
@@ -113,7 +106,6 @@ pub fn get_clockwise_skin(width: f32, height: f32) -> Skin {
     return get_skin(width, height, texture, hovered, clicked);
 }
 
-#[allow(dead_code)]
 const DEFAULT_VERTEX_SHADER: &'static str = "#version 100
 precision lowp float;
 
@@ -135,7 +127,6 @@ void main() {
 }
 ";
 
-#[allow(dead_code)]
 const ARROW_FRAGMENT_SHADER: &'static str = "#version 100
 precision lowp float;
 
@@ -0,0 +1,167 @@
+// Checks the behaviour of the splitters, magnets, shielded flowers and bombs, run with `cargo test`
+#![allow(dead_code)]
+
+// The game is a single binary, so the simulation modules the world needs are pulled in directly
+#[path = "../../src/simulation"]
+mod simulation {
+    pub mod ball;
+    pub mod body_path;
+    pub mod broadphase;
+    pub mod capsule;
+    pub mod contact_solver;
+    pub mod gravity;
+    pub mod hash_grid;
+    pub mod integrator;
+    pub mod interactions;
+    pub mod quad_tree;
+    pub mod random;
+    pub mod sweep_and_prune;
+    pub mod world;
+}
+
+use macroquad::{color::colors, math::Vec2};
+
+use simulation::{
+    ball::{Ball, BallType},
+    interactions::{InteractionRule, InteractionTable, Outcome},
+    quad_tree::Rect,
+    world::{World, MAGNET_RADIUS},
+};
+
+const SIMULATION_DT: f32 = 1. / 240.;
+
+fn new_ball(position: Vec2, velocity: Vec2, ball_type: BallType) -> Ball {
+    let mut ball = Ball::new(
+        position,
+        velocity,
+        10.,
+        40.,
+        colors::WHITE,
+        0.,
+        0.,
+        ball_type,
+    );
+    ball.set_velocity(velocity, SIMULATION_DT);
+    return ball;
+}
+
+// A world without bodies, so balls move in straight lines
+fn new_world(balls: Vec<Ball>) -> World {
+    let mut world = World::new(Rect::new(-2000., -2000., 4000., 4000.), 0., 0.);
+    world.balls = balls;
+    return world;
+}
+
+fn run(world: &mut World, steps: usize) {
+    for _ in 0..steps {
+        world.step(SIMULATION_DT);
+    }
+}
+
+fn find(world: &World, ball_type: BallType) -> Vec<Ball> {
+    return world
+        .balls
+        .iter()
+        .filter(|ball| ball.ball_type == ball_type)
+        .copied()
+        .collect();
+}
+
+#[test]
+fn splitters_break_in_two_once() {
+    let mut world = new_world(vec![
+        new_ball(
+            Vec2::new(-100., 0.),
+            Vec2::new(600., 0.),
+            BallType::Splitter,
+        ),
+        new_ball(Vec2::ZERO, Vec2::ZERO, BallType::Ball),
+        // Pieces hitting another ball do not split again
+        new_ball(Vec2::new(300., 0.), Vec2::ZERO, BallType::Ball),
+    ]);
+    run(&mut world, 240);
+
+    let pieces = find(&world, BallType::Splitter);
+    assert_eq!(pieces.len(), 2);
+    for piece in pieces {
+        assert!(piece.effect.is_none());
+        assert!((piece.mass - 20.).abs() < 1e-3);
+        assert!(piece.radius < 10.);
+    }
+    assert_eq!(find(&world, BallType::Ball).len(), 2);
+}
+
+#[test]
+fn magnets_pull_the_balls_around_them() {
+    let mut world = new_world(vec![
+        new_ball(Vec2::ZERO, Vec2::ZERO, BallType::Magnet),
+        new_ball(
+            Vec2::new(MAGNET_RADIUS / 2., 0.),
+            Vec2::ZERO,
+            BallType::Ball,
+        ),
+        new_ball(
+            Vec2::new(0., MAGNET_RADIUS * 2.),
+            Vec2::ZERO,
+            BallType::Ball,
+        ),
+    ]);
+    run(&mut world, 10);
+
+    let near = world.balls[1];
+    let far = world.balls[2];
+    assert!(near.velocity.x < 0., "{}", near.velocity);
+    assert!(near.velocity.y.abs() < 1e-3);
+    assert_eq!(far.velocity, Vec2::ZERO);
+    assert_eq!(far.position, Vec2::new(0., MAGNET_RADIUS * 2.));
+}
+
+#[test]
+fn shielded_flowers_survive_one_spike_hit() {
+    let mut world = new_world(vec![
+        new_ball(Vec2::ZERO, Vec2::ZERO, BallType::ShieldedBall),
+        new_ball(Vec2::new(-100., 0.), Vec2::new(600., 0.), BallType::BadBall),
+        new_ball(Vec2::new(300., 0.), Vec2::new(-900., 0.), BallType::BadBall),
+    ]);
+    world.interactions = InteractionTable::new(vec![InteractionRule {
+        ball: BallType::Ball,
+        other: BallType::BadBall,
+        outcomes: vec![Outcome::Destroy],
+    }]);
+
+    // First hit, the flower loses its shield
+    run(&mut world, 40);
+    assert!(find(&world, BallType::ShieldedBall).is_empty());
+    assert_eq!(find(&world, BallType::Ball).len(), 1);
+
+    // Second hit, it is killed
+    run(&mut world, 200);
+    assert!(find(&world, BallType::Ball).is_empty());
+    assert_eq!(find(&world, BallType::BadBall).len(), 2);
+}
+
+#[test]
+fn bombs_push_the_balls_around_them_away() {
+    let neighbours = [Vec2::new(0., 60.), Vec2::new(0., -60.), Vec2::new(60., 0.)];
+    let mut balls = vec![
+        new_ball(Vec2::new(-100., 0.), Vec2::new(600., 0.), BallType::Bomb),
+        new_ball(Vec2::ZERO, Vec2::ZERO, BallType::Ball),
+    ];
+    for position in neighbours {
+        balls.push(new_ball(position, Vec2::ZERO, BallType::GoodBall));
+    }
+    let mut world = new_world(balls);
+    run(&mut world, 40);
+
+    assert!(find(&world, BallType::Bomb).is_empty());
+    let pushed = find(&world, BallType::GoodBall);
+    assert_eq!(pushed.len(), 3);
+    for (ball, start) in pushed.iter().zip(neighbours) {
+        assert!(
+            ball.velocity.dot(start) > 0.,
+            "ball from {} goes {}",
+            start,
+            ball.velocity
+        );
+    }
+}
@@ -0,0 +1,278 @@
+// Checks that every broadphase backend finds the same balls, run with `cargo test`
+#![allow(dead_code)]
+
+// The game is a single binary, so the simulation modules the backends need are pulled in directly
+#[path = "../../src/simulation"]
+mod simulation {
+    pub mod ball;
+    pub mod capsule;
+    pub mod broadphase;
+    pub mod gravity;
+    pub mod hash_grid;
+    pub mod quad_tree;
+    pub mod random;
+    pub mod sweep_and_prune;
+}
+
+use macroquad::{color::colors, math::Vec2};
+
+use simulation::{
+    ball::{Ball, BallType},
+    broadphase::BroadphaseKind,
+    quad_tree::{sort_by_distance, QuadTreeEntry, Rect},
+    random::{random_orbital_pos, Random},
+};
+
+const SIMULATION_DT: f32 = 1. / 240.;
+
+const BACKENDS: [BroadphaseKind; 3] = [
+    BroadphaseKind::QuadTree,
+    BroadphaseKind::HashGrid,
+    BroadphaseKind::SweepAndPrune,
+];
+const TREE_SIZE: f32 = 2000.;
+
+fn new_ball(position: Vec2, radius: f32) -> Ball {
+    return Ball::new(
+        position,
+        Vec2::ZERO,
+        radius,
+        40.,
+        colors::WHITE,
+        0.,
+        0.,
+        BallType::Ball,
+    );
+}
+
+// A garden like ring of balls with a few different radii
+fn get_ring(seed: u64, count: usize) -> Vec<Ball> {
+    let mut random = Random::new(seed);
+    let radii = [5.6, 7., 9.1];
+    return (0..count)
+        .map(|index| {
+            let position = random_orbital_pos(&mut random, Vec2::ZERO, 210., 351.);
+            new_ball(position, radii[index % radii.len()])
+        })
+        .collect();
+}
+
+// Balls piled on a few spots, some of them outside of the quad tree area
+fn get_clusters(seed: u64) -> Vec<Ball> {
+    let mut random = Random::new(seed);
+    let spots = [
+        Vec2::ZERO,
+        Vec2::new(TREE_SIZE / 2., TREE_SIZE / 2.),
+        Vec2::new(-TREE_SIZE, 0.),
+    ];
+    let mut balls = Vec::new();
+    for spot in spots {
+        for _ in 0..100 {
+            let offset = Vec2::new(random.gen_range(-20., 20.), random.gen_range(-20., 20.));
+            balls.push(new_ball(spot + offset, random.gen_range(2., 12.)));
+        }
+        // Balls at the exact same place
+        balls.push(new_ball(spot, 7.));
+        balls.push(new_ball(spot, 7.));
+    }
+    return balls;
+}
+
+// What a backend finds, sorted so that backends can be compared
+fn run_backend(
+    kind: BroadphaseKind,
+    balls: &[Ball],
+    queries: &[Rect],
+) -> (Vec<(usize, usize)>, Vec<Vec<usize>>) {
+    let mut broadphase = kind.create(Rect::new(0., 0., TREE_SIZE, TREE_SIZE));
+    broadphase.update(balls);
+
+    let mut pairs = Vec::new();
+    broadphase.get_candidate_pairs(balls, &mut pairs);
+
+    let mut found = Vec::new();
+    for query in queries {
+        let mut entries: Vec<QuadTreeEntry> = Vec::new();
+        broadphase.query_entries(query, &mut entries);
+        let mut payloads: Vec<usize> = entries.iter().map(|entry| entry.payload).collect();
+        payloads.sort_unstable();
+        found.push(payloads);
+    }
+
+    return (pairs, found);
+}
+
+// Pairs of overlapping collision areas, checked one by one
+fn get_expected_pairs(balls: &[Ball]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for first in 0..balls.len() {
+        for second in first + 1..balls.len() {
+            if balls[first]
+                .get_collision_area()
+                .overlap(&balls[second].get_collision_area())
+            {
+                pairs.push((first, second));
+            }
+        }
+    }
+    return pairs;
+}
+
+fn check_backends_agree(name: &str, balls: &[Ball]) {
+    let queries: Vec<Rect> = balls
+        .iter()
+        .step_by(7)
+        .map(|ball| Rect::new(ball.position.x, ball.position.y, 60., 40.))
+        .collect();
+
+    let expected_pairs = get_expected_pairs(balls);
+    let (reference_pairs, reference_found) = run_backend(BACKENDS[0], balls, &queries);
+    assert_eq!(
+        reference_pairs, expected_pairs,
+        "{}: {:?} pairs differ from the brute force ones",
+        name, BACKENDS[0]
+    );
+
+    for kind in &BACKENDS[1..] {
+        let (pairs, found) = run_backend(*kind, balls, &queries);
+        assert_eq!(pairs, reference_pairs, "{}: {:?} pairs differ", name, kind);
+        assert_eq!(
+            found, reference_found,
+            "{}: {:?} queries differ",
+            name, kind
+        );
+    }
+}
+
+#[test]
+fn backends_agree_on_rings() {
+    for seed in 0..5 {
+        check_backends_agree(&format!("ring {}", seed), &get_ring(seed, 300));
+    }
+    check_backends_agree("crowded ring", &get_ring(42, 3000));
+}
+
+#[test]
+fn backends_agree_on_clusters() {
+    for seed in 0..5 {
+        check_backends_agree(&format!("clusters {}", seed), &get_clusters(seed));
+    }
+}
+
+#[test]
+fn backends_agree_after_moves() {
+    let mut balls = get_ring(7, 500);
+    let mut random = Random::new(7);
+    let mut backends: Vec<_> = BACKENDS
+        .iter()
+        .map(|kind| kind.create(Rect::new(0., 0., TREE_SIZE, TREE_SIZE)))
+        .collect();
+
+    for step in 0..50 {
+        for ball in balls.iter_mut() {
+            ball.position =
+                ball.position + Vec2::new(random.gen_range(-3., 3.), random.gen_range(-3., 3.));
+        }
+        // Balls leaving and coming back, as happens when they are removed or launched
+        if step % 10 == 5 {
+            balls.truncate(balls.len() - 20);
+        }
+        if step % 10 == 9 {
+            balls.extend(get_ring(step, 20));
+        }
+
+        let expected_pairs = get_expected_pairs(&balls);
+        for (kind, broadphase) in BACKENDS.iter().zip(backends.iter_mut()) {
+            let mut pairs = Vec::new();
+            broadphase.update(&balls);
+            broadphase.get_candidate_pairs(&balls, &mut pairs);
+            assert_eq!(
+                pairs, expected_pairs,
+                "step {}: {:?} pairs differ",
+                step, kind
+            );
+        }
+    }
+}
+
+// Closest payloads checked one by one, the closest first
+fn get_expected_nearest(balls: &[Ball], position: Vec2) -> Vec<usize> {
+    let mut entries: Vec<QuadTreeEntry> = balls
+        .iter()
+        .enumerate()
+        .map(|(index, ball)| QuadTreeEntry::new(ball.position, index, ball.mass))
+        .collect();
+    sort_by_distance(&mut entries, position);
+    return entries.iter().map(|entry| entry.payload).collect();
+}
+
+fn check_nearest_queries(name: &str, balls: &[Ball]) {
+    let mut random = Random::new(balls.len() as u64);
+    let mut probes: Vec<Vec2> = balls
+        .iter()
+        .step_by(11)
+        .map(|ball| ball.position + Vec2::new(random.gen_range(-9., 9.), 0.))
+        .collect();
+    // Far from every ball, and outside of the quad tree area
+    probes.push(Vec2::new(TREE_SIZE * 3., -TREE_SIZE));
+    probes.push(Vec2::ZERO);
+
+    for kind in BACKENDS {
+        let mut broadphase = kind.create(Rect::new(0., 0., TREE_SIZE, TREE_SIZE));
+        broadphase.update(balls);
+        let mut entries = Vec::new();
+
+        for probe in &probes {
+            let expected = get_expected_nearest(balls, *probe);
+            let payloads = |entries: &Vec<QuadTreeEntry>| -> Vec<usize> {
+                entries.iter().map(|entry| entry.payload).collect()
+            };
+
+            assert_eq!(
+                broadphase.nearest(*probe).map(|entry| entry.payload),
+                expected.first().copied(),
+                "{}: {:?} nearest to {} differs",
+                name,
+                kind,
+                probe
+            );
+
+            for k in [1, 5, 40] {
+                broadphase.k_nearest(*probe, k, &mut entries);
+                assert_eq!(
+                    payloads(&entries),
+                    expected[..k.min(expected.len())],
+                    "{}: {:?} {} nearest to {} differ",
+                    name,
+                    kind,
+                    k,
+                    probe
+                );
+            }
+
+            let radius = 30.;
+            broadphase.query_circle(*probe, radius, &mut entries);
+            let within = expected
+                .iter()
+                .take_while(|index| balls[**index].position.distance(*probe) <= radius)
+                .count();
+            assert_eq!(
+                payloads(&entries),
+                expected[..within],
+                "{}: {:?} circle around {} differs",
+                name,
+                kind,
+                probe
+            );
+        }
+    }
+}
+
+#[test]
+fn nearest_queries_match_brute_force() {
+    for seed in 0..3 {
+        check_nearest_queries(&format!("ring {}", seed), &get_ring(seed, 400));
+        check_nearest_queries(&format!("clusters {}", seed), &get_clusters(seed));
+    }
+    check_nearest_queries("empty", &[]);
+}
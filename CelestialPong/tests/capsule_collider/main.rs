@@ -0,0 +1,257 @@
+// Checks that balls bounce on barriers and paddles, run with `cargo test`
+#![allow(dead_code)]
+
+// The game is a single binary, so the simulation modules the world needs are pulled in directly
+#[path = "../../src/simulation"]
+mod simulation {
+    pub mod ball;
+    pub mod body_path;
+    pub mod broadphase;
+    pub mod capsule;
+    pub mod contact_solver;
+    pub mod gravity;
+    pub mod hash_grid;
+    pub mod integrator;
+    pub mod interactions;
+    pub mod quad_tree;
+    pub mod random;
+    pub mod sweep_and_prune;
+    pub mod world;
+}
+
+use macroquad::{color::colors, math::Vec2};
+
+use simulation::{
+    ball::{Ball, BallType},
+    broadphase::BroadphaseKind,
+    capsule::Capsule,
+    quad_tree::Rect,
+    world::{World, WorldEvent},
+};
+
+const SIMULATION_DT: f32 = 1. / 240.;
+
+const BACKENDS: [BroadphaseKind; 3] = [
+    BroadphaseKind::QuadTree,
+    BroadphaseKind::HashGrid,
+    BroadphaseKind::SweepAndPrune,
+];
+
+fn new_ball(position: Vec2, velocity: Vec2, radius: f32, ball_type: BallType) -> Ball {
+    let mut ball = Ball::new(
+        position,
+        velocity,
+        radius,
+        40.,
+        colors::WHITE,
+        0.,
+        0.,
+        ball_type,
+    );
+    ball.set_velocity(velocity, SIMULATION_DT);
+    return ball;
+}
+
+// A world without bodies, so balls move in straight lines, with a vertical barrier at x = 0
+fn new_world(broadphase: BroadphaseKind, balls: Vec<Ball>, radius: f32) -> World {
+    let mut world = World::new(Rect::new(0., 0., 4000., 4000.), 0., 0.);
+    world.balls = balls;
+    world.capsules.push(Capsule::new(
+        Vec2::new(0., -100.),
+        Vec2::new(0., 100.),
+        radius,
+        colors::WHITE,
+    ));
+    world.set_broadphase(broadphase);
+    return world;
+}
+
+// Steps until the ball hits the capsule, giving the step at which it did
+fn find_first_hit(world: &mut World, ball_index: usize) -> Option<usize> {
+    for step in 0..2000 {
+        let hit = world.step(SIMULATION_DT).iter().any(|event| {
+            matches!(event, WorldEvent::CapsuleHit { capsule_index: 0, ball_index: hit, .. } if *hit == ball_index)
+        });
+        if hit {
+            return Some(step);
+        }
+    }
+    return None;
+}
+
+#[test]
+fn balls_bounce_back_on_barriers() {
+    for broadphase in BACKENDS {
+        let velocity = Vec2::new(300., 40.);
+        let ball = new_ball(Vec2::new(-100., 0.), velocity, 3., BallType::Ball);
+        let mut world = new_world(broadphase, vec![ball], 4.);
+        assert!(find_first_hit(&mut world, 0).is_some(), "{:?}", broadphase);
+
+        // Reflected along the normal of the barrier, the speed along it is kept
+        let ball = world.balls[0];
+        assert!(
+            ball.position.x <= -7.,
+            "{:?}: {}",
+            broadphase,
+            ball.position
+        );
+        assert!(
+            (ball.velocity - Vec2::new(-300., 40.)).length() < 1.,
+            "{:?}: {}",
+            broadphase,
+            ball.velocity
+        );
+    }
+}
+
+#[test]
+fn balls_bounce_on_the_ends_of_barriers() {
+    // Coming straight at the rounded end, the ball goes back the way it came
+    let velocity = Vec2::new(0., -300.);
+    let ball = new_ball(Vec2::new(0., 200.), velocity, 3., BallType::Ball);
+    let mut world = new_world(BroadphaseKind::QuadTree, vec![ball], 4.);
+    assert!(find_first_hit(&mut world, 0).is_some());
+    let ball = world.balls[0];
+    assert!(ball.position.y >= 107.);
+    assert!((ball.velocity - Vec2::new(0., 300.)).length() < 1.);
+}
+
+#[test]
+fn fast_projectiles_do_not_go_through_thin_barriers() {
+    for broadphase in BACKENDS {
+        for speed in [900., 8000., 20000.] {
+            let velocity = Vec2::new(speed, 0.);
+            let ball = new_ball(Vec2::new(-700., 10.), velocity, 2., BallType::Projectil);
+            let mut world = new_world(broadphase, vec![ball], 1.);
+            assert!(
+                find_first_hit(&mut world, 0).is_some(),
+                "{:?}: projectile at {} went through",
+                broadphase,
+                speed
+            );
+            let ball = world.balls[0];
+            assert!(ball.position.x < 0., "{:?}: {}", broadphase, ball.position);
+            assert!(ball.velocity.x < 0., "{:?}: {}", broadphase, ball.velocity);
+        }
+    }
+}
+
+#[test]
+fn moving_paddles_throw_balls() {
+    // The ball sits still in the way of a paddle moving right
+    let ball = new_ball(Vec2::new(20., 0.), Vec2::ZERO, 3., BallType::Ball);
+    let mut world = new_world(BroadphaseKind::QuadTree, vec![ball], 4.);
+    let paddle_speed = 200.;
+    let mut hit = false;
+    for _ in 0..240 {
+        let capsule = world.capsules[0];
+        let offset = Vec2::new(paddle_speed * SIMULATION_DT, 0.);
+        world.capsules[0].move_to(capsule.p1 + offset, capsule.p2 + offset, SIMULATION_DT);
+        let events = world.step(SIMULATION_DT);
+        if events
+            .iter()
+            .any(|event| matches!(event, WorldEvent::CapsuleHit { .. }))
+        {
+            hit = true;
+        }
+    }
+
+    assert!(hit);
+    // An elastic bounce on a moving wall sends the ball at twice its speed, ahead of it
+    let ball = world.balls[0];
+    assert!(
+        (ball.velocity.x - paddle_speed * 2.).abs() < 1.,
+        "{}",
+        ball.velocity
+    );
+    assert!(ball.position.x > world.capsules[0].p1.x + 7.);
+}
+
+#[test]
+fn balls_away_from_barriers_are_left_alone() {
+    let velocity = Vec2::new(0., 300.);
+    let ball = new_ball(Vec2::new(-8., -200.), velocity, 3., BallType::Ball);
+    let mut world = new_world(BroadphaseKind::QuadTree, vec![ball], 4.);
+    assert_eq!(find_first_hit(&mut world, 0), None);
+    assert!((world.balls[0].velocity - velocity).length() < 0.01);
+}
+
+#[test]
+fn paddles_turn_balls_toward_the_end_they_hit() {
+    let max_angle = std::f32::consts::FRAC_PI_4;
+    // Hitting the middle, the lower end and the upper end of the barrier
+    for (offset, angle) in [(0., 0.), (100., max_angle), (-50., -max_angle / 2.)] {
+        let velocity = Vec2::new(300., 0.);
+        let ball = new_ball(Vec2::new(-100., offset), velocity, 3., BallType::Ball);
+        let mut world = new_world(BroadphaseKind::QuadTree, vec![ball], 4.);
+        world.capsules[0].deflection = max_angle;
+        assert!(find_first_hit(&mut world, 0).is_some());
+
+        let ball = world.balls[0];
+        let expected = Vec2::from_angle(std::f32::consts::PI - angle) * 300.;
+        assert!(
+            (ball.velocity - expected).length() < 1.,
+            "hit at {}: {}, expected {}",
+            offset,
+            ball.velocity,
+            expected
+        );
+    }
+}
+
+#[test]
+fn capsule_hits_report_the_bounced_ball() {
+    let ball = new_ball(
+        Vec2::new(-20., 0.),
+        Vec2::new(300., 0.),
+        3.,
+        BallType::GoodBall,
+    );
+    let mut world = new_world(BroadphaseKind::QuadTree, vec![ball], 4.);
+    for _ in 0..240 {
+        let events = world.step(SIMULATION_DT).to_vec();
+        match events.iter().find_map(|event| match event {
+            WorldEvent::CapsuleHit { ball, .. } => Some(*ball),
+            _ => None,
+        }) {
+            Some(ball) => {
+                assert_eq!(ball.ball_type, BallType::GoodBall);
+                assert!(ball.velocity.x < 0.);
+                assert_eq!(ball, world.balls[0]);
+                return;
+            }
+            None => {}
+        }
+    }
+    panic!("the ball never hit the barrier");
+}
+
+#[test]
+fn balls_removed_between_steps_are_reported() {
+    let balls = (0..4)
+        .map(|index| {
+            new_ball(
+                Vec2::new(-200., index as f32 * 20.),
+                Vec2::ZERO,
+                3.,
+                BallType::GoodBall,
+            )
+        })
+        .collect();
+    let mut world = new_world(BroadphaseKind::QuadTree, balls, 4.);
+    world.grabbed_ball = Some(3);
+    let kept = world.balls[3];
+
+    let removed: Vec<usize> = world
+        .remove_balls(&[2, 0])
+        .iter()
+        .filter_map(|event| match event {
+            WorldEvent::Removed { index, .. } => Some(*index),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(removed, vec![2, 0]);
+    assert_eq!(world.balls.len(), 2);
+    assert_eq!(world.grabbed_ball, Some(1));
+    assert_eq!(world.balls[1], kept);
+}
@@ -0,0 +1,400 @@
+// Checks the contact solver on chains and piles of balls, run with `cargo test`
+#![allow(dead_code)]
+
+// The game is a single binary, so the simulation modules the solver needs are pulled in directly
+#[path = "../../src/simulation"]
+mod simulation {
+    pub mod ball;
+    pub mod capsule;
+    pub mod contact_solver;
+    pub mod gravity;
+    pub mod quad_tree;
+}
+
+use macroquad::{color::colors, math::Vec2};
+
+use simulation::{
+    ball::{Ball, BallMaterial, BallType},
+    contact_solver::{bounce_on_body, ContactSolver},
+};
+
+const SIMULATION_DT: f32 = 1. / 240.;
+const DT: f32 = SIMULATION_DT;
+const RADIUS: f32 = 7.;
+
+fn new_ball(position: Vec2, velocity: Vec2, mass: f32) -> Ball {
+    let mut ball = Ball::new(
+        position,
+        velocity,
+        RADIUS,
+        mass,
+        colors::WHITE,
+        0.,
+        0.,
+        BallType::Ball,
+    );
+    ball.set_velocity(velocity, DT);
+    return ball;
+}
+
+fn get_all_pairs(balls: &[Ball]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for first in 0..balls.len() {
+        for second in first + 1..balls.len() {
+            pairs.push((first, second));
+        }
+    }
+    return pairs;
+}
+
+fn solve(balls: &mut [Ball]) -> usize {
+    let mut solver = ContactSolver::new();
+    solver.find_contacts(balls, &get_all_pairs(balls));
+    solver.solve(balls, DT);
+    return solver.get_contacts().len();
+}
+
+fn get_momentum(balls: &[Ball]) -> Vec2 {
+    return balls
+        .iter()
+        .fold(Vec2::ZERO, |sum, ball| sum + ball.velocity * ball.mass);
+}
+
+fn get_kinetic_energy(balls: &[Ball]) -> f32 {
+    return balls
+        .iter()
+        .map(|ball| 0.5 * ball.mass * ball.velocity.length_squared())
+        .sum();
+}
+
+fn get_max_overlap(balls: &[Ball]) -> f32 {
+    let mut max_overlap: f32 = 0.;
+    for (first, second) in get_all_pairs(balls) {
+        let distance = balls[first].position.distance(balls[second].position);
+        max_overlap = max_overlap.max(balls[first].radius + balls[second].radius - distance);
+    }
+    return max_overlap;
+}
+
+// Touching balls must not be moving into each other anymore
+fn assert_no_closing_contact(balls: &[Ball]) {
+    for (first, second) in get_all_pairs(balls) {
+        let (a, b) = (&balls[first], &balls[second]);
+        if a.check_collision(b) {
+            let normal = (b.position - a.position).normalize();
+            let separating_speed = (b.velocity - a.velocity).dot(normal);
+            assert!(
+                separating_speed > -0.01,
+                "balls {} and {} still close at {}",
+                first,
+                second,
+                separating_speed
+            );
+        }
+    }
+}
+
+fn assert_close(value: f32, expected: f32, what: &str) {
+    assert!(
+        (value - expected).abs() <= expected.abs() * 1e-3 + 1e-3,
+        "{} is {}, expected {}",
+        what,
+        value,
+        expected
+    );
+}
+
+#[test]
+fn two_balls_bounce_elastically() {
+    let mut balls = vec![
+        new_ball(Vec2::new(0., 0.), Vec2::new(100., 0.), 40.),
+        new_ball(Vec2::new(RADIUS * 2., 0.), Vec2::new(-100., 0.), 40.),
+    ];
+    assert_eq!(solve(&mut balls), 1);
+    assert_close(balls[0].velocity.x, -100., "first velocity");
+    assert_close(balls[1].velocity.x, 100., "second velocity");
+}
+
+#[test]
+fn three_ball_chain_passes_the_push() {
+    // A ball hitting the end of a row of two resting ones
+    let mut balls = vec![
+        new_ball(Vec2::new(0., 0.), Vec2::new(100., 0.), 40.),
+        new_ball(Vec2::new(RADIUS * 2., 0.), Vec2::ZERO, 40.),
+        new_ball(Vec2::new(RADIUS * 4., 0.), Vec2::ZERO, 40.),
+    ];
+    let momentum = get_momentum(&balls);
+    let energy = get_kinetic_energy(&balls);
+
+    assert_eq!(solve(&mut balls), 2);
+    assert_no_closing_contact(&balls);
+    assert_close(get_momentum(&balls).x, momentum.x, "momentum");
+    assert!(get_kinetic_energy(&balls) <= energy * 1.001);
+    // Both contacts were solved, the last ball moves away from the one behind it
+    assert!(balls[2].velocity.x > 0.);
+    assert!(balls[2].velocity.x >= balls[1].velocity.x - 0.01);
+}
+
+#[test]
+fn three_ball_chain_with_different_masses() {
+    let mut balls = vec![
+        new_ball(Vec2::new(0., 0.), Vec2::new(0., 60.), 120.),
+        new_ball(Vec2::new(0., RADIUS * 2.), Vec2::ZERO, 40.),
+        new_ball(Vec2::new(0., RADIUS * 4.), Vec2::new(0., -30.), 10.),
+    ];
+    let momentum = get_momentum(&balls);
+    let energy = get_kinetic_energy(&balls);
+
+    assert_eq!(solve(&mut balls), 2);
+    assert_no_closing_contact(&balls);
+    assert_close(get_momentum(&balls).y, momentum.y, "momentum");
+    assert!(get_kinetic_energy(&balls) <= energy * 1.001);
+}
+
+#[test]
+fn head_on_clusters() {
+    // Two packed clusters of balls thrown at each other
+    let mut balls = Vec::new();
+    for (center, velocity) in [(-RADIUS * 6., 80.), (RADIUS * 6. - 0.5, -80.)] {
+        for row in 0..3 {
+            for column in 0..3 {
+                let offset = Vec2::new(column as f32 - 1., row as f32 - 1.) * RADIUS * 2.;
+                balls.push(new_ball(
+                    Vec2::new(center, 0.) + offset,
+                    Vec2::new(velocity, 0.),
+                    40.,
+                ));
+            }
+        }
+    }
+    let energy = get_kinetic_energy(&balls);
+
+    assert!(solve(&mut balls) > 18);
+    assert_no_closing_contact(&balls);
+    assert_close(get_momentum(&balls).x, 0., "momentum");
+    assert_close(get_momentum(&balls).y, 0., "momentum");
+    assert!(get_kinetic_energy(&balls) <= energy * 1.001);
+}
+
+#[test]
+fn overlapping_balls_are_pushed_apart() {
+    let mut balls = vec![
+        new_ball(Vec2::new(0., 0.), Vec2::ZERO, 40.),
+        new_ball(Vec2::new(RADIUS, 0.), Vec2::ZERO, 40.),
+        // At the exact same place as the first one
+        new_ball(Vec2::new(0., 0.), Vec2::ZERO, 40.),
+    ];
+    for _ in 0..30 {
+        solve(&mut balls);
+    }
+    assert!(get_max_overlap(&balls) < 0.1);
+    // Pushing balls apart does not make them move by themselves
+    for ball in &balls {
+        assert!((ball.position - ball.prev_position - ball.velocity * DT).length() < 1e-4);
+        assert!(ball.velocity.length() < 1e-3);
+    }
+}
+
+// Balls falling toward a point, as in a crowded orbit
+fn get_pile() -> Vec<Ball> {
+    let mut balls = Vec::new();
+    for index in 0..40 {
+        let angle = index as f32 * 2.4;
+        let distance = 20. + index as f32 * 3.;
+        balls.push(new_ball(
+            Vec2::from_angle(angle) * distance,
+            Vec2::ZERO,
+            40.,
+        ));
+    }
+    return balls;
+}
+
+const PILE_PULL: f32 = 400.;
+
+fn get_pile_energy(balls: &[Ball]) -> f32 {
+    let potential_energy: f32 = balls
+        .iter()
+        .map(|ball| ball.mass * PILE_PULL * ball.position.length())
+        .sum();
+    return get_kinetic_energy(balls) + potential_energy;
+}
+
+// Steps the pile, checking the balls never sink far into each other
+fn run_pile(balls: &mut [Ball], solver: &mut ContactSolver, steps: usize) {
+    for step in 0..steps {
+        for ball in balls.iter_mut() {
+            let pull = -ball.position.normalize_or_zero() * PILE_PULL;
+            ball.update_verlet(DT, pull);
+        }
+        solver.find_contacts(balls, &get_all_pairs(balls));
+        solver.solve(balls, DT);
+
+        let max_overlap = get_max_overlap(balls);
+        assert!(
+            max_overlap < RADIUS * 0.5,
+            "step {}: overlap of {}",
+            step,
+            max_overlap
+        );
+    }
+}
+
+#[test]
+fn elastic_pile_does_not_gain_energy() {
+    let mut balls = get_pile();
+    let energy = get_pile_energy(&balls);
+
+    run_pile(&mut balls, &mut ContactSolver::new(), 4000);
+    let end_energy = get_pile_energy(&balls);
+    assert!(
+        end_energy <= energy * 1.01,
+        "energy went from {} to {}",
+        energy,
+        end_energy
+    );
+}
+
+#[test]
+fn damped_pile_settles() {
+    let mut balls = get_pile();
+    for ball in balls.iter_mut() {
+        ball.material.restitution = 0.5;
+    }
+
+    run_pile(&mut balls, &mut ContactSolver::new(), 4000);
+    let max_speed = balls
+        .iter()
+        .map(|ball| ball.velocity.length())
+        .fold(0., f32::max);
+    assert!(max_speed < 5., "pile still moving at {}", max_speed);
+    assert!(get_max_overlap(&balls) < 0.5);
+}
+
+// Angular momentum around a point away from the balls, of the balls moving and of their spin
+fn get_angular_momentum(balls: &[Ball]) -> f32 {
+    let pivot = Vec2::new(-50., -30.);
+    return balls
+        .iter()
+        .map(|ball| {
+            ball.mass * (ball.position - pivot).perp_dot(ball.velocity)
+                + ball.get_inertia() * ball.spin
+        })
+        .sum();
+}
+
+fn get_total_energy(balls: &[Ball]) -> f32 {
+    let spin_energy: f32 = balls
+        .iter()
+        .map(|ball| 0.5 * ball.get_inertia() * ball.spin * ball.spin)
+        .sum();
+    return get_kinetic_energy(balls) + spin_energy;
+}
+
+const ROUGH: BallMaterial = BallMaterial {
+    restitution: 1.,
+    friction: 0.5,
+};
+
+#[test]
+fn restitution_keeps_part_of_the_speed() {
+    let mut balls = vec![
+        new_ball(Vec2::new(0., 0.), Vec2::new(100., 0.), 40.),
+        new_ball(Vec2::new(RADIUS * 2., 0.), Vec2::new(-100., 0.), 40.),
+    ];
+    balls[0].material.restitution = 0.5;
+    balls[1].material.restitution = 0.2;
+
+    solve(&mut balls);
+    // The bounciest material wins
+    assert_close(balls[0].velocity.x, -50., "first velocity");
+    assert_close(balls[1].velocity.x, 50., "second velocity");
+}
+
+#[test]
+fn glancing_hit_with_friction_spins_the_balls() {
+    let mut balls = vec![
+        new_ball(Vec2::new(0., 0.), Vec2::new(200., 0.), 40.),
+        new_ball(Vec2::new(RADIUS * 1.2, RADIUS * 1.6), Vec2::ZERO, 40.),
+    ];
+    balls[0].material = ROUGH;
+    balls[1].material = ROUGH;
+    let momentum = get_momentum(&balls);
+    let angular_momentum = get_angular_momentum(&balls);
+    let energy = get_total_energy(&balls);
+
+    solve(&mut balls);
+    assert!(balls[0].spin != 0. && balls[1].spin != 0.);
+    // Rubbing turns both balls the same way
+    assert!(balls[0].spin * balls[1].spin > 0.);
+    assert_close(get_momentum(&balls).x, momentum.x, "momentum");
+    assert_close(get_momentum(&balls).y, momentum.y, "momentum");
+    assert_close(
+        get_angular_momentum(&balls),
+        angular_momentum,
+        "angular momentum",
+    );
+    assert!(get_total_energy(&balls) <= energy * 1.001);
+}
+
+#[test]
+fn smooth_balls_keep_their_spin() {
+    let mut balls = vec![
+        new_ball(Vec2::new(0., 0.), Vec2::new(200., 0.), 40.),
+        new_ball(Vec2::new(RADIUS * 1.2, RADIUS * 1.6), Vec2::ZERO, 40.),
+    ];
+    balls[0].spin = 3.;
+
+    solve(&mut balls);
+    assert_eq!(balls[0].spin, 3.);
+    assert_eq!(balls[1].spin, 0.);
+}
+
+#[test]
+fn spinning_ball_throws_the_other_sideways() {
+    let mut balls = vec![
+        new_ball(Vec2::new(0., 0.), Vec2::new(100., 0.), 40.),
+        new_ball(Vec2::new(RADIUS * 2., 0.), Vec2::ZERO, 40.),
+    ];
+    balls[0].material = ROUGH;
+    balls[1].material = ROUGH;
+    balls[0].spin = 20.;
+    let angular_momentum = get_angular_momentum(&balls);
+
+    solve(&mut balls);
+    // The spinning edge drags the other ball along it
+    assert!(balls[1].velocity.y.abs() > 1.);
+    assert!(balls[0].spin < 20.);
+    assert_close(
+        get_angular_momentum(&balls),
+        angular_momentum,
+        "angular momentum",
+    );
+}
+
+#[test]
+fn balls_bounce_on_bodies() {
+    let mut body = new_ball(Vec2::ZERO, Vec2::ZERO, 5000000.);
+    body.ball_type = BallType::Body;
+    body.radius = 30.;
+    let mut ball = new_ball(Vec2::new(35., 0.), Vec2::new(-100., 20.), 40.);
+
+    bounce_on_body(&mut ball, &body, 0.9, DT);
+    // Pushed out of the body
+    assert_close(ball.position.length(), 30. + RADIUS, "distance to the body");
+    assert_close(ball.velocity.x, 90., "normal velocity");
+    assert_close(ball.velocity.y, 20., "tangent velocity");
+    assert!((ball.position - ball.prev_position - ball.velocity * DT).length() < 1e-4);
+
+    // Friction against the body spins the ball
+    let mut ball = new_ball(Vec2::new(35., 0.), Vec2::new(-100., 20.), 40.);
+    ball.material = ROUGH;
+    bounce_on_body(&mut ball, &body, 0.9, DT);
+    assert!(ball.velocity.y.abs() < 20.);
+    assert!(ball.spin != 0.);
+
+    // Balls already leaving are only pushed out
+    let mut ball = new_ball(Vec2::new(35., 0.), Vec2::new(50., 0.), 40.);
+    bounce_on_body(&mut ball, &body, 0.9, DT);
+    assert_close(ball.velocity.x, 50., "leaving velocity");
+}
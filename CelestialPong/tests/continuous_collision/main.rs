@@ -0,0 +1,177 @@
+// Checks that fast balls hit small ones instead of jumping over them, run with `cargo test`
+#![allow(dead_code)]
+
+// The game is a single binary, so the simulation modules the world needs are pulled in directly
+#[path = "../../src/simulation"]
+mod simulation {
+    pub mod ball;
+    pub mod body_path;
+    pub mod broadphase;
+    pub mod capsule;
+    pub mod contact_solver;
+    pub mod gravity;
+    pub mod hash_grid;
+    pub mod integrator;
+    pub mod interactions;
+    pub mod quad_tree;
+    pub mod random;
+    pub mod sweep_and_prune;
+    pub mod world;
+}
+
+use macroquad::{color::colors, math::Vec2};
+
+use simulation::{
+    ball::{Ball, BallType},
+    broadphase::BroadphaseKind,
+    quad_tree::Rect,
+    world::{World, WorldEvent},
+};
+
+const SIMULATION_DT: f32 = 1. / 240.;
+
+const BACKENDS: [BroadphaseKind; 3] = [
+    BroadphaseKind::QuadTree,
+    BroadphaseKind::HashGrid,
+    BroadphaseKind::SweepAndPrune,
+];
+// Pixels per second, the fastest crosses more than 80 pixels in a step
+const SPEEDS: [f32; 5] = [100., 900., 2500., 8000., 20000.];
+
+fn new_ball(position: Vec2, velocity: Vec2, radius: f32, ball_type: BallType) -> Ball {
+    let mut ball = Ball::new(
+        position,
+        velocity,
+        radius,
+        40.,
+        colors::WHITE,
+        0.,
+        0.,
+        ball_type,
+    );
+    ball.set_velocity(velocity, SIMULATION_DT);
+    return ball;
+}
+
+// A world without bodies, so balls move in straight lines
+fn new_world(broadphase: BroadphaseKind, balls: Vec<Ball>) -> World {
+    let mut world = World::new(Rect::new(0., 0., 4000., 4000.), 0., 0.);
+    world.balls = balls;
+    world.set_broadphase(broadphase);
+    return world;
+}
+
+// Steps until the balls first touch, giving the step at which they did
+fn find_first_collision(world: &mut World, first: usize, second: usize) -> Option<usize> {
+    for step in 0..2000 {
+        let hit = world.step(SIMULATION_DT).iter().any(|event| {
+            *event
+                == WorldEvent::Collision {
+                    first: first.min(second),
+                    second: first.max(second),
+                }
+        });
+        if hit {
+            return Some(step);
+        }
+    }
+    return None;
+}
+
+// A ball thrown at a thin one from far enough to need a few steps at the highest speed
+fn fire(
+    broadphase: BroadphaseKind,
+    ball_type: BallType,
+    speed: f32,
+    offset: f32,
+    target_radius: f32,
+) -> (World, Option<usize>) {
+    let start = Vec2::new(-700., offset);
+    let balls = vec![
+        new_ball(Vec2::ZERO, Vec2::ZERO, target_radius, BallType::Ball),
+        new_ball(start, Vec2::new(speed, 0.), 2., ball_type),
+    ];
+    let mut world = new_world(broadphase, balls);
+    let step = find_first_collision(&mut world, 0, 1);
+    return (world, step);
+}
+
+#[test]
+fn projectiles_hit_thin_targets_at_every_speed() {
+    for broadphase in BACKENDS {
+        for speed in SPEEDS {
+            for offset in [0., 1.5, -2.9] {
+                let (world, step) = fire(broadphase, BallType::Projectil, speed, offset, 1.);
+                assert!(
+                    step.is_some(),
+                    "{:?}: projectile at {} with offset {} missed",
+                    broadphase,
+                    speed,
+                    offset
+                );
+                // The target was pushed forward
+                assert!(
+                    world.balls[0].velocity.x > 0.,
+                    "{:?}: target not pushed at {}",
+                    broadphase,
+                    speed
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn fast_balls_hit_thin_targets() {
+    for broadphase in BACKENDS {
+        for speed in SPEEDS {
+            let (_, step) = fire(broadphase, BallType::Ball, speed, 2., 0.5);
+            assert!(step.is_some(), "{:?}: ball at {} missed", broadphase, speed);
+        }
+    }
+}
+
+#[test]
+fn hits_happen_at_the_first_contact() {
+    // The ball touches the target when 697 pixels were crossed, at any speed
+    for speed in SPEEDS {
+        let (world, step) = fire(BroadphaseKind::QuadTree, BallType::Projectil, speed, 0., 1.);
+        let step = step.unwrap();
+        let expected_step = (697. / (speed * SIMULATION_DT)).ceil() as usize - 1;
+        assert!(
+            step <= expected_step + 1,
+            "hit at step {} at {}, expected {}",
+            step,
+            speed,
+            expected_step
+        );
+        // The projectile never went through the target
+        assert!(world.balls[1].position.x < world.balls[0].position.x);
+    }
+}
+
+#[test]
+fn passing_balls_are_not_hit() {
+    for broadphase in BACKENDS {
+        for speed in SPEEDS {
+            let (world, step) = fire(broadphase, BallType::Projectil, speed, 3.1, 1.);
+            assert_eq!(step, None, "{:?}: hit at {}", broadphase, speed);
+            assert_eq!(world.balls[0].velocity, Vec2::ZERO);
+        }
+    }
+}
+
+#[test]
+fn fast_ball_hits_the_first_ball_on_its_way() {
+    for broadphase in BACKENDS {
+        let balls = vec![
+            new_ball(Vec2::new(60., 0.), Vec2::ZERO, 1., BallType::Ball),
+            new_ball(Vec2::new(30., 0.), Vec2::ZERO, 1., BallType::Ball),
+            new_ball(Vec2::ZERO, Vec2::new(24000., 0.), 2., BallType::Projectil),
+        ];
+        let mut world = new_world(broadphase, balls);
+        let step = find_first_collision(&mut world, 1, 2);
+        assert_eq!(step, Some(0), "{:?}", broadphase);
+        assert_eq!(world.balls[0].velocity, Vec2::ZERO, "{:?}", broadphase);
+    }
+}
@@ -0,0 +1,158 @@
+// Checks that planted flowers grow, spread seeds and die to the spikes, run with `cargo test`
+#![allow(dead_code)]
+
+// The game is a single binary, so the modules the flowers need are pulled in directly
+#[path = "../../src/simulation"]
+mod simulation {
+    pub mod ball;
+    pub mod body_path;
+    pub mod broadphase;
+    pub mod capsule;
+    pub mod contact_solver;
+    pub mod gravity;
+    pub mod hash_grid;
+    pub mod integrator;
+    pub mod interactions;
+    pub mod quad_tree;
+    pub mod random;
+    pub mod sweep_and_prune;
+    pub mod world;
+}
+
+#[path = "../../src/levels"]
+mod levels {
+    pub mod flowers;
+    pub mod level_definition;
+    pub mod objectives;
+    pub mod weapon;
+}
+
+use macroquad::{color::colors, math::Vec2};
+
+use levels::{
+    flowers::{FlowerGarden, GROWTH_STAGES},
+    level_definition::GrowthDefinition,
+};
+use simulation::{
+    ball::{Ball, BallType},
+    body_path::BodyPath,
+    quad_tree::Rect,
+    world::{World, WorldEvent},
+};
+
+const SIMULATION_DT: f32 = 1. / 240.;
+
+fn new_body() -> Ball {
+    return Ball::new(
+        Vec2::ZERO,
+        Vec2::ZERO,
+        90.,
+        10000000.,
+        colors::WHITE,
+        0.,
+        0.,
+        BallType::Body,
+    );
+}
+
+// Ball touching the body on the side of the direction
+fn new_landed_ball(direction: Vec2, ball_type: BallType) -> Ball {
+    return Ball::new(
+        direction.normalize() * 95.,
+        Vec2::ZERO,
+        5.6,
+        40.,
+        colors::WHITE,
+        0.,
+        0.,
+        ball_type,
+    );
+}
+
+fn new_garden() -> FlowerGarden {
+    return FlowerGarden::new(GrowthDefinition {
+        stage_time: 1.,
+        health: 2,
+        damage_radius: 30.,
+        seed_interval: 0.5,
+        seeds_per_flower: 2,
+        stage_scores: [1, 2, 3, 10],
+    });
+}
+
+fn grow(garden: &mut FlowerGarden, seconds: f32, bodies: &[Ball], balls: &mut Vec<Ball>) {
+    for _ in 0..(seconds / SIMULATION_DT).round() as usize {
+        garden.update(SIMULATION_DT, bodies, balls);
+    }
+}
+
+#[test]
+fn flowers_grow_through_the_stages() {
+    let body = new_body();
+    let mut garden = new_garden();
+    garden.plant(0, &body, &new_landed_ball(Vec2::X, BallType::GoodBall), 0.);
+    assert_eq!(garden.get_score(), 1);
+
+    let mut balls = Vec::new();
+    for stage in 1..GROWTH_STAGES {
+        grow(&mut garden, 1.01, &[body], &mut balls);
+        assert_eq!(garden.flowers[0].stage, stage);
+    }
+    assert_eq!(garden.get_grown_count(), 1);
+    assert_eq!(garden.get_score(), 10);
+    assert!(balls.is_empty());
+}
+
+#[test]
+fn grown_flowers_spread_seeds_into_orbit() {
+    let body = new_body();
+    let mut garden = new_garden();
+    garden.plant(0, &body, &new_landed_ball(Vec2::Y, BallType::GoodBall), 0.);
+
+    let mut world = World::new(Rect::new(-2000., -2000., 4000., 4000.), 0., 0.);
+    world.add_body(body, BodyPath::Static, SIMULATION_DT);
+    grow(&mut garden, 10., &[body], &mut world.balls);
+
+    // Every seed was released, and no more
+    assert_eq!(world.balls.len(), 2);
+    for seed in &world.balls {
+        assert_eq!(seed.ball_type, BallType::GoodBall);
+        assert!(seed.position.y > body.radius);
+    }
+
+    // Seeds go around the body instead of landing back right away
+    for _ in 0..240 {
+        let landed = world
+            .step(SIMULATION_DT)
+            .iter()
+            .any(|event| matches!(event, WorldEvent::BodyImpact { .. }));
+        assert!(!landed);
+    }
+    assert!(world
+        .balls
+        .iter()
+        .all(|seed| seed.position.length() > body.radius + 10.));
+}
+
+#[test]
+fn spikes_damage_the_flowers_close_to_their_impact() {
+    let body = new_body();
+    let mut garden = new_garden();
+    garden.plant(0, &body, &new_landed_ball(Vec2::X, BallType::GoodBall), 0.);
+    garden.plant(0, &body, &new_landed_ball(-Vec2::X, BallType::GoodBall), 0.);
+
+    let spike = new_landed_ball(Vec2::new(1., 0.1), BallType::BadBall);
+    garden.damage(0, &body, &spike);
+    assert_eq!(garden.flowers.len(), 2);
+    assert_eq!(garden.flowers[0].health, 1);
+    assert_eq!(garden.flowers[1].health, 2);
+
+    // Spikes landing on another body leave the flowers alone
+    garden.damage(1, &body, &spike);
+    assert_eq!(garden.flowers[0].health, 1);
+
+    garden.damage(0, &body, &spike);
+    assert_eq!(garden.flowers.len(), 1);
+    assert!(garden.flowers[0].offset.x < 0.);
+    assert_eq!(garden.get_score(), 1);
+}
@@ -0,0 +1,188 @@
+// Checks the splitting and blasting projectiles, run with `cargo test`
+#![allow(dead_code)]
+
+// The game is a single binary, so the simulation modules the world needs are pulled in directly
+#[path = "../../src/simulation"]
+mod simulation {
+    pub mod ball;
+    pub mod body_path;
+    pub mod broadphase;
+    pub mod capsule;
+    pub mod contact_solver;
+    pub mod gravity;
+    pub mod hash_grid;
+    pub mod integrator;
+    pub mod interactions;
+    pub mod quad_tree;
+    pub mod random;
+    pub mod sweep_and_prune;
+    pub mod world;
+}
+
+use macroquad::{color::colors, math::Vec2};
+
+use simulation::{
+    ball::{Ball, BallType, ImpactEffect},
+    broadphase::BroadphaseKind,
+    quad_tree::Rect,
+    world::{World, WorldEvent},
+};
+
+const SIMULATION_DT: f32 = 1. / 240.;
+
+const BACKENDS: [BroadphaseKind; 3] = [
+    BroadphaseKind::QuadTree,
+    BroadphaseKind::HashGrid,
+    BroadphaseKind::SweepAndPrune,
+];
+
+fn new_ball(position: Vec2, velocity: Vec2, radius: f32, mass: f32, ball_type: BallType) -> Ball {
+    let mut ball = Ball::new(
+        position,
+        velocity,
+        radius,
+        mass,
+        colors::WHITE,
+        0.,
+        0.,
+        ball_type,
+    );
+    ball.set_velocity(velocity, SIMULATION_DT);
+    return ball;
+}
+
+// A projectile with the effect heading to a resting ball at the origin, without bodies so balls
+// move in straight lines
+fn new_world(broadphase: BroadphaseKind, effect: ImpactEffect, others: Vec<Ball>) -> World {
+    let mut world = World::new(Rect::new(-2000., -2000., 4000., 4000.), 0., 0.);
+    let mut projectile = new_ball(
+        Vec2::new(-40., 0.),
+        Vec2::new(600., 0.),
+        8.,
+        80.,
+        BallType::Projectil,
+    );
+    projectile.effect = Some(effect);
+    world.balls.push(projectile);
+    world.balls.push(new_ball(
+        Vec2::ZERO,
+        Vec2::ZERO,
+        10.,
+        40.,
+        BallType::BadBall,
+    ));
+    world.balls.extend(others);
+    world.set_broadphase(broadphase);
+    return world;
+}
+
+// Steps until an effect goes off, giving the ball that triggered it
+fn find_effect(world: &mut World) -> Option<Ball> {
+    for _ in 0..500 {
+        let triggered = world
+            .step(SIMULATION_DT)
+            .iter()
+            .find_map(|event| match event {
+                WorldEvent::EffectTriggered { ball } => Some(*ball),
+                _ => None,
+            });
+        match triggered {
+            Some(ball) => return Some(ball),
+            None => {}
+        }
+    }
+    return None;
+}
+
+#[test]
+fn split_shots_break_into_pieces_keeping_mass_and_momentum() {
+    for broadphase in BACKENDS {
+        let mut world = new_world(broadphase, ImpactEffect::Split { pieces: 3 }, Vec::new());
+        let triggered = find_effect(&mut world).expect("projectile never split");
+
+        let pieces: Vec<&Ball> = world
+            .balls
+            .iter()
+            .filter(|ball| ball.ball_type == BallType::Projectil)
+            .collect();
+        assert_eq!(pieces.len(), 3, "{:?}", broadphase);
+        assert!(pieces.iter().all(|piece| piece.effect.is_none()));
+
+        let mass: f32 = pieces.iter().map(|piece| piece.mass).sum();
+        assert!((mass - triggered.mass).abs() < 1e-3, "mass {}", mass);
+
+        let momentum = pieces
+            .iter()
+            .fold(Vec2::ZERO, |sum, piece| sum + piece.velocity * piece.mass);
+        let expected = triggered.velocity * triggered.mass;
+        assert!(
+            (momentum - expected).length() < expected.length() * 1e-3,
+            "momentum {} expected {}",
+            momentum,
+            expected
+        );
+    }
+}
+
+#[test]
+fn blasts_push_and_gravity_bombs_pull_the_balls_around() {
+    for broadphase in BACKENDS {
+        for impulse in [4000., -4000.] {
+            let neighbour = new_ball(Vec2::new(0., 60.), Vec2::ZERO, 10., 40., BallType::Ball);
+            let far = new_ball(Vec2::new(0., 400.), Vec2::ZERO, 10., 40., BallType::Ball);
+            let mut world = new_world(
+                broadphase,
+                ImpactEffect::Blast {
+                    radius: 120.,
+                    impulse,
+                },
+                vec![neighbour, far],
+            );
+            let triggered = find_effect(&mut world).expect("projectile never went off");
+            assert!(world
+                .balls
+                .iter()
+                .all(|ball| ball.ball_type != BallType::Projectil));
+
+            let neighbour = world
+                .balls
+                .iter()
+                .find(|ball| ball.ball_type == BallType::Ball && ball.position.y < 200.)
+                .unwrap();
+            let away = (neighbour.position - triggered.position).normalize();
+            let radial_speed = neighbour.velocity.dot(away);
+            assert!(
+                radial_speed * impulse > 0.,
+                "{:?} impulse {} gave radial speed {}",
+                broadphase,
+                impulse,
+                radial_speed
+            );
+
+            let far = world
+                .balls
+                .iter()
+                .find(|ball| ball.ball_type == BallType::Ball && ball.position.y > 200.)
+                .unwrap();
+            assert_eq!(far.velocity, Vec2::ZERO);
+        }
+    }
+}
+
+#[test]
+fn effects_wait_for_a_contact() {
+    let mut world = World::new(Rect::new(-2000., -2000., 4000., 4000.), 0., 0.);
+    let mut projectile = new_ball(
+        Vec2::ZERO,
+        Vec2::new(300., 0.),
+        8.,
+        80.,
+        BallType::Projectil,
+    );
+    projectile.effect = Some(ImpactEffect::Split { pieces: 3 });
+    world.balls.push(projectile);
+
+    assert!(find_effect(&mut world).is_none());
+    assert_eq!(world.balls.len(), 1);
+    assert!(world.balls[0].effect.is_some());
+}
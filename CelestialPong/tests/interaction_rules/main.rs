@@ -0,0 +1,281 @@
+// Checks the interaction rules between ball types and bodies, run with `cargo test`
+#![allow(dead_code)]
+
+// The game is a single binary, so the simulation modules the world needs are pulled in directly
+#[path = "../../src/simulation"]
+mod simulation {
+    pub mod ball;
+    pub mod body_path;
+    pub mod broadphase;
+    pub mod capsule;
+    pub mod contact_solver;
+    pub mod gravity;
+    pub mod hash_grid;
+    pub mod integrator;
+    pub mod interactions;
+    pub mod quad_tree;
+    pub mod random;
+    pub mod sweep_and_prune;
+    pub mod world;
+}
+
+use macroquad::{color::colors, math::Vec2};
+
+use simulation::{
+    ball::{Ball, BallType},
+    body_path::BodyPath,
+    interactions::{InteractionRule, InteractionTable, Outcome},
+    quad_tree::Rect,
+    world::{World, WorldEvent},
+};
+
+const SIMULATION_DT: f32 = 1. / 240.;
+
+fn new_ball(position: Vec2, velocity: Vec2, ball_type: BallType) -> Ball {
+    let mut ball = Ball::new(
+        position,
+        velocity,
+        10.,
+        40.,
+        colors::WHITE,
+        0.,
+        0.,
+        ball_type,
+    );
+    ball.set_velocity(velocity, SIMULATION_DT);
+    return ball;
+}
+
+// A light body at the origin, so that balls barely fall toward it
+fn new_world(rules: Vec<InteractionRule>, balls: Vec<Ball>) -> World {
+    let mut world = World::new(Rect::new(-2000., -2000., 4000., 4000.), 0., 0.);
+    world.add_body(
+        Ball::new(
+            Vec2::ZERO,
+            Vec2::ZERO,
+            50.,
+            1.,
+            colors::WHITE,
+            0.,
+            0.,
+            BallType::Body,
+        ),
+        BodyPath::Static,
+        SIMULATION_DT,
+    );
+    world.interactions = InteractionTable::new(rules);
+    world.balls = balls;
+    return world;
+}
+
+// Steps until nothing happens anymore, collecting the events
+fn run(world: &mut World) -> Vec<WorldEvent> {
+    let mut events = Vec::new();
+    for _ in 0..240 {
+        events.extend_from_slice(world.step(SIMULATION_DT));
+    }
+    return events;
+}
+
+fn count(world: &World, ball_type: BallType) -> usize {
+    return world
+        .balls
+        .iter()
+        .filter(|ball| ball.ball_type == ball_type)
+        .count();
+}
+
+#[test]
+fn bodies_destroy_balls_and_seeds_plant_flowers_by_default() {
+    let mut world = new_world(
+        Vec::new(),
+        vec![
+            new_ball(Vec2::new(-100., 0.), Vec2::new(600., 0.), BallType::Ball),
+            new_ball(
+                Vec2::new(100., 0.),
+                Vec2::new(-600., 0.),
+                BallType::GoodBall,
+            ),
+        ],
+    );
+    let events = run(&mut world);
+
+    assert!(world.balls.is_empty());
+    let impacts = events
+        .iter()
+        .filter(|event| matches!(event, WorldEvent::BodyImpact { body_index: 0, .. }))
+        .count();
+    assert_eq!(impacts, 2);
+    let plants: Vec<&Ball> = events
+        .iter()
+        .filter_map(|event| match event {
+            WorldEvent::Interaction {
+                ball,
+                body_index: Some(0),
+                outcome: Outcome::Plant,
+                ..
+            } => Some(ball),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(plants.len(), 1);
+    assert_eq!(plants[0].ball_type, BallType::GoodBall);
+}
+
+#[test]
+fn spikes_kill_the_flowers_they_touch() {
+    let rules = vec![InteractionRule {
+        ball: BallType::Ball,
+        other: BallType::BadBall,
+        outcomes: vec![Outcome::Destroy],
+    }];
+    let mut world = new_world(
+        rules,
+        vec![
+            new_ball(
+                Vec2::new(-300., 200.),
+                Vec2::new(600., 0.),
+                BallType::BadBall,
+            ),
+            new_ball(Vec2::new(0., 200.), Vec2::ZERO, BallType::Ball),
+            // Seeds have no rule with spikes, they only bounce
+            new_ball(
+                Vec2::new(300., -200.),
+                Vec2::new(-600., 0.),
+                BallType::BadBall,
+            ),
+            new_ball(Vec2::new(0., -200.), Vec2::ZERO, BallType::GoodBall),
+        ],
+    );
+    run(&mut world);
+
+    assert_eq!(count(&world, BallType::Ball), 0);
+    assert_eq!(count(&world, BallType::GoodBall), 1);
+    assert_eq!(count(&world, BallType::BadBall), 2);
+}
+
+#[test]
+fn rules_convert_spawn_and_score() {
+    let rules = vec![
+        InteractionRule {
+            ball: BallType::BadBall,
+            other: BallType::Projectil,
+            outcomes: vec![
+                Outcome::Convert {
+                    into: BallType::Ball,
+                },
+                Outcome::Score { points: 5 },
+            ],
+        },
+        InteractionRule {
+            ball: BallType::Projectil,
+            other: BallType::BadBall,
+            outcomes: vec![
+                Outcome::Destroy,
+                Outcome::Spawn {
+                    ball_type: BallType::GoodBall,
+                    count: 2,
+                },
+            ],
+        },
+    ];
+    let mut world = new_world(
+        rules,
+        vec![
+            new_ball(
+                Vec2::new(-300., 200.),
+                Vec2::new(600., 0.),
+                BallType::Projectil,
+            ),
+            new_ball(Vec2::new(0., 200.), Vec2::ZERO, BallType::BadBall),
+        ],
+    );
+    let events = run(&mut world);
+
+    assert_eq!(count(&world, BallType::Projectil), 0);
+    assert_eq!(count(&world, BallType::BadBall), 0);
+    assert_eq!(count(&world, BallType::Ball), 1);
+    assert_eq!(count(&world, BallType::GoodBall), 2);
+    let points: i32 = events
+        .iter()
+        .filter_map(|event| match event {
+            WorldEvent::Interaction {
+                outcome: Outcome::Score { points },
+                ..
+            } => Some(*points),
+            _ => None,
+        })
+        .sum();
+    assert_eq!(points, 5);
+}
+
+#[test]
+fn balls_with_a_bounce_rule_survive_the_bodies() {
+    let rules = vec![InteractionRule {
+        ball: BallType::Ball,
+        other: BallType::Body,
+        outcomes: vec![Outcome::Bounce],
+    }];
+    let mut world = new_world(
+        rules,
+        vec![new_ball(
+            Vec2::new(-100., 0.),
+            Vec2::new(600., 0.),
+            BallType::Ball,
+        )],
+    );
+    world.step(SIMULATION_DT);
+    let events = run(&mut world);
+
+    assert_eq!(world.balls.len(), 1);
+    assert!(world.balls[0].velocity.x < 0.);
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, WorldEvent::BodyImpact { .. })));
+}
+
+// Seeds scoring when touching a ball, and such a pair resting against each other
+fn get_resting_pair() -> (Vec<InteractionRule>, Vec<Ball>) {
+    let rules = vec![InteractionRule {
+        ball: BallType::GoodBall,
+        other: BallType::Ball,
+        outcomes: vec![Outcome::Score { points: 5 }],
+    }];
+    let balls = vec![
+        new_ball(Vec2::new(300., 0.), Vec2::ZERO, BallType::GoodBall),
+        new_ball(Vec2::new(319.9, 0.), Vec2::ZERO, BallType::Ball),
+    ];
+    return (rules, balls);
+}
+
+fn count_interactions(events: &[WorldEvent]) -> usize {
+    return events
+        .iter()
+        .filter(|event| matches!(event, WorldEvent::Interaction { .. }))
+        .count();
+}
+
+#[test]
+fn resting_balls_score_once() {
+    let (rules, balls) = get_resting_pair();
+    let mut world = new_world(rules, balls);
+    let events = run(&mut world);
+
+    let contacts = events
+        .iter()
+        .filter(|event| matches!(event, WorldEvent::Collision { .. }))
+        .count();
+    assert!(contacts > 200, "balls stopped touching after {}", contacts);
+    assert_eq!(count_interactions(&events), 1);
+}
+
+#[test]
+fn balls_put_back_after_a_clear_score_again() {
+    let (rules, balls) = get_resting_pair();
+    let mut world = new_world(rules, balls.clone());
+    run(&mut world);
+
+    world.clear_balls();
+    world.balls.extend(balls);
+    assert_eq!(count_interactions(&run(&mut world)), 1);
+}
@@ -0,0 +1,194 @@
+// Checks how the level objectives and lose conditions end a garden, run with `cargo test`
+#![allow(dead_code)]
+
+// The game is a single binary, so the modules the objectives need are pulled in directly
+#[path = "../../src/simulation"]
+mod simulation {
+    pub mod ball;
+    pub mod body_path;
+    pub mod broadphase;
+    pub mod capsule;
+    pub mod contact_solver;
+    pub mod gravity;
+    pub mod hash_grid;
+    pub mod integrator;
+    pub mod interactions;
+    pub mod quad_tree;
+    pub mod random;
+    pub mod sweep_and_prune;
+    pub mod world;
+}
+
+#[path = "../../src/levels"]
+mod levels {
+    pub mod flowers;
+    pub mod level_definition;
+    pub mod objectives;
+    pub mod weapon;
+}
+
+use macroquad::{color::colors, math::Vec2};
+
+use levels::{
+    level_definition::{LevelDefinition, RulesDefinition},
+    objectives::{
+        get_outcome, DefeatReason, GardenProgress, LevelOutcome, LoseCondition, Objective,
+    },
+};
+use simulation::ball::{Ball, BallType};
+
+const SIMULATION_DT: f32 = 1. / 240.;
+
+fn new_balls(ball_type: BallType, count: usize) -> Vec<Ball> {
+    return (0..count)
+        .map(|index| {
+            Ball::new(
+                Vec2::new(index as f32 * 20., 0.),
+                Vec2::ZERO,
+                7.,
+                40.,
+                colors::WHITE,
+                0.,
+                0.,
+                ball_type,
+            )
+        })
+        .collect();
+}
+
+fn new_rules(objectives: Vec<Objective>, lose_conditions: Vec<LoseCondition>) -> RulesDefinition {
+    return RulesDefinition {
+        objectives,
+        lose_conditions,
+        score_per_ball: 1,
+    };
+}
+
+fn new_progress(elapsed: f32, balls: &[Ball]) -> GardenProgress<'_> {
+    return GardenProgress {
+        elapsed,
+        balls,
+        initial_flowers: 10,
+        planted_flowers: 0,
+        out_of_lives: false,
+        waves_left: false,
+    };
+}
+
+const CLEAR_SPIKES: Objective = Objective::Clear {
+    ball_type: BallType::BadBall,
+    time_limit: None,
+};
+
+#[test]
+fn clearing_the_spikes_wins_unless_out_of_lives() {
+    let rules = new_rules(vec![CLEAR_SPIKES], Vec::new());
+    let mut balls = new_balls(BallType::Ball, 10);
+    balls.extend(new_balls(BallType::BadBall, 2));
+    assert_eq!(get_outcome(&rules, &new_progress(10., &balls)), None);
+
+    balls.retain(|ball| ball.ball_type != BallType::BadBall);
+    let progress = new_progress(10., &balls);
+    assert_eq!(get_outcome(&rules, &progress), Some(LevelOutcome::Victory));
+
+    let progress = GardenProgress {
+        out_of_lives: true,
+        ..progress
+    };
+    assert_eq!(
+        get_outcome(&rules, &progress),
+        Some(LevelOutcome::Defeat(DefeatReason::OutOfLives))
+    );
+}
+
+#[test]
+fn spikes_left_after_the_time_limit_lose_the_level() {
+    let rules = new_rules(
+        vec![Objective::Clear {
+            ball_type: BallType::BadBall,
+            time_limit: Some(60.),
+        }],
+        Vec::new(),
+    );
+    let balls = new_balls(BallType::BadBall, 1);
+    assert_eq!(get_outcome(&rules, &new_progress(59., &balls)), None);
+    assert_eq!(
+        get_outcome(&rules, &new_progress(61., &balls)),
+        Some(LevelOutcome::Defeat(DefeatReason::TimeUp))
+    );
+
+    // Clearing them in time still wins
+    assert_eq!(
+        get_outcome(&rules, &new_progress(59., &[])),
+        Some(LevelOutcome::Victory)
+    );
+}
+
+#[test]
+fn every_objective_is_needed_to_win() {
+    let rules = new_rules(
+        vec![
+            Objective::Survive { seconds: 30. },
+            Objective::Plant { flowers: 5 },
+            Objective::KeepAlive { fraction: 0.5 },
+        ],
+        vec![LoseCondition::Cleared {
+            ball_type: BallType::GoodBall,
+        }],
+    );
+    let mut balls = new_balls(BallType::Ball, 6);
+    balls.extend(new_balls(BallType::GoodBall, 1));
+
+    let planted = GardenProgress {
+        planted_flowers: 5,
+        ..new_progress(20., &balls)
+    };
+    assert_eq!(get_outcome(&rules, &planted), None);
+    let survived = GardenProgress {
+        elapsed: 30.,
+        ..planted
+    };
+    assert_eq!(get_outcome(&rules, &survived), Some(LevelOutcome::Victory));
+
+    // Fewer than half of the starting flowers left
+    let wilted = GardenProgress {
+        balls: &balls[2..],
+        ..survived
+    };
+    assert_eq!(
+        get_outcome(&rules, &wilted),
+        Some(LevelOutcome::Defeat(DefeatReason::FlowersLost))
+    );
+
+    // No seed left
+    let no_seeds = GardenProgress {
+        balls: &balls[..6],
+        ..survived
+    };
+    assert_eq!(
+        get_outcome(&rules, &no_seeds),
+        Some(LevelOutcome::Defeat(DefeatReason::Cleared(
+            BallType::GoodBall
+        )))
+    );
+}
+
+#[test]
+fn levels_need_an_objective_ending_them() {
+    let base = LevelDefinition::load("levels/garden.toml").unwrap();
+    assert_eq!(base.rules.objectives, vec![CLEAR_SPIKES]);
+
+    let text = std::fs::read_to_string("levels/garden.toml")
+        .unwrap()
+        .replace(
+            r#"objectives = [{ Clear = { ball_type = "BadBall" } }]"#,
+            r#"objectives = [{ KeepAlive = { fraction = 0.5 } }]"#,
+        );
+    assert!(LevelDefinition::parse(&text, "keep alive only").is_err());
+
+    let text = text.replace(
+        "[{ KeepAlive = { fraction = 0.5 } }]",
+        r#"[{ Survive = { seconds = 60.0 } }, { KeepAlive = { fraction = 1.5 } }]"#,
+    );
+    assert!(LevelDefinition::parse(&text, "fraction over one").is_err());
+}
@@ -0,0 +1,198 @@
+// Checks when the waves come, how they grow and where their balls appear, run with `cargo test`
+#![allow(dead_code)]
+
+// The game is a single binary, so the modules the waves need are pulled in directly
+#[path = "../../src/simulation"]
+mod simulation {
+    pub mod ball;
+    pub mod body_path;
+    pub mod broadphase;
+    pub mod capsule;
+    pub mod contact_solver;
+    pub mod gravity;
+    pub mod hash_grid;
+    pub mod integrator;
+    pub mod interactions;
+    pub mod quad_tree;
+    pub mod random;
+    pub mod sweep_and_prune;
+    pub mod world;
+}
+
+#[path = "../../src/levels"]
+mod levels {
+    pub mod flowers;
+    pub mod level_definition;
+    pub mod objectives;
+    pub mod waves;
+    pub mod weapon;
+}
+
+use macroquad::{color::colors, math::Vec2};
+
+use levels::{
+    level_definition::{
+        DifficultyCurve, OrbitBand, PopulationDefinition, SpawnKind, WaveDefinition,
+    },
+    waves::{spawn_population, WaveSpawner},
+};
+use simulation::{
+    ball::{Ball, BallMaterial, BallType},
+    body_path::BodyPath,
+    quad_tree::Rect,
+    random::Random,
+    world::World,
+};
+
+const SIMULATION_DT: f32 = 1. / 240.;
+
+fn new_body() -> Ball {
+    return Ball::new(
+        Vec2::ZERO,
+        Vec2::ZERO,
+        90.,
+        10000000.,
+        colors::WHITE,
+        0.,
+        0.,
+        BallType::Body,
+    );
+}
+
+fn new_population(count: usize, spawn: SpawnKind) -> PopulationDefinition {
+    return PopulationDefinition {
+        ball_type: BallType::BadBall,
+        count,
+        radius: 9.,
+        mass: 40.,
+        orbit: OrbitBand {
+            body: Some(0),
+            min: 210.,
+            max: 260.,
+        },
+        color: None,
+        texture: None,
+        material: BallMaterial::default(),
+        spawn,
+    };
+}
+
+fn new_waves(count: Option<usize>) -> WaveDefinition {
+    return WaveDefinition {
+        delay: 10.,
+        interval: 5.,
+        count,
+        curve: DifficultyCurve::Linear { step: 1. },
+        score_per_wave: 3,
+        populations: vec![new_population(2, SpawnKind::Orbit)],
+    };
+}
+
+fn screen() -> Rect {
+    return Rect::new(0., 0., 1000., 1000.);
+}
+
+#[test]
+fn difficulty_curves_scale_the_waves() {
+    assert_eq!(DifficultyCurve::Constant.get_multiplier(7), 1.);
+    assert_eq!(DifficultyCurve::Linear { step: 0.5 }.get_multiplier(4), 3.);
+    assert_eq!(
+        DifficultyCurve::Exponential { rate: 2. }.get_multiplier(3),
+        8.
+    );
+
+    let steps = DifficultyCurve::Steps {
+        multipliers: vec![1., 1.5, 3.],
+    };
+    let multipliers: Vec<f32> = (0..5).map(|wave| steps.get_multiplier(wave)).collect();
+    assert_eq!(multipliers, vec![1., 1.5, 3., 3., 3.]);
+}
+
+#[test]
+fn waves_come_on_time_and_grow() {
+    let bodies = [new_body()];
+    let mut random = Random::new(7);
+    let mut balls = Vec::new();
+    let mut spawner = WaveSpawner::new(new_waves(Some(3)), false);
+    let mut update = |spawner: &mut WaveSpawner, elapsed: f32, balls: &mut Vec<Ball>| {
+        return spawner.update(elapsed, &bodies, &screen(), &mut random, balls);
+    };
+
+    assert!(!update(&mut spawner, 9.9, &mut balls));
+    assert!(balls.is_empty());
+    assert_eq!(spawner.get_next_wave_time(), Some(10.));
+
+    assert!(update(&mut spawner, 10., &mut balls));
+    assert_eq!(balls.len(), 2);
+    assert!(update(&mut spawner, 15., &mut balls));
+    assert_eq!(balls.len(), 6);
+    assert!(!spawner.is_over());
+
+    // Late updates catch up on every wave due
+    spawner.reset();
+    balls.clear();
+    assert!(update(&mut spawner, 60., &mut balls));
+    assert_eq!(balls.len(), 12);
+    assert_eq!(spawner.wave, 3);
+    assert!(spawner.is_over());
+    assert_eq!(spawner.get_next_wave_time(), None);
+    assert_eq!(spawner.get_score(), 9);
+
+    assert!(!update(&mut spawner, 100., &mut balls));
+    assert_eq!(balls.len(), 12);
+}
+
+#[test]
+fn endless_waves_never_stop() {
+    let bodies = [new_body()];
+    let mut random = Random::new(7);
+    let mut balls = Vec::new();
+    let mut spawner = WaveSpawner::new(new_waves(Some(3)), true);
+    assert_eq!(spawner.get_count(), None);
+
+    spawner.update(100., &bodies, &screen(), &mut random, &mut balls);
+    assert_eq!(spawner.wave, 19);
+    assert!(!spawner.is_over());
+    assert_eq!(spawner.get_next_wave_time(), Some(105.));
+
+    // Levels without a wave count are endless too
+    let spawner = WaveSpawner::new(new_waves(None), false);
+    assert!(spawner.is_endless());
+}
+
+#[test]
+fn off_screen_balls_fall_in_to_their_band() {
+    let mut world = World::new(Rect::new(0., 0., 4000., 4000.), 0., 0.);
+    world.add_body(new_body(), BodyPath::Static, SIMULATION_DT);
+    world.bad_ball_area = Some(screen());
+
+    let mut random = Random::new(3);
+    spawn_population(
+        &new_population(4, SpawnKind::OffScreen),
+        4,
+        &world.static_bodies,
+        &screen(),
+        &mut random,
+        &mut world.balls,
+    );
+    for ball in &world.balls {
+        assert!(screen().contains(ball.position));
+        assert!(ball.position.length() > 400.);
+    }
+
+    let mut closest = [f32::MAX; 4];
+    for _ in 0..240 * 20 {
+        world.step(SIMULATION_DT);
+        assert_eq!(world.balls.len(), 4);
+        for (index, ball) in world.balls.iter().enumerate() {
+            closest[index] = closest[index].min(ball.position.length());
+        }
+    }
+    for distance in closest {
+        assert!(
+            distance > 200. && distance < 270.,
+            "came down to {}",
+            distance
+        );
+    }
+}
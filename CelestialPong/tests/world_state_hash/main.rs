@@ -0,0 +1,213 @@
+// Checks that a seeded garden plays out bit for bit the same, run with `cargo test`
+#![allow(dead_code)]
+
+// The game is a single binary, so the simulation modules are pulled in directly
+#[path = "../../src/simulation"]
+mod simulation {
+    pub mod ball;
+    pub mod body_path;
+    pub mod broadphase;
+    pub mod capsule;
+    pub mod contact_solver;
+    pub mod gravity;
+    pub mod hash_grid;
+    pub mod integrator;
+    pub mod interactions;
+    pub mod quad_tree;
+    pub mod random;
+    pub mod sweep_and_prune;
+    pub mod world;
+}
+
+use macroquad::{color::colors, math::Vec2};
+
+use simulation::{
+    ball::{Ball, BallType},
+    gravity::get_orbital_velocity,
+    quad_tree::Rect,
+    random::{random_orbital_pos, Random},
+    world::World,
+};
+
+const SIMULATION_DT: f32 = 1. / 240.;
+
+const SEED: u64 = 42;
+const STEPS: u64 = 10000;
+// State of the garden after the steps. Any change moving a ball changes it, only update it when
+// the simulation is meant to play differently.
+const EXPECTED_HASH: u64 = 0x10475675af4d8e7d;
+
+// What the player does, at the step it is done
+#[derive(Clone, Copy)]
+enum Input {
+    // Fires a projectile from a position toward another one
+    Shoot { from: Vec2, toward: Vec2 },
+    // Holds the ball with that index and drags it to a position
+    Grab { ball: usize, to: Vec2 },
+    Release,
+}
+
+const INPUTS: [(u64, Input); 9] = [
+    (
+        240,
+        Input::Shoot {
+            from: Vec2::new(300., 300.),
+            toward: Vec2::new(0., 250.),
+        },
+    ),
+    (
+        900,
+        Input::Shoot {
+            from: Vec2::new(-380., 120.),
+            toward: Vec2::new(-200., -100.),
+        },
+    ),
+    (
+        1500,
+        Input::Grab {
+            ball: 40,
+            to: Vec2::new(150., -150.),
+        },
+    ),
+    (2100, Input::Release),
+    (
+        3000,
+        Input::Shoot {
+            from: Vec2::new(0., -400.),
+            toward: Vec2::new(30., -260.),
+        },
+    ),
+    (
+        4200,
+        Input::Grab {
+            ball: 120,
+            to: Vec2::new(-280., 0.),
+        },
+    ),
+    (4500, Input::Release),
+    (
+        6000,
+        Input::Shoot {
+            from: Vec2::new(390., -60.),
+            toward: Vec2::new(220., 80.),
+        },
+    ),
+    (
+        8000,
+        Input::Shoot {
+            from: Vec2::new(-250., -320.),
+            toward: Vec2::new(-100., -220.),
+        },
+    ),
+];
+
+// The first garden : spikes, seeds and balls orbiting a planet at the origin
+fn new_world(seed: u64) -> World {
+    let mut world = World::new(Rect::new(0., 0., 3200., 3200.), 10., 9.);
+    world.kill_distance_squared = f32::powf(800. * f32::sqrt(2.) / 2., 2.);
+    world.bad_ball_area = Some(Rect::new(0., 0., 800., 800.));
+    world.static_bodies.push(Ball::new(
+        Vec2::ZERO,
+        Vec2::ZERO,
+        90.,
+        10000000.,
+        colors::WHITE,
+        0.,
+        0.,
+        BallType::Body,
+    ));
+
+    let mut random = Random::new(seed);
+    for index in 0..300 {
+        let (ball_type, radius) = match index {
+            0..=19 => (BallType::BadBall, 9.1),
+            20..=34 => (BallType::GoodBall, 5.6),
+            _ => (BallType::Ball, 7.),
+        };
+        let position = random_orbital_pos(&mut random, Vec2::ZERO, 210., 351.);
+        let mut ball = Ball::new(
+            position,
+            Vec2::ZERO,
+            radius,
+            40.,
+            colors::WHITE,
+            random.gen_range(-std::f32::consts::PI, std::f32::consts::PI),
+            random.gen_range(-std::f32::consts::PI * 2., std::f32::consts::PI * 2.),
+            ball_type,
+        );
+        let velocity = get_orbital_velocity(&ball, &world.static_bodies[0]);
+        ball.set_velocity(velocity, SIMULATION_DT);
+        world.balls.push(ball);
+    }
+    return world;
+}
+
+fn apply_input(world: &mut World, input: Input) {
+    match input {
+        Input::Shoot { from, toward } => {
+            world.balls.push(Ball::new(
+                from,
+                (toward - from).normalize() * 7.,
+                8.4,
+                80.,
+                colors::BLUE,
+                0.,
+                0.,
+                BallType::Projectil,
+            ));
+        }
+        // The ball may have been removed since the start
+        Input::Grab { ball, .. } => {
+            if ball < world.balls.len() {
+                world.grabbed_ball = Some(ball);
+            }
+        }
+        Input::Release => {
+            world.grabbed_ball = None;
+        }
+    }
+}
+
+// Steps the garden, replaying the inputs
+fn run(seed: u64) -> World {
+    let mut world = new_world(seed);
+    let mut drag_to = Vec2::ZERO;
+    for step in 0..STEPS {
+        for (input_step, input) in INPUTS {
+            if input_step == step {
+                match input {
+                    Input::Grab { to, .. } => drag_to = to,
+                    _ => {}
+                }
+                apply_input(&mut world, input);
+            }
+        }
+
+        match world.grabbed_ball {
+            Some(index) => {
+                let ball = &mut world.balls[index];
+                let velocity = (drag_to - ball.position) * 2.;
+                ball.set_velocity(velocity, SIMULATION_DT);
+            }
+            None => {}
+        }
+        world.step(SIMULATION_DT);
+    }
+    return world;
+}
+
+#[test]
+fn seeded_garden_gives_the_expected_state() {
+    let world = run(SEED);
+    assert_eq!(world.tick, STEPS);
+    // The balls must still be around for the hash to mean anything
+    assert!(world.balls.len() > 50, "{} balls left", world.balls.len());
+
+    let hash = world.state_hash();
+    assert_eq!(hash, EXPECTED_HASH, "state hash {:#018x}", hash);
+}
+
+#[test]
+fn other_seed_gives_another_state() {
+    assert_ne!(run(SEED + 1).state_hash(), run(SEED).state_hash());
+}